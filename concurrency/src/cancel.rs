@@ -0,0 +1,260 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError, Weak};
+use std::time::{Duration, Instant};
+
+// Why a job stopped early
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupted {
+    Cancelled,
+    TimedOut
+}
+
+struct Node {
+    cancelled : AtomicBool,
+    // Earliest deadline of this token and its ancestors
+    deadline : Option<Instant>,
+    children : Mutex<Vec<Weak<Node>>>,
+    // Keeps the path from the root alive while a descendant is still used
+    _parent : Option<Arc<Node>>,
+    // Wakes threads in sleep when the token is cancelled
+    sleepers : Mutex<()>,
+    wake : Condvar
+}
+
+/* A token that jobs poll to find out if they should stop.  Cancelling a
+ * token cancels every child made from it (and their children), but not its
+ * parent.  A token with a deadline counts as timed out once the deadline
+ * passes, and children never have a later deadline than their parent.
+ * Clones share the same token.
+ */
+#[derive(Clone)]
+pub struct CancellationToken {
+    node : Arc<Node>
+}
+
+fn lock<T>(mutex : &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        CancellationToken::with_parent(None, None)
+    }
+
+    fn with_parent(parent : Option<&CancellationToken>, deadline : Option<Instant>) -> Self {
+        let deadline = match (parent.and_then(|parent| parent.deadline()), deadline) {
+            (Some(inherited), Some(deadline)) => Some(inherited.min(deadline)),
+            (inherited, deadline) => inherited.or(deadline)
+        };
+        let token = CancellationToken { node : Arc::new(Node {
+            cancelled : AtomicBool::new(false),
+            deadline,
+            children : Mutex::new(Vec::new()),
+            _parent : parent.map(|parent| Arc::clone(&parent.node)),
+            sleepers : Mutex::new(()),
+            wake : Condvar::new()
+        })};
+        if let Some(parent) = parent {
+            {
+                let mut children = lock(&parent.node.children);
+                // Drop tokens that are gone before the list doubles
+                if children.len() >= 16 && children.len().is_power_of_two() {
+                    children.retain(|child| child.strong_count() > 0);
+                }
+                children.push(Arc::downgrade(&token.node));
+            }
+            // Checked after the child is in the list so a concurrent cancel
+            // either sees the child or the child sees the cancel
+            if parent.node.cancelled.load(Ordering::SeqCst) {
+                token.cancel();
+            }
+        }
+        token
+    }
+
+    pub fn child(&self) -> Self {
+        CancellationToken::with_parent(Some(self), None)
+    }
+
+    pub fn child_with_deadline(&self, deadline : Instant) -> Self {
+        CancellationToken::with_parent(Some(self), Some(deadline))
+    }
+
+    pub fn child_with_timeout(&self, timeout : Duration) -> Self {
+        self.child_with_deadline(Instant::now() + timeout)
+    }
+
+    // Cancel this token and every token made from it
+    pub fn cancel(&self) {
+        let mut pending = vec![Arc::clone(&self.node)];
+        while let Some(node) = pending.pop() {
+            if node.cancelled.swap(true, Ordering::SeqCst) {
+                continue;
+            }
+            {
+                let _sleepers = lock(&node.sleepers);
+                node.wake.notify_all();
+            }
+            pending.extend(lock(&node.children).iter().filter_map(Weak::upgrade));
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.node.cancelled.load(Ordering::SeqCst)
+    }
+
+    pub fn is_timed_out(&self) -> bool {
+        self.node.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.node.deadline
+    }
+
+    // Time left before the deadline (None without a deadline)
+    pub fn remaining(&self) -> Option<Duration> {
+        self.node.deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /* Ok if the job should keep going.  Jobs call this between steps:
+     *
+     *     for step in steps {
+     *         token.check()?;
+     *         ...
+     *     }
+     */
+    pub fn check(&self) -> Result<(), Interrupted> {
+        if self.is_cancelled() {
+            Err(Interrupted::Cancelled)
+        }
+        else if self.is_timed_out() {
+            Err(Interrupted::TimedOut)
+        }
+        else {
+            Ok(())
+        }
+    }
+
+    // Sleep for the duration unless the token is cancelled or times out first
+    pub fn sleep(&self, duration : Duration) -> Result<(), Interrupted> {
+        let wake_up = Instant::now() + duration;
+        let until = self.node.deadline.map_or(wake_up, |deadline| deadline.min(wake_up));
+        let mut sleepers = lock(&self.node.sleepers);
+        loop {
+            self.check()?;
+            let now = Instant::now();
+            if now >= until {
+                return if now >= wake_up { Ok(()) } else { self.check() };
+            }
+            sleepers = self.node.wake.wait_timeout(sleepers, until - now)
+                .unwrap_or_else(PoisonError::into_inner).0;
+        }
+    }
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        CancellationToken::new()
+    }
+}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .field("deadline", &self.node.deadline)
+            .finish()
+    }
+}
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Interrupted::Cancelled => write!(f, "Cancelled"),
+            Interrupted::TimedOut => write!(f, "Timed out")
+        }
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_hierarchy() {
+        let root = CancellationToken::new();
+        let child = root.child();
+        let grandchild = child.child();
+        let sibling = root.child();
+
+        grandchild.cancel();
+        assert!(grandchild.is_cancelled());
+        assert!(!child.is_cancelled() && !root.is_cancelled());
+
+        child.cancel();
+        assert!(!root.is_cancelled() && !sibling.is_cancelled());
+
+        root.cancel();
+        assert!(sibling.is_cancelled());
+        assert_eq!(sibling.check(), Err(Interrupted::Cancelled));
+        // Children of a cancelled token start cancelled
+        assert!(root.child().is_cancelled());
+    }
+
+    #[test]
+    fn test_deadline() {
+        let root = CancellationToken::new();
+        assert_eq!(root.deadline(), None);
+        let parent = root.child_with_timeout(Duration::from_millis(30));
+        let child = parent.child_with_timeout(Duration::from_secs(60));
+        // A child can't outlive the deadline of its parent
+        assert_eq!(child.deadline(), parent.deadline());
+        assert!(child.remaining().unwrap() <= Duration::from_millis(30));
+        assert_eq!(child.check(), Ok(()));
+
+        thread::sleep(Duration::from_millis(40));
+        assert_eq!(child.check(), Err(Interrupted::TimedOut));
+        assert!(!child.is_cancelled());
+        assert_eq!(root.check(), Ok(()));
+    }
+
+    #[test]
+    fn test_sleep() {
+        let token = CancellationToken::new();
+        assert_eq!(token.sleep(Duration::from_millis(1)), Ok(()));
+
+        let child = token.child();
+        let sleeper = thread::spawn(move || {
+            let start = Instant::now();
+            (child.sleep(Duration::from_secs(60)), start.elapsed())
+        });
+        thread::sleep(Duration::from_millis(20));
+        token.cancel();
+        let (result, elapsed) = sleeper.join().unwrap();
+        assert_eq!(result, Err(Interrupted::Cancelled));
+        assert!(elapsed < Duration::from_secs(10));
+
+        let token = CancellationToken::new().child_with_timeout(Duration::from_millis(10));
+        assert_eq!(token.sleep(Duration::from_secs(60)), Err(Interrupted::TimedOut));
+    }
+
+    #[test]
+    fn test_concurrent_children() {
+        // Children made while the parent is being cancelled still end up cancelled
+        for _ in 0..50 {
+            let root = CancellationToken::new();
+            let makers = (0..4).map(|_| {
+                let root = root.clone();
+                thread::spawn(move || (0..200).map(|_| root.child().child()).collect::<Vec<_>>())
+            }).collect::<Vec<_>>();
+            root.cancel();
+            for maker in makers {
+                assert!(maker.join().unwrap().iter().all(CancellationToken::is_cancelled));
+            }
+        }
+    }
+}
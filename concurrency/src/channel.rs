@@ -0,0 +1,832 @@
+use std::any::Any;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+use rand::Rng;
+
+/* Bounded multi-producer multi-consumer channel.  Senders and Receivers can
+ * both be cloned.  When every Sender is dropped, receivers get the messages
+ * that are left and then Disconnected.  When every Receiver is dropped, send
+ * returns the message with Disconnected.
+ */
+pub fn bounded<T>(capacity : usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "Channel capacity must be at least 1");
+    let channel = Arc::new(Channel {
+        state : Mutex::new(State {
+            queue : VecDeque::with_capacity(capacity),
+            capacity,
+            senders : 1,
+            receivers : 1,
+            selectors : Vec::new()
+        }),
+        not_empty : Condvar::new(),
+        not_full : Condvar::new()
+    });
+    (Sender { channel : Arc::clone(&channel) }, Receiver { channel })
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub struct SendError<T>(pub T);
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum TrySendError<T> {
+    Full(T),
+    Disconnected(T)
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum SendTimeoutError<T> {
+    Timeout(T),
+    Disconnected(T)
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RecvError;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TryRecvError {
+    Empty,
+    Disconnected
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RecvTimeoutError {
+    Timeout,
+    Disconnected
+}
+
+// Wakes a Select waiting on several channels
+struct Signal {
+    fired : Mutex<bool>,
+    changed : Condvar
+}
+
+struct State<T> {
+    queue : VecDeque<T>,
+    capacity : usize,
+    senders : usize,
+    receivers : usize,
+    selectors : Vec<Arc<Signal>>
+}
+
+struct Channel<T> {
+    state : Mutex<State<T>>,
+    not_empty : Condvar,
+    not_full : Condvar
+}
+
+pub struct Sender<T> {
+    channel : Arc<Channel<T>>
+}
+
+pub struct Receiver<T> {
+    channel : Arc<Channel<T>>
+}
+
+fn lock<T>(mutex : &Mutex<T>) -> MutexGuard<'_, T> {
+    // No user code runs while a lock is held so a poisoned lock still has
+    // consistent data.
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Wait on the condvar until the deadline.  Returns the guard and whether the
+// deadline has passed.
+fn wait<'a, T>(changed : &Condvar, guard : MutexGuard<'a, T>, deadline : Option<Instant>) -> (MutexGuard<'a, T>, bool) {
+    match deadline {
+        None => (changed.wait(guard).unwrap_or_else(PoisonError::into_inner), false),
+        Some(deadline) => {
+            let now = Instant::now();
+            if now >= deadline {
+                return (guard, true);
+            }
+            let (guard, _) = changed.wait_timeout(guard, deadline - now).unwrap_or_else(PoisonError::into_inner);
+            (guard, false)
+        }
+    }
+}
+
+impl Signal {
+    fn new() -> Self {
+        Signal { fired : Mutex::new(false), changed : Condvar::new() }
+    }
+
+    fn notify(&self) {
+        *lock(&self.fired) = true;
+        self.changed.notify_all();
+    }
+
+    fn reset(&self) {
+        *lock(&self.fired) = false;
+    }
+
+    // Returns false if the deadline passed first
+    fn wait(&self, deadline : Option<Instant>) -> bool {
+        let mut fired = lock(&self.fired);
+        while !*fired {
+            let (guard, timed_out) = wait(&self.changed, fired, deadline);
+            fired = guard;
+            if timed_out {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl<T> State<T> {
+    // A message was added or the senders are gone
+    fn notify_selectors(&self) {
+        for signal in self.selectors.iter() {
+            signal.notify();
+        }
+    }
+}
+
+impl<T> Channel<T> {
+    fn send(&self, message : T, deadline : Option<Instant>) -> Result<(), SendTimeoutError<T>> {
+        let mut state = lock(&self.state);
+        loop {
+            if state.receivers == 0 {
+                return Err(SendTimeoutError::Disconnected(message));
+            }
+            if state.queue.len() < state.capacity {
+                state.queue.push_back(message);
+                state.notify_selectors();
+                self.not_empty.notify_one();
+                return Ok(());
+            }
+            let (guard, timed_out) = wait(&self.not_full, state, deadline);
+            state = guard;
+            if timed_out {
+                return Err(SendTimeoutError::Timeout(message));
+            }
+        }
+    }
+
+    fn recv(&self, deadline : Option<Instant>) -> Result<T, RecvTimeoutError> {
+        let mut state = lock(&self.state);
+        loop {
+            if let Some(message) = state.queue.pop_front() {
+                self.not_full.notify_one();
+                return Ok(message);
+            }
+            if state.senders == 0 {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let (guard, timed_out) = wait(&self.not_empty, state, deadline);
+            state = guard;
+            if timed_out {
+                return Err(RecvTimeoutError::Timeout);
+            }
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    // Wait for space in the channel
+    pub fn send(&self, message : T) -> Result<(), SendError<T>> {
+        self.channel.send(message, None).map_err(|error| match error {
+            SendTimeoutError::Timeout(message) | SendTimeoutError::Disconnected(message) => SendError(message)
+        })
+    }
+
+    pub fn try_send(&self, message : T) -> Result<(), TrySendError<T>> {
+        let mut state = lock(&self.channel.state);
+        if state.receivers == 0 {
+            return Err(TrySendError::Disconnected(message));
+        }
+        if state.queue.len() >= state.capacity {
+            return Err(TrySendError::Full(message));
+        }
+        state.queue.push_back(message);
+        state.notify_selectors();
+        self.channel.not_empty.notify_one();
+        Ok(())
+    }
+
+    pub fn send_timeout(&self, message : T, timeout : Duration) -> Result<(), SendTimeoutError<T>> {
+        self.channel.send(message, Some(Instant::now() + timeout))
+    }
+
+    pub fn len(&self) -> usize {
+        lock(&self.channel.state).queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        let state = lock(&self.channel.state);
+        state.queue.len() >= state.capacity
+    }
+
+    pub fn capacity(&self) -> usize {
+        lock(&self.channel.state).capacity
+    }
+
+    // True if every Receiver was dropped
+    pub fn is_disconnected(&self) -> bool {
+        lock(&self.channel.state).receivers == 0
+    }
+}
+
+impl<T> Receiver<T> {
+    // Wait for a message.  Fails once the channel is empty and every Sender
+    // was dropped.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.channel.recv(None).map_err(|_| RecvError)
+    }
+
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut state = lock(&self.channel.state);
+        match state.queue.pop_front() {
+            Some(message) => {
+                self.channel.not_full.notify_one();
+                Ok(message)
+            }
+            None if state.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty)
+        }
+    }
+
+    pub fn recv_timeout(&self, timeout : Duration) -> Result<T, RecvTimeoutError> {
+        self.channel.recv(Some(Instant::now() + timeout))
+    }
+
+    // Blocking iterator that ends when the channel is disconnected
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(|| self.recv().ok())
+    }
+
+    // Messages that are ready now
+    pub fn try_iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(|| self.try_recv().ok())
+    }
+
+    pub fn len(&self) -> usize {
+        lock(&self.channel.state).queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        lock(&self.channel.state).capacity
+    }
+
+    // True if every Sender was dropped (there may still be messages)
+    pub fn is_disconnected(&self) -> bool {
+        lock(&self.channel.state).senders == 0
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        lock(&self.channel.state).senders += 1;
+        Sender { channel : Arc::clone(&self.channel) }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        lock(&self.channel.state).receivers += 1;
+        Receiver { channel : Arc::clone(&self.channel) }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = lock(&self.channel.state);
+        state.senders -= 1;
+        if state.senders == 0 {
+            state.notify_selectors();
+            self.channel.not_empty.notify_all();
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = lock(&self.channel.state);
+        state.receivers -= 1;
+        if state.receivers == 0 {
+            self.channel.not_full.notify_all();
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Receiver<T> {
+    type Item = T;
+    type IntoIter = Box<dyn Iterator<Item = T> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.iter())
+    }
+}
+
+// Receivers of any message type in a Select
+trait Selectable {
+    fn try_recv_any(&self) -> Result<Box<dyn Any + Send>, TryRecvError>;
+    fn register(&self, signal : &Arc<Signal>);
+    fn unregister(&self, signal : &Arc<Signal>);
+}
+
+impl<T : Send + 'static> Selectable for Receiver<T> {
+    fn try_recv_any(&self) -> Result<Box<dyn Any + Send>, TryRecvError> {
+        self.try_recv().map(|message| Box::new(message) as Box<dyn Any + Send>)
+    }
+
+    fn register(&self, signal : &Arc<Signal>) {
+        lock(&self.channel.state).selectors.push(Arc::clone(signal));
+    }
+
+    fn unregister(&self, signal : &Arc<Signal>) {
+        lock(&self.channel.state).selectors.retain(|other| !Arc::ptr_eq(other, signal));
+    }
+}
+
+/* Wait for a message from any of several receivers (which can carry
+ * different message types).  Each call to recv returns the index that
+ * Selected::index reports for that receiver.  A receiver that is
+ * disconnected is also ready, with Err(RecvError).  When more than one
+ * receiver is ready, one is picked at random so none is starved.
+ *
+ *     let mut select = Select::new();
+ *     let numbers = select.recv(&number_rx);
+ *     let words = select.recv(&word_rx);
+ *     let selected = select.select();
+ *     if selected.index() == numbers {
+ *         let number : Result<i32, RecvError> = selected.recv(&number_rx);
+ *     }
+ *
+ * The select! macro does the same with less code.
+ */
+#[derive(Default)]
+pub struct Select<'a> {
+    receivers : Vec<&'a dyn Selectable>
+}
+
+pub struct Selected {
+    index : usize,
+    message : Result<Box<dyn Any + Send>, RecvError>
+}
+
+impl<'a> Select<'a> {
+    pub fn new() -> Self {
+        Select { receivers : Vec::new() }
+    }
+
+    pub fn recv<T : Send + 'static>(&mut self, receiver : &'a Receiver<T>) -> usize {
+        self.receivers.push(receiver);
+        self.receivers.len() - 1
+    }
+
+    pub fn try_select(&self) -> Option<Selected> {
+        let count = self.receivers.len();
+        if count == 0 {
+            return None;
+        }
+        let start = rand::thread_rng().gen_range(0..count);
+        (0..count).map(|offset| (start + offset) % count).find_map(|index| {
+            match self.receivers[index].try_recv_any() {
+                Ok(message) => Some(Selected { index, message : Ok(message) }),
+                Err(TryRecvError::Disconnected) => Some(Selected { index, message : Err(RecvError) }),
+                Err(TryRecvError::Empty) => None
+            }
+        })
+    }
+
+    // Wait until one of the receivers is ready
+    pub fn select(&self) -> Selected {
+        assert!(!self.receivers.is_empty(), "Select has no receivers");
+        self.wait(None).expect("Select without a deadline timed out")
+    }
+
+    // None if no receiver was ready before the timeout
+    pub fn select_timeout(&self, timeout : Duration) -> Option<Selected> {
+        self.wait(Some(Instant::now() + timeout))
+    }
+
+    fn wait(&self, deadline : Option<Instant>) -> Option<Selected> {
+        if let Some(selected) = self.try_select() {
+            return Some(selected);
+        }
+        let signal = Arc::new(Signal::new());
+        for receiver in self.receivers.iter() {
+            receiver.register(&signal);
+        }
+        // The signal is reset before each try so a message sent after the
+        // try wakes the wait
+        let selected = loop {
+            signal.reset();
+            if let Some(selected) = self.try_select() {
+                break Some(selected);
+            }
+            if !signal.wait(deadline) {
+                break self.try_select();
+            }
+        };
+        for receiver in self.receivers.iter() {
+            receiver.unregister(&signal);
+        }
+        selected
+    }
+}
+
+impl Selected {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /* Take the message.  The receiver must be the one at index() (it is only
+     * used for the message type).
+     */
+    pub fn recv<T : 'static>(self, _receiver : &Receiver<T>) -> Result<T, RecvError> {
+        self.message.map(|message| *message.downcast::<T>().expect("Selected::recv with the wrong receiver"))
+    }
+}
+
+/* Wait on several receivers and run the arm of the one that is ready:
+ *
+ *     select! {
+ *         recv(numbers) -> number => println!("{number:?}"),
+ *         recv(words) -> word => println!("{word:?}"),
+ *         default(Duration::from_secs(1)) => println!("timed out")
+ *     }
+ *
+ * The message is a Result<T, RecvError> (Err when that channel is
+ * disconnected).  The default arm is optional.  The receiver expressions are
+ * evaluated more than once so they should be plain variables.
+ */
+#[macro_export]
+macro_rules! select {
+    ($(recv($rx:expr) -> $res:pat => $body:expr),+ $(,)?) => {{
+        let mut select = $crate::channel::Select::new();
+        $( select.recv(&$rx); )+
+        let selected = select.select();
+        drop(select);
+        $crate::select!(@dispatch selected, 0usize; $(recv($rx) -> $res => $body),+)
+    }};
+    ($(recv($rx:expr) -> $res:pat => $body:expr,)+ default($timeout:expr) => $default:expr $(,)?) => {{
+        let mut select = $crate::channel::Select::new();
+        $( select.recv(&$rx); )+
+        let selected = select.select_timeout($timeout);
+        drop(select);
+        match selected {
+            Some(selected) => $crate::select!(@dispatch selected, 0usize; $(recv($rx) -> $res => $body),+),
+            None => $default
+        }
+    }};
+    (@dispatch $selected:ident, $index:expr; recv($rx:expr) -> $res:pat => $body:expr $(, recv($rest:expr) -> $rest_res:pat => $rest_body:expr)*) => {
+        if $selected.index() == $index {
+            let $res = $selected.recv(&$rx);
+            $body
+        }
+        else {
+            $crate::select!(@dispatch $selected, $index + 1; $(recv($rest) -> $rest_res => $rest_body),*)
+        }
+    };
+    (@dispatch $selected:ident, $index:expr; ) => {
+        unreachable!("Selected index out of range")
+    };
+}
+
+impl<T> fmt::Debug for SendError<T> {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SendError(..)")
+    }
+}
+
+impl<T> fmt::Debug for TrySendError<T> {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => write!(f, "Full(..)"),
+            TrySendError::Disconnected(_) => write!(f, "Disconnected(..)")
+        }
+    }
+}
+
+impl<T> fmt::Debug for SendTimeoutError<T> {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SendTimeoutError::Timeout(_) => write!(f, "Timeout(..)"),
+            SendTimeoutError::Disconnected(_) => write!(f, "Disconnected(..)")
+        }
+    }
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Sending on a disconnected channel")
+    }
+}
+
+impl<T> fmt::Display for TrySendError<T> {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => write!(f, "Sending on a full channel"),
+            TrySendError::Disconnected(_) => write!(f, "Sending on a disconnected channel")
+        }
+    }
+}
+
+impl<T> fmt::Display for SendTimeoutError<T> {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SendTimeoutError::Timeout(_) => write!(f, "Timed out sending on a full channel"),
+            SendTimeoutError::Disconnected(_) => write!(f, "Sending on a disconnected channel")
+        }
+    }
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Receiving on an empty and disconnected channel")
+    }
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TryRecvError::Empty => write!(f, "Receiving on an empty channel"),
+            TryRecvError::Disconnected => write!(f, "Receiving on an empty and disconnected channel")
+        }
+    }
+}
+
+impl fmt::Display for RecvTimeoutError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecvTimeoutError::Timeout => write!(f, "Timed out receiving on an empty channel"),
+            RecvTimeoutError::Disconnected => write!(f, "Receiving on an empty and disconnected channel")
+        }
+    }
+}
+
+impl<T> std::error::Error for SendError<T> {}
+impl<T> std::error::Error for TrySendError<T> {}
+impl<T> std::error::Error for SendTimeoutError<T> {}
+impl std::error::Error for RecvError {}
+impl std::error::Error for TryRecvError {}
+impl std::error::Error for RecvTimeoutError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::env;
+    use std::thread;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_send_recv() {
+        let (tx, rx) = bounded(2);
+        tx.send(1).unwrap();
+        assert_eq!(tx.try_send(2), Ok(()));
+        assert_eq!(tx.try_send(3), Err(TrySendError::Full(3)));
+        assert_eq!(tx.send_timeout(3, Duration::from_millis(10)), Err(SendTimeoutError::Timeout(3)));
+        assert!(tx.is_full());
+        assert_eq!(rx.len(), 2);
+        assert_eq!(rx.recv(), Ok(1));
+        assert_eq!(rx.try_recv(), Ok(2));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(rx.recv_timeout(Duration::from_millis(10)), Err(RecvTimeoutError::Timeout));
+    }
+
+    #[test]
+    fn test_blocking() {
+        let (tx, rx) = bounded(1);
+        let producer = thread::spawn(move || {
+            for n in 0..100 {
+                tx.send(n).unwrap();
+            }
+        });
+        let received = rx.iter().collect::<Vec<i32>>();
+        producer.join().unwrap();
+        assert_eq!(received, (0..100).collect::<Vec<i32>>());
+    }
+
+    #[test]
+    fn test_disconnect() {
+        let (tx, rx) = bounded(4);
+        let tx2 = tx.clone();
+        tx.send("left").unwrap();
+        drop(tx);
+        assert!(!rx.is_disconnected());
+        drop(tx2);
+        assert!(rx.is_disconnected());
+        // Messages that were sent are still delivered
+        assert_eq!(rx.recv(), Ok("left"));
+        assert_eq!(rx.recv(), Err(RecvError));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+        assert_eq!(rx.recv_timeout(Duration::from_secs(1)), Err(RecvTimeoutError::Disconnected));
+
+        let (tx, rx) = bounded(1);
+        tx.send(1).unwrap();
+        let blocked = thread::spawn(move || tx.send(2));
+        thread::sleep(Duration::from_millis(20));
+        drop(rx);
+        assert_eq!(blocked.join().unwrap(), Err(SendError(2)));
+    }
+
+    #[test]
+    fn test_select() {
+        let (number_tx, numbers) = bounded::<i32>(1);
+        let (word_tx, words) = bounded::<String>(1);
+
+        let sender = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            word_tx.send("cat".to_string()).unwrap();
+        });
+        let received = select! {
+            recv(numbers) -> number => format!("number {}", number.unwrap()),
+            recv(words) -> word => format!("word {}", word.unwrap()),
+        };
+        assert_eq!(received, "word cat");
+        sender.join().unwrap();
+
+        // The word channel is now disconnected so it is always ready
+        let received = select! {
+            recv(numbers) -> _number => None,
+            recv(words) -> word => Some(word),
+        };
+        assert_eq!(received, Some(Err(RecvError)));
+
+        let (_number_tx2, numbers2) = bounded::<i32>(1);
+        let received = select! {
+            recv(numbers) -> _number => "number",
+            recv(numbers2) -> _number => "number",
+            default(Duration::from_millis(20)) => "timeout"
+        };
+        assert_eq!(received, "timeout");
+        drop(number_tx);
+    }
+
+    #[test]
+    fn test_select_fair() {
+        let (a_tx, a) = bounded(1000);
+        let (b_tx, b) = bounded(1000);
+        for n in 0..1000 {
+            a_tx.send(n).unwrap();
+            b_tx.send(n).unwrap();
+        }
+        let mut counts = [0; 2];
+        let mut select = Select::new();
+        select.recv(&a);
+        select.recv(&b);
+        for _ in 0..1000 {
+            counts[select.select().index()] += 1;
+        }
+        assert!(counts[0] > 300 && counts[1] > 300, "{counts:?}");
+    }
+
+    /* Producers and consumers with random capacity, timing and operations.
+     * Every message must be received exactly once, messages from one
+     * producer must arrive in order at each consumer, and the channel must
+     * never hold more than its capacity.
+     */
+    fn stress(seed : u64) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let capacity = rng.gen_range(1..8);
+        let producers = rng.gen_range(1..5);
+        let consumers = rng.gen_range(1..5);
+        let messages = rng.gen_range(1..500);
+        let (tx, rx) = bounded::<(usize, usize)>(capacity);
+
+        let producer_threads = (0..producers).map(|producer| {
+            let tx = tx.clone();
+            let mut rng = StdRng::seed_from_u64(seed + 1 + producer as u64);
+            thread::spawn(move || {
+                for sequence in 0..messages {
+                    let mut message = (producer, sequence);
+                    loop {
+                        let result = match rng.gen_range(0..3) {
+                            0 => tx.send(message).map_err(|SendError(message)| TrySendError::Disconnected(message)),
+                            1 => tx.try_send(message),
+                            _ => tx.send_timeout(message, Duration::from_micros(rng.gen_range(0..200)))
+                                .map_err(|error| match error {
+                                    SendTimeoutError::Timeout(message) => TrySendError::Full(message),
+                                    SendTimeoutError::Disconnected(message) => TrySendError::Disconnected(message)
+                                })
+                        };
+                        match result {
+                            Ok(()) => break,
+                            Err(TrySendError::Full(unsent)) => message = unsent,
+                            Err(TrySendError::Disconnected(_)) => panic!("Receivers dropped early")
+                        }
+                        if rng.gen_bool(0.5) {
+                            thread::yield_now();
+                        }
+                    }
+                    assert!(tx.len() <= capacity);
+                }
+            })
+        }).collect::<Vec<_>>();
+        drop(tx);
+
+        let consumer_threads = (0..consumers).map(|consumer| {
+            let rx = rx.clone();
+            let mut rng = StdRng::seed_from_u64(seed + 100 + consumer as u64);
+            thread::spawn(move || {
+                let mut received = Vec::new();
+                loop {
+                    let result = match rng.gen_range(0..3) {
+                        0 => rx.recv().map_err(|_| TryRecvError::Disconnected),
+                        1 => rx.try_recv(),
+                        _ => rx.recv_timeout(Duration::from_micros(rng.gen_range(0..200)))
+                            .map_err(|error| match error {
+                                RecvTimeoutError::Timeout => TryRecvError::Empty,
+                                RecvTimeoutError::Disconnected => TryRecvError::Disconnected
+                            })
+                    };
+                    match result {
+                        Ok(message) => received.push(message),
+                        Err(TryRecvError::Empty) => thread::yield_now(),
+                        Err(TryRecvError::Disconnected) => break
+                    }
+                }
+                received
+            })
+        }).collect::<Vec<_>>();
+        drop(rx);
+
+        for producer in producer_threads {
+            producer.join().unwrap();
+        }
+        let mut all = Vec::new();
+        for consumer in consumer_threads {
+            let received = consumer.join().unwrap();
+            let mut last = HashMap::new();
+            for (producer, sequence) in received.iter() {
+                if let Some(previous) = last.insert(*producer, *sequence) {
+                    assert!(previous < *sequence, "Out of order (seed {seed})");
+                }
+            }
+            all.extend(received);
+        }
+        all.sort();
+        let expected = (0..producers).flat_map(|producer| (0..messages).map(move |sequence| (producer, sequence)))
+            .collect::<Vec<_>>();
+        assert_eq!(all, expected, "Lost or duplicated messages (seed {seed})");
+    }
+
+    #[test]
+    fn test_stress() {
+        for seed in 0..20 {
+            stress(seed);
+        }
+    }
+
+    /* Long randomized run.  Set CHANNEL_STRESS_RUNS for more runs:
+     *
+     *     CHANNEL_STRESS_RUNS=10000 cargo test --release stress_long -- --ignored
+     */
+    #[test]
+    #[ignore]
+    fn stress_long() {
+        let runs = env::var("CHANNEL_STRESS_RUNS").ok().and_then(|runs| runs.parse().ok()).unwrap_or(1000);
+        let first = rand::thread_rng().gen::<u32>() as u64;
+        println!("Seeds {first} to {}", first + runs);
+        for seed in first..first + runs {
+            stress(seed * 1000);
+        }
+    }
+
+    #[test]
+    fn test_select_stress() {
+        // Several consumers select over the same channels
+        let (a_tx, a) = bounded::<u32>(2);
+        let (b_tx, b) = bounded::<u64>(2);
+        let consumers = (0..4).map(|_| {
+            let (a, b) = (a.clone(), b.clone());
+            thread::spawn(move || {
+                let mut total = 0_u64;
+                loop {
+                    let disconnected = select! {
+                        recv(a) -> value => value.map(|value| total += value as u64).is_err(),
+                        recv(b) -> value => value.map(|value| total += value).is_err(),
+                    };
+                    // A disconnected channel may be picked while the other still has messages
+                    if disconnected && a.is_disconnected() && b.is_disconnected() && a.is_empty() && b.is_empty() {
+                        break;
+                    }
+                }
+                total
+            })
+        }).collect::<Vec<_>>();
+        drop((a, b));
+        let producers = [
+            thread::spawn(move || (1..=5000).for_each(|n| a_tx.send(n).unwrap())),
+            thread::spawn(move || (1..=5000).for_each(|n| b_tx.send(n).unwrap()))
+        ];
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        let total = consumers.into_iter().map(|consumer| consumer.join().unwrap()).sum::<u64>();
+        assert_eq!(total, 2 * 5000 * 5001 / 2);
+    }
+}
@@ -1,10 +1,9 @@
-use std::sync::{Arc, Mutex, Condvar};
+use std::sync::{Arc, Mutex};
 use std::{thread, time};
 
 pub type SharedData<T> = Arc<Mutex<Vec<T>>>;
 
-fn factory_init(count : u8, requests : &SharedData<()>) -> u64 {
-    let x = Arc::new(Condvar::new());
+pub fn factory_init(count : u8, requests : &SharedData<()>) -> u64 {
     let mut threads = Vec::new();
     for _ in 0..count {
         let requests = requests.clone();
@@ -1,4 +1,10 @@
 pub mod threadmap;
 pub mod factory;
+pub mod cancel;
+pub mod pool;
+pub mod parallel;
+pub mod channel;
+pub mod map;
+pub mod pipeline;
 
 
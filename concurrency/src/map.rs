@@ -0,0 +1,416 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::thread;
+use std::vec;
+
+// Shards per available thread, so two threads rarely want the same lock
+const SHARDS_PER_THREAD : usize = 4;
+
+/* A hash map that can be shared between threads (in an Arc or by
+ * reference).  The keys are split over shards by their hash and every shard
+ * is a HashMap behind its own RwLock, so readers never block each other and
+ * writers only block the threads that use the same shard.
+ *
+ * Values are returned by cloning them out of the map because no reference
+ * can outlive the shard lock.  Use get_with to look at a value in place.
+ */
+pub struct ConcurrentMap<K, V, S = RandomState> {
+    shards : Box<[RwLock<HashMap<K, V, S>>]>,
+    hasher : S
+}
+
+fn read<T>(lock : &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn write<T>(lock : &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl<K : Hash + Eq, V> ConcurrentMap<K, V> {
+    pub fn new() -> Self {
+        let threads = thread::available_parallelism().map(|count| count.get()).unwrap_or(4);
+        ConcurrentMap::with_shards(threads * SHARDS_PER_THREAD)
+    }
+
+    // Shards is rounded up to a power of two
+    pub fn with_shards(shards : usize) -> Self {
+        ConcurrentMap::with_shards_and_hasher(shards, RandomState::new())
+    }
+}
+
+impl<K : Hash + Eq, V, S : BuildHasher + Clone> ConcurrentMap<K, V, S> {
+    pub fn with_shards_and_hasher(shards : usize, hasher : S) -> Self {
+        let shards = shards.max(1).next_power_of_two();
+        ConcurrentMap {
+            shards : (0..shards).map(|_| RwLock::new(HashMap::with_hasher(hasher.clone()))).collect(),
+            hasher
+        }
+    }
+
+    fn shard<Q>(&self, key : &Q) -> &RwLock<HashMap<K, V, S>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + ?Sized
+    {
+        // The top bits, because HashMap uses the low bits inside the shard
+        let bits = self.shards.len().trailing_zeros();
+        let index = self.hasher.hash_one(key).checked_shr(u64::BITS - bits).unwrap_or(0) as usize;
+        &self.shards[index]
+    }
+
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn get<Q>(&self, key : &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: Clone
+    {
+        self.get_with(key, V::clone)
+    }
+
+    // Call f on the value while the shard is locked for reading
+    pub fn get_with<Q, R, F>(&self, key : &Q, f : F) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        F: FnOnce(&V) -> R
+    {
+        read(self.shard(key)).get(key).map(f)
+    }
+
+    pub fn contains_key<Q>(&self, key : &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized
+    {
+        read(self.shard(key)).contains_key(key)
+    }
+
+    // Returns the old value if the key was already in the map
+    pub fn insert(&self, key : K, value : V) -> Option<V> {
+        write(self.shard(&key)).insert(key, value)
+    }
+
+    pub fn remove<Q>(&self, key : &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized
+    {
+        write(self.shard(key)).remove(key)
+    }
+
+    /* The value for key, made with make if the key is missing.  When several
+     * threads ask for the same missing key, make is called once and they all
+     * get that value.  make runs with the shard locked, so it must not use
+     * the map.
+     */
+    pub fn compute_if_absent<F>(&self, key : K, make : F) -> V
+    where
+        F: FnOnce(&K) -> V,
+        V: Clone
+    {
+        let shard = self.shard(&key);
+        if let Some(value) = read(shard).get(&key) {
+            return value.clone();
+        }
+        let mut shard = write(shard);
+        // Another thread may have added it between the two locks
+        if let Some(value) = shard.get(&key) {
+            return value.clone();
+        }
+        let value = make(&key);
+        shard.insert(key, value.clone());
+        value
+    }
+
+    // Change the value in place and return what f returns (None if missing)
+    pub fn update<Q, R, F>(&self, key : &Q, f : F) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        F: FnOnce(&mut V) -> R
+    {
+        write(self.shard(key)).get_mut(key).map(f)
+    }
+
+    // The shards are counted one at a time, so this is only exact when no
+    // other thread is changing the map
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| read(shard).len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| read(shard).is_empty())
+    }
+
+    pub fn clear(&self) {
+        for shard in self.shards.iter() {
+            write(shard).clear();
+        }
+    }
+
+    /* Copies of the entries, one shard at a time.  Other threads can keep
+     * using the map: a shard is only locked while it is copied.  Keys that
+     * are in the map for the whole iteration are returned exactly once, keys
+     * that are added or removed during it may or may not be returned.
+     */
+    pub fn iter(&self) -> Iter<'_, K, V, S>
+    where
+        K: Clone,
+        V: Clone
+    {
+        Iter { map : self, next_shard : 0, entries : Vec::new().into_iter() }
+    }
+}
+
+impl<K : Hash + Eq, V> Default for ConcurrentMap<K, V> {
+    fn default() -> Self {
+        ConcurrentMap::new()
+    }
+}
+
+impl<K : Hash + Eq, V> FromIterator<(K, V)> for ConcurrentMap<K, V> {
+    fn from_iter<I : IntoIterator<Item = (K, V)>>(entries : I) -> Self {
+        let map = ConcurrentMap::new();
+        for (key, value) in entries {
+            map.insert(key, value);
+        }
+        map
+    }
+}
+
+impl<K, V, S> fmt::Debug for ConcurrentMap<K, V, S>
+where
+    K: Hash + Eq + Clone + fmt::Debug,
+    V: Clone + fmt::Debug,
+    S: BuildHasher + Clone
+{
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+pub struct Iter<'a, K, V, S> {
+    map : &'a ConcurrentMap<K, V, S>,
+    next_shard : usize,
+    entries : vec::IntoIter<(K, V)>
+}
+
+impl<K : Clone, V : Clone, S> Iterator for Iter<'_, K, V, S> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        loop {
+            if let Some(entry) = self.entries.next() {
+                return Some(entry);
+            }
+            let shard = self.map.shards.get(self.next_shard)?;
+            self.next_shard += 1;
+            self.entries = read(shard).iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect::<Vec<_>>()
+                .into_iter();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Barrier, Mutex};
+    use std::time::{Duration, Instant};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_basic() {
+        let map = ConcurrentMap::with_shards(3);
+        assert_eq!(map.shards(), 4);
+        assert!(map.is_empty());
+        assert_eq!(map.insert("cat".to_string(), 1), None);
+        assert_eq!(map.insert("dog".to_string(), 2), None);
+        assert_eq!(map.insert("cat".to_string(), 3), Some(1));
+        // Looked up by &str like a HashMap<String, _>
+        assert_eq!(map.get("cat"), Some(3));
+        assert_eq!(map.get_with("dog", |value| value * 10), Some(20));
+        assert!(!map.contains_key("pig"));
+        assert_eq!(map.update("dog", |value| { *value += 1; *value }), Some(3));
+        assert_eq!(map.len(), 2);
+
+        assert_eq!(map.remove("cat"), Some(3));
+        assert_eq!(map.remove("cat"), None);
+        assert_eq!(map.compute_if_absent("pig".to_string(), |key| key.len()), 3);
+        assert_eq!(map.compute_if_absent("pig".to_string(), |_| panic!("Already there")), 3);
+
+        let mut entries = map.iter().collect::<Vec<_>>();
+        entries.sort();
+        assert_eq!(entries, [("dog".to_string(), 3), ("pig".to_string(), 3)]);
+        map.clear();
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn test_one_shard() {
+        let map = (0..100).map(|n| (n, n * n)).collect::<ConcurrentMap<i32, i32>>();
+        let single = ConcurrentMap::with_shards(1);
+        for (key, value) in map.iter() {
+            single.insert(key, value);
+        }
+        assert_eq!(single.shards(), 1);
+        assert_eq!(single.len(), 100);
+        assert_eq!(single.get(&9), Some(81));
+    }
+
+    #[test]
+    fn test_threads() {
+        let map = ConcurrentMap::new();
+        thread::scope(|scope| {
+            for thread in 0..8 {
+                let map = &map;
+                scope.spawn(move || {
+                    for n in 0..1000 {
+                        map.insert(thread * 1000 + n, n);
+                    }
+                    for n in (0..1000).step_by(2) {
+                        assert_eq!(map.remove(&(thread * 1000 + n)), Some(n));
+                    }
+                });
+            }
+        });
+        assert_eq!(map.len(), 4000);
+        assert!(map.iter().all(|(key, value)| key % 1000 == value && value % 2 == 1));
+    }
+
+    #[test]
+    fn test_compute_if_absent_once() {
+        let map = Arc::new(ConcurrentMap::new());
+        let calls = Arc::new(AtomicUsize::new(0));
+        let barrier = Arc::new(Barrier::new(8));
+        let threads = (0..8).map(|_| {
+            let (map, calls, barrier) = (Arc::clone(&map), Arc::clone(&calls), Arc::clone(&barrier));
+            thread::spawn(move || {
+                barrier.wait();
+                (0..100).map(|key| map.compute_if_absent(key, |key| {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    format!("value {key}")
+                })).collect::<Vec<_>>()
+            })
+        }).collect::<Vec<_>>();
+        for thread in threads {
+            let values = thread.join().unwrap();
+            assert!(values.iter().enumerate().all(|(key, value)| *value == format!("value {key}")));
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 100);
+    }
+
+    #[test]
+    fn test_iter_while_changing() {
+        // Keys 0..1000 stay in the map while the writers add and remove others
+        let map = (0..1000).map(|key| (key, key)).collect::<ConcurrentMap<i32, i32>>();
+        let stop = std::sync::atomic::AtomicBool::new(false);
+        thread::scope(|scope| {
+            for thread in 0..2 {
+                let (map, stop) = (&map, &stop);
+                scope.spawn(move || {
+                    let mut rng = StdRng::seed_from_u64(thread);
+                    while !stop.load(Ordering::SeqCst) {
+                        let key = rng.gen_range(1000..2000);
+                        if rng.gen_bool(0.5) {
+                            map.insert(key, key);
+                        }
+                        else {
+                            map.remove(&key);
+                        }
+                    }
+                });
+            }
+            for _ in 0..20 {
+                let mut seen = HashSet::new();
+                for (key, value) in map.iter() {
+                    assert_eq!(key, value);
+                    assert!(seen.insert(key), "{key} returned twice");
+                }
+                assert!((0..1000).all(|key| seen.contains(&key)));
+            }
+            stop.store(true, Ordering::SeqCst);
+        });
+    }
+
+    // Each thread does OPERATIONS random operations on keys below KEYS, and
+    // writes is the share of them that are inserts or removes
+    fn run_load<G, I, R>(threads : usize, writes : f64, get : G, insert : I, remove : R) -> Duration
+    where
+        G: Fn(u64) -> Option<u64> + Sync,
+        I: Fn(u64, u64) + Sync,
+        R: Fn(u64) + Sync
+    {
+        const KEYS : u64 = 10_000;
+        const OPERATIONS : usize = 200_000;
+        for key in (0..KEYS).step_by(2) {
+            insert(key, key);
+        }
+        let barrier = Barrier::new(threads);
+        let start = Mutex::new(None);
+        thread::scope(|scope| {
+            for thread in 0..threads {
+                let (get, insert, remove, barrier, start) = (&get, &insert, &remove, &barrier, &start);
+                scope.spawn(move || {
+                    let mut rng = StdRng::seed_from_u64(thread as u64);
+                    if barrier.wait().is_leader() {
+                        *start.lock().unwrap() = Some(Instant::now());
+                    }
+                    let mut found = 0;
+                    for _ in 0..OPERATIONS {
+                        let key = rng.gen_range(0..KEYS);
+                        if !rng.gen_bool(writes) {
+                            found += get(key).is_some() as usize;
+                        }
+                        else if rng.gen_bool(0.5) {
+                            insert(key, key);
+                        }
+                        else {
+                            remove(key);
+                        }
+                    }
+                    std::hint::black_box(found);
+                });
+            }
+        });
+        let start = start.into_inner().unwrap().unwrap();
+        start.elapsed()
+    }
+
+    /* Benchmark the ConcurrentMap against Mutex<HashMap>.  Run with:
+     *
+     *     cargo test --release -- --ignored --nocapture
+     */
+    #[test]
+    #[ignore]
+    fn bench_map_vs_mutex_hashmap() {
+        let threads = thread::available_parallelism().map(|count| count.get()).unwrap_or(4).max(2);
+        for (load, writes) in [("read-heavy", 0.05), ("mixed", 0.5), ("write-heavy", 0.95)] {
+            let mutex = Mutex::new(HashMap::new());
+            let mutex_time = run_load(threads, writes,
+                |key| mutex.lock().unwrap().get(&key).copied(),
+                |key, value| { mutex.lock().unwrap().insert(key, value); },
+                |key| { mutex.lock().unwrap().remove(&key); });
+
+            let map = ConcurrentMap::new();
+            let map_time = run_load(threads, writes,
+                |key| map.get(&key),
+                |key, value| { map.insert(key, value); },
+                |key| { map.remove(&key); });
+            println!("{load} ({threads} threads, {} shards): Mutex<HashMap> {mutex_time:?}  ConcurrentMap {map_time:?}", map.shards());
+        }
+    }
+}
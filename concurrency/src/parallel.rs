@@ -0,0 +1,195 @@
+use std::panic;
+use crate::pool::{JobHandle, ThreadPool};
+
+// Chunks per worker.  More than one lets idle workers steal the remaining
+// chunks when some elements take longer than others.
+const CHUNKS_PER_WORKER : usize = 4;
+
+fn chunk_len(pool : &ThreadPool, len : usize) -> usize {
+    len.div_ceil(pool.workers() * CHUNKS_PER_WORKER).max(1)
+}
+
+/* Run job once for each chunk on the pool and wait for all of them.  If a
+ * job panics, the remaining jobs still finish and then the first panic is
+ * resumed on the calling thread.
+ */
+fn run_chunks<I, F>(pool : &ThreadPool, chunks : I, job : F)
+where
+    I: Iterator,
+    I::Item: Send,
+    F: Fn(I::Item) + Sync
+{
+    let job = &job;
+    let panic = pool.scope(|scope| {
+        let handles = chunks.map(|chunk| scope.spawn(move || job(chunk))).collect::<Vec<_>>();
+        // Every handle is joined so the scope doesn't count the other panics
+        let results = handles.into_iter().map(JobHandle::join).collect::<Vec<_>>();
+        results.into_iter().find_map(Result::err)
+    });
+    if let Some(payload) = panic {
+        panic::resume_unwind(payload);
+    }
+}
+
+/* Apply map to every element and keep the order.  Each chunk writes its
+ * results directly into its part of the output.
+ */
+pub fn par_map<T, U, F>(pool : &ThreadPool, data : Vec<T>, map : F) -> Vec<U>
+where
+    T: Send,
+    U: Send,
+    F: Fn(T) -> U + Sync
+{
+    let size = chunk_len(pool, data.len());
+    let mut input = data.into_iter().map(Some).collect::<Vec<Option<T>>>();
+    let mut output = input.iter().map(|_| None).collect::<Vec<Option<U>>>();
+    run_chunks(pool, input.chunks_mut(size).zip(output.chunks_mut(size)), |(input, output)| {
+        for (value, result) in input.iter_mut().zip(output.iter_mut()) {
+            *result = value.take().map(&map);
+        }
+    });
+    output.into_iter().map(|result| result.expect("Chunk skipped")).collect()
+}
+
+/* Combine the elements with reduce (None if data is empty).  Each chunk is
+ * reduced in order and then the chunk results are reduced in order, so
+ * reduce has to be associative but not commutative.
+ */
+pub fn par_reduce<T, F>(pool : &ThreadPool, data : Vec<T>, reduce : F) -> Option<T>
+where
+    T: Send,
+    F: Fn(T, T) -> T + Sync
+{
+    let size = chunk_len(pool, data.len());
+    let mut input = data.into_iter().map(Some).collect::<Vec<Option<T>>>();
+    let mut partial = input.chunks(size).map(|_| None).collect::<Vec<Option<T>>>();
+    run_chunks(pool, input.chunks_mut(size).zip(partial.iter_mut()), |(input, partial)| {
+        *partial = input.iter_mut().filter_map(Option::take).reduce(&reduce);
+    });
+    partial.into_iter().flatten().reduce(&reduce)
+}
+
+// Keep the elements where keep returns true, in their original order
+pub fn par_filter<T, F>(pool : &ThreadPool, data : Vec<T>, keep : F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(&T) -> bool + Sync
+{
+    let size = chunk_len(pool, data.len());
+    let mut items = data.into_iter().map(Some).collect::<Vec<Option<T>>>();
+    run_chunks(pool, items.chunks_mut(size), |chunk| {
+        for item in chunk.iter_mut() {
+            if !item.as_ref().is_some_and(&keep) {
+                *item = None;
+            }
+        }
+    });
+    items.into_iter().flatten().collect()
+}
+
+// Call action on every element (in place)
+pub fn par_for_each<T, F>(pool : &ThreadPool, data : &mut [T], action : F)
+where
+    T: Send,
+    F: Fn(&mut T) + Sync
+{
+    let size = chunk_len(pool, data.len());
+    run_chunks(pool, data.chunks_mut(size), |chunk| chunk.iter_mut().for_each(&action));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Not Clone, so the functions must move the elements
+    #[derive(Debug, PartialEq)]
+    struct Word(String);
+
+    #[test]
+    fn test_map() {
+        let pool = ThreadPool::new(4);
+        let words = ["cat", "dog", "pig", "cow", "hen"].iter().map(|word| Word(word.to_string())).collect();
+        let lengths = par_map(&pool, words, |Word(word)| (word.to_uppercase(), word.len()));
+        assert_eq!(lengths, [("CAT", 3), ("DOG", 3), ("PIG", 3), ("COW", 3), ("HEN", 3)]
+            .map(|(word, len)| (word.to_string(), len)));
+        assert_eq!(par_map(&pool, Vec::<i32>::new(), |x| x + 1), Vec::<i32>::new());
+    }
+
+    #[test]
+    fn test_map_large() {
+        // One job per chunk instead of one thread per element
+        let pool = ThreadPool::new(4);
+        let data = (0..100_000_u64).collect::<Vec<u64>>();
+        let squares = par_map(&pool, data, |x| x * x);
+        assert_eq!(squares.len(), 100_000);
+        assert!(squares.iter().enumerate().all(|(index, square)| *square == (index * index) as u64));
+        assert_eq!(pool.stats().completed, (4 * CHUNKS_PER_WORKER) as u64);
+    }
+
+    #[test]
+    fn test_reduce() {
+        let pool = ThreadPool::new(3);
+        let data = (1..=10_000_u64).collect::<Vec<u64>>();
+        assert_eq!(par_reduce(&pool, data, |a, b| a + b), Some(50_005_000));
+
+        // Order is kept for operations that are not commutative
+        let letters = ('a'..='z').map(String::from).collect::<Vec<String>>();
+        assert_eq!(par_reduce(&pool, letters, |a, b| a + &b).unwrap(), "abcdefghijklmnopqrstuvwxyz");
+        assert_eq!(par_reduce(&pool, Vec::<u64>::new(), |a, b| a + b), None);
+    }
+
+    #[test]
+    fn test_filter() {
+        let pool = ThreadPool::new(4);
+        let words = ["apple", "bear", "avocado", "cat", "ant"].iter().map(|word| Word(word.to_string())).collect();
+        let words = par_filter(&pool, words, |Word(word)| word.starts_with('a'));
+        assert_eq!(words, ["apple", "avocado", "ant"].map(|word| Word(word.to_string())));
+
+        let evens = par_filter(&pool, (0..1000).collect(), |x| x % 2 == 0);
+        assert_eq!(evens, (0..1000).step_by(2).collect::<Vec<i32>>());
+    }
+
+    #[test]
+    fn test_for_each() {
+        let pool = ThreadPool::new(4);
+        let calls = AtomicUsize::new(0);
+        let mut data = (0..1000).collect::<Vec<i32>>();
+        par_for_each(&pool, &mut data, |x| {
+            *x *= 3;
+            calls.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(data, (0..1000).map(|x| x * 3).collect::<Vec<i32>>());
+        assert_eq!(calls.load(Ordering::SeqCst), 1000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Bad element (expected in test)")]
+    fn test_panic() {
+        let pool = ThreadPool::new(4);
+        par_map(&pool, (0..1000).collect(), |x : i32| {
+            if x == 500 {
+                panic!("Bad element (expected in test)");
+            }
+            x
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Bad chunk (expected in test)")]
+    fn test_many_panics() {
+        // Every chunk panics, the payload of one of them is resumed
+        let pool = ThreadPool::new(4);
+        par_for_each(&pool, &mut (0..100).collect::<Vec<i32>>(), |_| panic!("Bad chunk (expected in test)"));
+    }
+
+    #[test]
+    fn test_pool_survives_panic() {
+        let pool = ThreadPool::new(2);
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            par_for_each(&pool, &mut [1, 2, 3], |_| panic!("Job panic (expected in test)"))
+        }));
+        assert!(result.is_err());
+        assert_eq!(par_map(&pool, vec![1, 2, 3], |x| x + 1), vec![2, 3, 4]);
+    }
+}
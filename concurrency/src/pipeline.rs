@@ -0,0 +1,590 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use crate::channel::{bounded, Receiver, Sender};
+
+// Capacity of the channels between stages unless changed with buffer
+pub const DEFAULT_BUFFER : usize = 64;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PipelineError<E> {
+    // The first error returned by a stage.  Every other stage was stopped.
+    Failed { stage : String, error : E },
+    Panicked { stage : String }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StageStats {
+    pub name : String,
+    pub workers : usize,
+    // Capacity of the channel into the stage (0 for the source)
+    pub buffer : usize,
+    pub received : u64,
+    pub sent : u64,
+    // Messages waiting in the channel into the stage (last seen and largest)
+    pub queued : usize,
+    pub max_queued : usize,
+    pub elapsed : Duration
+}
+
+// Result of a pipeline that finished without errors
+#[derive(Debug)]
+pub struct Output<R> {
+    pub value : R,
+    pub stages : Vec<StageStats>
+}
+
+struct Control<E> {
+    cancelled : AtomicBool,
+    error : Mutex<Option<PipelineError<E>>>
+}
+
+struct StageCounter {
+    name : String,
+    workers : usize,
+    buffer : usize,
+    received : AtomicU64,
+    sent : AtomicU64,
+    queued : AtomicUsize,
+    max_queued : AtomicUsize,
+    active : AtomicUsize,
+    started : OnceLock<Instant>,
+    finished : OnceLock<Instant>
+}
+
+type Stages = Arc<Mutex<Vec<Arc<StageCounter>>>>;
+// Starts the threads of a stage
+type Launch = Box<dyn FnOnce() -> Vec<JoinHandle<()>> + Send>;
+// Gives the last stage its output channel
+type Connect<T> = Box<dyn FnOnce(Sender<T>) -> Launch + Send>;
+
+/* Chain of stages connected by bounded channels:
+ *
+ *     let output = Pipeline::source("numbers", 0..1000)
+ *         .map("square", 4, |n| Ok::<u64, String>(n * n))
+ *         .filter("even", 2, |n| Ok(n % 2 == 0))
+ *         .batch("batch", 100)
+ *         .sink("total", 0, |total, batch| { *total += batch.iter().sum::<u64>(); Ok(()) })?;
+ *
+ * Every stage has its own threads.  A full channel blocks the stage that
+ * sends to it so a slow stage slows down the stages before it instead of
+ * filling memory.  The first error (or panic) cancels every stage and is
+ * returned by sink.  Stages with more than one worker do not keep the order
+ * of the messages.  Nothing runs until sink is called.
+ */
+pub struct Pipeline<T, E> {
+    control : Arc<Control<E>>,
+    stages : Stages,
+    launches : Vec<Launch>,
+    connect : Connect<T>,
+    buffer : usize
+}
+
+/* Reads the statistics of a running pipeline from another thread.
+ */
+#[derive(Clone)]
+pub struct PipelineMonitor {
+    stages : Stages
+}
+
+fn lock<T>(mutex : &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl<E> Control<E> {
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    // Keep the first error and stop every stage
+    fn fail(&self, error : PipelineError<E>) {
+        let mut first = lock(&self.error);
+        if first.is_none() {
+            *first = Some(error);
+        }
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+}
+
+impl StageCounter {
+    fn start(&self) {
+        self.started.get_or_init(Instant::now);
+    }
+
+    fn receive(&self, queued : usize) {
+        self.received.fetch_add(1, Ordering::SeqCst);
+        self.queued.store(queued, Ordering::SeqCst);
+        self.max_queued.fetch_max(queued, Ordering::SeqCst);
+    }
+
+    fn send(&self) {
+        self.sent.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn worker_done(&self) {
+        if self.active.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.finished.get_or_init(Instant::now);
+        }
+    }
+
+    fn stats(&self) -> StageStats {
+        let elapsed = match (self.started.get(), self.finished.get()) {
+            (Some(started), Some(finished)) => finished.duration_since(*started),
+            (Some(started), None) => started.elapsed(),
+            _ => Duration::ZERO
+        };
+        StageStats {
+            name : self.name.clone(),
+            workers : self.workers,
+            buffer : self.buffer,
+            received : self.received.load(Ordering::SeqCst),
+            sent : self.sent.load(Ordering::SeqCst),
+            queued : self.queued.load(Ordering::SeqCst),
+            max_queued : self.max_queued.load(Ordering::SeqCst),
+            elapsed
+        }
+    }
+}
+
+impl StageStats {
+    // Messages received per second (sent for the source)
+    pub fn throughput(&self) -> f64 {
+        let count = if self.buffer == 0 { self.sent } else { self.received };
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 { count as f64 / seconds } else { 0.0 }
+    }
+}
+
+/* Each stage thread owns a Worker.  If the stage panics, the Worker is
+ * dropped while the thread is unwinding and the pipeline is cancelled.
+ */
+struct Worker<E> {
+    control : Arc<Control<E>>,
+    counter : Arc<StageCounter>
+}
+
+impl<E> Drop for Worker<E> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.control.fail(PipelineError::Panicked { stage : self.counter.name.clone() });
+        }
+        self.counter.worker_done();
+    }
+}
+
+fn spawn_worker<E, F>(name : String, worker : Worker<E>, work : F) -> JoinHandle<()>
+where
+    E: Send + 'static,
+    F: FnOnce(&Worker<E>) + Send + 'static
+{
+    thread::Builder::new()
+        .name(name)
+        .spawn(move || {
+            worker.counter.start();
+            work(&worker);
+        })
+        .expect("Failed to spawn pipeline stage")
+}
+
+impl<T, E> Pipeline<T, E>
+where
+    T: Send + 'static,
+    E: Send + 'static
+{
+    // Start a pipeline with the items of an iterator (read on its own thread)
+    pub fn source<I>(name : &str, items : I) -> Self
+    where
+        I: IntoIterator<Item = T> + Send + 'static
+    {
+        let control = Arc::new(Control { cancelled : AtomicBool::new(false), error : Mutex::new(None) });
+        let stages = Arc::new(Mutex::new(Vec::new()));
+        let counter = register(&stages, name, 1, 0);
+        let worker = Worker { control : Arc::clone(&control), counter };
+        let name = name.to_string();
+        let connect : Connect<T> = Box::new(move |output : Sender<T>| -> Launch { Box::new(move || {
+            vec![spawn_worker(name, worker, move |worker| {
+                for item in items {
+                    if worker.control.is_cancelled() || output.send(item).is_err() {
+                        break;
+                    }
+                    worker.counter.send();
+                }
+            })]
+        })});
+        Pipeline { control, stages, launches : Vec::new(), connect, buffer : DEFAULT_BUFFER }
+    }
+
+    // Capacity of the channels into the stages added after this
+    pub fn buffer(mut self, capacity : usize) -> Self {
+        self.buffer = capacity.max(1);
+        self
+    }
+
+    pub fn monitor(&self) -> PipelineMonitor {
+        PipelineMonitor { stages : Arc::clone(&self.stages) }
+    }
+
+    pub fn map<U, F>(self, name : &str, workers : usize, map : F) -> Pipeline<U, E>
+    where
+        U: Send + 'static,
+        F: Fn(T) -> Result<U, E> + Send + Sync + 'static
+    {
+        let map = Arc::new(map);
+        self.stage(name, workers, move || {
+            let map = Arc::clone(&map);
+            move |item : Option<T>, emit : &mut dyn FnMut(U) -> bool| {
+                if let Some(item) = item {
+                    emit(map(item)?);
+                }
+                Ok(())
+            }
+        })
+    }
+
+    // Each item becomes zero or more items
+    pub fn flat_map<U, I, F>(self, name : &str, workers : usize, map : F) -> Pipeline<U, E>
+    where
+        U: Send + 'static,
+        I: IntoIterator<Item = U>,
+        F: Fn(T) -> Result<I, E> + Send + Sync + 'static
+    {
+        let map = Arc::new(map);
+        self.stage(name, workers, move || {
+            let map = Arc::clone(&map);
+            move |item : Option<T>, emit : &mut dyn FnMut(U) -> bool| {
+                if let Some(item) = item {
+                    for value in map(item)? {
+                        if !emit(value) {
+                            break;
+                        }
+                    }
+                }
+                Ok(())
+            }
+        })
+    }
+
+    pub fn filter<F>(self, name : &str, workers : usize, keep : F) -> Pipeline<T, E>
+    where
+        F: Fn(&T) -> Result<bool, E> + Send + Sync + 'static
+    {
+        let keep = Arc::new(keep);
+        self.stage(name, workers, move || {
+            let keep = Arc::clone(&keep);
+            move |item : Option<T>, emit : &mut dyn FnMut(T) -> bool| {
+                if let Some(item) = item {
+                    if keep(&item)? {
+                        emit(item);
+                    }
+                }
+                Ok(())
+            }
+        })
+    }
+
+    // Group items into Vecs of size (the last one may be smaller)
+    pub fn batch(self, name : &str, size : usize) -> Pipeline<Vec<T>, E> {
+        let size = size.max(1);
+        self.stage(name, 1, move || {
+            let mut batch = Vec::with_capacity(size);
+            move |item : Option<T>, emit : &mut dyn FnMut(Vec<T>) -> bool| {
+                match item {
+                    Some(item) => {
+                        batch.push(item);
+                        if batch.len() == size {
+                            emit(std::mem::replace(&mut batch, Vec::with_capacity(size)));
+                        }
+                    }
+                    None if !batch.is_empty() => { emit(std::mem::take(&mut batch)); }
+                    None => ()
+                }
+                Ok(())
+            }
+        })
+    }
+
+    /* Add a stage.  make is called once per worker.  Each worker gets every
+     * item it receives and then None when the input is finished, and sends
+     * its results with emit (false once the next stage is gone).
+     */
+    fn stage<U, W, M>(mut self, name : &str, workers : usize, make : M) -> Pipeline<U, E>
+    where
+        U: Send + 'static,
+        W: FnMut(Option<T>, &mut dyn FnMut(U) -> bool) -> Result<(), E> + 'static,
+        M: Fn() -> W + Send + Sync + 'static
+    {
+        let workers = workers.max(1);
+        let (sender, input) = bounded::<T>(self.buffer);
+        self.launches.push((self.connect)(sender));
+        let counter = register(&self.stages, name, workers, self.buffer);
+        let control = Arc::clone(&self.control);
+        let name = name.to_string();
+        let make = Arc::new(make);
+        let connect : Connect<U> = Box::new(move |output : Sender<U>| -> Launch { Box::new(move || {
+            (0..workers).map(|index| {
+                let worker = Worker { control : Arc::clone(&control), counter : Arc::clone(&counter) };
+                let (input, output, make) = (input.clone(), output.clone(), Arc::clone(&make));
+                spawn_worker(format!("{name}-{index}"), worker, move |worker| {
+                    run_stage(worker, input, output, make())
+                })
+            }).collect()
+        })});
+        Pipeline { control : self.control, stages : self.stages, launches : self.launches, connect, buffer : self.buffer }
+    }
+
+    /* Run the pipeline.  The sink runs on the calling thread and folds every
+     * item into value.  Returns the value when every stage has finished, or
+     * the first error.
+     */
+    pub fn sink<R, F>(mut self, name : &str, initial : R, mut sink : F) -> Result<Output<R>, PipelineError<E>>
+    where
+        F: FnMut(&mut R, T) -> Result<(), E>
+    {
+        let (sender, input) = bounded::<T>(self.buffer);
+        self.launches.push((self.connect)(sender));
+        let counter = register(&self.stages, name, 1, self.buffer);
+        let threads = self.launches.into_iter().flat_map(|launch| launch()).collect::<Vec<_>>();
+
+        let worker = Worker { control : Arc::clone(&self.control), counter };
+        worker.counter.start();
+        let mut value = initial;
+        while let Ok(item) = input.recv() {
+            if worker.control.is_cancelled() {
+                break;
+            }
+            worker.counter.receive(input.len());
+            if let Err(error) = sink(&mut value, item) {
+                worker.control.fail(PipelineError::Failed { stage : name.to_string(), error });
+                break;
+            }
+        }
+        // Stages still sending see the channel close
+        drop(input);
+        drop(worker);
+        for thread in threads {
+            let _ = thread.join();
+        }
+
+        match lock(&self.control.error).take() {
+            Some(error) => Err(error),
+            None => Ok(Output { value, stages : PipelineMonitor { stages : self.stages }.stats() })
+        }
+    }
+}
+
+fn register(stages : &Stages, name : &str, workers : usize, buffer : usize) -> Arc<StageCounter> {
+    let counter = Arc::new(StageCounter {
+        name : name.to_string(),
+        workers,
+        buffer,
+        received : AtomicU64::new(0),
+        sent : AtomicU64::new(0),
+        queued : AtomicUsize::new(0),
+        max_queued : AtomicUsize::new(0),
+        active : AtomicUsize::new(workers),
+        started : OnceLock::new(),
+        finished : OnceLock::new()
+    });
+    lock(stages).push(Arc::clone(&counter));
+    counter
+}
+
+fn run_stage<T, U, E, W>(worker : &Worker<E>, input : Receiver<T>, output : Sender<U>, mut work : W)
+where
+    W: FnMut(Option<T>, &mut dyn FnMut(U) -> bool) -> Result<(), E>
+{
+    let counter = &worker.counter;
+    let mut emit = |value : U| {
+        let sent = output.send(value).is_ok();
+        if sent {
+            counter.send();
+        }
+        sent
+    };
+    loop {
+        let item = input.recv().ok();
+        if worker.control.is_cancelled() {
+            break;
+        }
+        let finished = item.is_none();
+        if !finished {
+            counter.receive(input.len());
+        }
+        if let Err(error) = work(item, &mut emit) {
+            worker.control.fail(PipelineError::Failed { stage : counter.name.clone(), error });
+            break;
+        }
+        if finished || output.is_disconnected() {
+            break;
+        }
+    }
+}
+
+impl PipelineMonitor {
+    // One entry per stage in pipeline order (source first, sink last)
+    pub fn stats(&self) -> Vec<StageStats> {
+        lock(&self.stages).iter().map(|counter| counter.stats()).collect()
+    }
+}
+
+impl<E : fmt::Display> fmt::Display for PipelineError<E> {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PipelineError::Failed { stage, error } => write!(f, "Stage {stage} failed: {error}"),
+            PipelineError::Panicked { stage } => write!(f, "Stage {stage} panicked")
+        }
+    }
+}
+
+impl<E : fmt::Debug + fmt::Display> std::error::Error for PipelineError<E> {}
+
+impl fmt::Display for StageStats {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:<12} workers {:>2}  received {:>8}  sent {:>8}  queued {:>4}/{:<4} (max {:>4})  {:>12.0}/s",
+            self.name, self.workers, self.received, self.sent, self.queued, self.buffer, self.max_queued,
+            self.throughput())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::Path;
+    use serde::Deserialize;
+
+    // The parts of the ss scripture files used for the word count.  The
+    // Doctrine and Covenants has sections instead of books of chapters.
+    #[derive(Deserialize)]
+    struct Scriptures {
+        #[serde(default)]
+        books : Vec<Book>,
+        #[serde(default)]
+        sections : Vec<Chapter>
+    }
+
+    #[derive(Deserialize)]
+    struct Book {
+        chapters : Vec<Chapter>
+    }
+
+    #[derive(Deserialize)]
+    struct Chapter {
+        verses : Vec<Verse>
+    }
+
+    #[derive(Deserialize)]
+    struct Verse {
+        text : String
+    }
+
+    const SCRIPTURES : [&str; 4] = ["bookofmormon.json", "doctrineandcovenants.json", "newtestament.json", "pearlofgreatprice.json"];
+
+    // Same separators as ss
+    const SEPARATORS : [char; 10] = [' ','.',',',':',';','?','!','(',')','—'];
+
+    fn load_verses() -> Vec<String> {
+        let folder = Path::new(env!("CARGO_MANIFEST_DIR")).join("../ss");
+        SCRIPTURES.iter().flat_map(|file| {
+            let json = fs::read_to_string(folder.join(file)).unwrap();
+            let scriptures : Scriptures = serde_json::from_str(&json).unwrap();
+            scriptures.books.into_iter()
+                .flat_map(|book| book.chapters)
+                .chain(scriptures.sections)
+                .flat_map(|chapter| chapter.verses)
+                .map(|verse| verse.text)
+        }).collect()
+    }
+
+    #[test]
+    fn test_word_count() {
+        let verses = load_verses();
+        let mut expected = HashMap::<String, u64>::new();
+        for word in verses.iter().flat_map(|verse| verse.split(SEPARATORS)).filter(|word| !word.is_empty()) {
+            *expected.entry(word.to_uppercase()).or_default() += 1;
+        }
+
+        let pipeline = Pipeline::source("verses", verses.clone())
+            .buffer(256)
+            .flat_map("split", 4, |verse : String| {
+                Ok::<_, String>(verse.split(SEPARATORS).map(str::to_string).collect::<Vec<String>>())
+            })
+            .buffer(1024)
+            .map("uppercase", 4, |word : String| Ok(word.to_uppercase()))
+            .filter("non-empty", 2, |word : &String| Ok(!word.is_empty()))
+            .batch("batch", 500)
+            .buffer(16);
+        let monitor = pipeline.monitor();
+        let output = pipeline.sink("count", HashMap::<String, u64>::new(), |counts, batch| {
+            for word in batch {
+                *counts.entry(word).or_default() += 1;
+            }
+            Ok(())
+        }).unwrap();
+
+        assert_eq!(output.value, expected);
+        assert_eq!(output.value.get("GOD").copied(), expected.get("GOD").copied());
+        let words = expected.values().sum::<u64>();
+        let stages = output.stages;
+        assert_eq!(stages, monitor.stats());
+        assert_eq!(stages.iter().map(|stage| stage.name.as_str()).collect::<Vec<_>>(),
+            ["verses", "split", "uppercase", "non-empty", "batch", "count"]);
+        assert_eq!(stages[0].sent, verses.len() as u64);
+        assert_eq!(stages[1].received, verses.len() as u64);
+        assert_eq!(stages[3].sent, words);
+        assert_eq!(stages[4].sent, words.div_ceil(500));
+        assert_eq!(stages[5].received, words.div_ceil(500));
+        for stage in stages.iter() {
+            assert!(stage.max_queued <= stage.buffer, "{stage}");
+        }
+    }
+
+    #[test]
+    fn test_error_cancels() {
+        let result = Pipeline::source("numbers", 0..u64::MAX)
+            .buffer(8)
+            .map("check", 2, |n| if n == 1000 { Err(format!("bad number {n}")) } else { Ok(n) })
+            .map("double", 2, |n| Ok(n * 2))
+            .sink("total", 0, |total, n| { *total += n; Ok(()) });
+        assert_eq!(result.unwrap_err(), PipelineError::Failed { stage : "check".to_string(), error : "bad number 1000".to_string() });
+
+        let result = Pipeline::source("numbers", 0..100)
+            .sink("total", 0, |total, n : u64| if n < 50 { *total += n; Ok(()) } else { Err("full") });
+        assert_eq!(result.unwrap_err(), PipelineError::Failed { stage : "total".to_string(), error : "full" });
+    }
+
+    #[test]
+    fn test_panic() {
+        let result = Pipeline::<u32, String>::source("numbers", 0..1000)
+            .filter("odd", 3, |n| if *n == 500 { panic!("Stage panic (expected in test)") } else { Ok(n % 2 == 1) })
+            .sink("count", 0, |count, _| { *count += 1; Ok(()) });
+        assert_eq!(result.unwrap_err(), PipelineError::Panicked { stage : "odd".to_string() });
+    }
+
+    #[test]
+    fn test_back_pressure() {
+        // A slow sink keeps the earlier stages from running ahead
+        let pipeline = Pipeline::<u32, ()>::source("numbers", 0..200)
+            .buffer(4)
+            .map("identity", 1, Ok);
+        let monitor = pipeline.monitor();
+        let watcher = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            monitor.stats()
+        });
+        let output = pipeline.sink("slow", Vec::new(), |values, n| {
+            thread::sleep(Duration::from_millis(1));
+            values.push(n);
+            Ok(())
+        }).unwrap();
+
+        assert_eq!(output.value, (0..200).collect::<Vec<u32>>());
+        let running = watcher.join().unwrap();
+        // At most 2 full buffers and the item each thread is holding
+        assert!(running[0].sent < running[2].received + 2 * 4 + 3, "{running:?}");
+        assert!(output.stages.iter().all(|stage| stage.max_queued <= 4));
+    }
+}
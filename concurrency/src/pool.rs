@@ -0,0 +1,695 @@
+use std::any::Any;
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use crate::cancel::{CancellationToken, Interrupted};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+// How long a worker that is waiting for a job (join or scope) sleeps before
+// looking for other jobs to run
+const HELP_INTERVAL : Duration = Duration::from_millis(1);
+
+thread_local! {
+    // (pool id, deque index) when the current thread is a pool worker
+    static WORKER : Cell<Option<(usize, usize)>> = const { Cell::new(None) };
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    pub workers : usize,
+    pub queued : usize,
+    pub completed : u64,
+    pub stolen : u64,
+    pub panicked : u64,
+    pub cancelled : u64,
+    pub timed_out : u64
+}
+
+/* Result of a job started with ThreadPool::spawn_with_token (or the other
+ * cancellable spawns).  A job whose token was cancelled or timed out before
+ * a worker got to it is not run at all.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome<T> {
+    Completed(T),
+    Cancelled,
+    TimedOut
+}
+
+// Payload used to unwind a job whose panic was already counted and given to
+// its JobHandle
+struct JobPanicked;
+
+struct Shared {
+    // Jobs submitted from outside the pool
+    injector : Mutex<VecDeque<Job>>,
+    // One deque per worker.  The owner pushes and pops at the back, other
+    // workers steal from the front.
+    deques : Vec<Mutex<VecDeque<Job>>>,
+    queued : AtomicUsize,
+    sleeping : AtomicUsize,
+    closing : Mutex<bool>,
+    wake : Condvar,
+    completed : AtomicU64,
+    stolen : AtomicU64,
+    panicked : AtomicU64,
+    cancelled : AtomicU64,
+    timed_out : AtomicU64
+}
+
+fn lock<T>(mutex : &Mutex<T>) -> MutexGuard<'_, T> {
+    // Jobs never run while holding a lock so a poisoned lock still has
+    // consistent data.
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl Shared {
+    fn id(&self) -> usize {
+        self as *const Shared as usize
+    }
+
+    // Deque index if the current thread is a worker of this pool
+    fn worker(&self) -> Option<usize> {
+        match WORKER.get() {
+            Some((id, index)) if id == self.id() => Some(index),
+            _ => None
+        }
+    }
+
+    fn push(&self, job : Job) {
+        // Counted before it is visible so a worker never sees more jobs than queued
+        self.queued.fetch_add(1, Ordering::SeqCst);
+        match self.worker() {
+            Some(index) => lock(&self.deques[index]).push_back(job),
+            None => lock(&self.injector).push_back(job)
+        }
+        // A worker going to sleep checks queued after it counts itself as
+        // sleeping so it either sees this job or is woken up
+        if self.sleeping.load(Ordering::SeqCst) > 0 {
+            let _closing = lock(&self.closing);
+            self.wake.notify_one();
+        }
+    }
+
+    // Own deque (newest first), then the injector (oldest first), then
+    // steal the oldest job from the other workers
+    fn find_job(&self) -> Option<Job> {
+        let local = self.worker();
+        let mut job = local.and_then(|index| lock(&self.deques[index]).pop_back());
+        if job.is_none() {
+            job = lock(&self.injector).pop_front();
+        }
+        if job.is_none() {
+            let start = local.map(|index| index + 1).unwrap_or(0);
+            let count = self.deques.len();
+            for victim in (0..count).map(|offset| (start + offset) % count) {
+                if Some(victim) == local {
+                    continue;
+                }
+                job = lock(&self.deques[victim]).pop_front();
+                if job.is_some() {
+                    self.stolen.fetch_add(1, Ordering::SeqCst);
+                    break;
+                }
+            }
+        }
+        if job.is_some() {
+            self.queued.fetch_sub(1, Ordering::SeqCst);
+        }
+        job
+    }
+
+    fn run(&self, job : Job) {
+        match panic::catch_unwind(AssertUnwindSafe(job)) {
+            Ok(()) => self.completed.fetch_add(1, Ordering::SeqCst),
+            Err(payload) if payload.is::<JobPanicked>() => 0,
+            Err(_) => self.panicked.fetch_add(1, Ordering::SeqCst)
+        };
+    }
+
+    fn work(&self, index : usize) {
+        WORKER.set(Some((self.id(), index)));
+        loop {
+            if let Some(job) = self.find_job() {
+                self.run(job);
+                continue;
+            }
+            let closing = lock(&self.closing);
+            self.sleeping.fetch_add(1, Ordering::SeqCst);
+            if self.queued.load(Ordering::SeqCst) == 0 && !*closing {
+                drop(self.wake.wait(closing).unwrap_or_else(PoisonError::into_inner));
+            }
+            else if self.queued.load(Ordering::SeqCst) == 0 {
+                self.sleeping.fetch_sub(1, Ordering::SeqCst);
+                break;
+            }
+            // A job was pushed (or is being pushed), look again
+            self.sleeping.fetch_sub(1, Ordering::SeqCst);
+        }
+        WORKER.set(None);
+    }
+
+    // Block until done is true.  A worker of this pool runs other jobs while
+    // it waits so jobs that wait on other jobs can't deadlock the pool.
+    fn wait_until<S>(&self, state : &Mutex<S>, changed : &Condvar, done : impl Fn(&S) -> bool) {
+        let helping = self.worker().is_some();
+        loop {
+            let guard = lock(state);
+            if done(&guard) {
+                return;
+            }
+            if !helping {
+                drop(changed.wait(guard).unwrap_or_else(PoisonError::into_inner));
+                continue;
+            }
+            drop(guard);
+            match self.find_job() {
+                Some(job) => self.run(job),
+                None => {
+                    let guard = lock(state);
+                    if !done(&guard) {
+                        drop(changed.wait_timeout(guard, HELP_INTERVAL).unwrap_or_else(PoisonError::into_inner));
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct Slot<T> {
+    result : Mutex<Option<thread::Result<T>>>,
+    done : Condvar
+}
+
+/* Result of a job submitted with ThreadPool::spawn or Scope::spawn.  Like
+ * std::thread::JoinHandle, join returns Err with the panic payload if the job
+ * panicked.
+ */
+pub struct JobHandle<T> {
+    slot : Arc<Slot<T>>,
+    shared : Arc<Shared>,
+    scope : Option<Arc<ScopeState>>
+}
+
+impl<T> JobHandle<T> {
+    pub fn is_finished(&self) -> bool {
+        lock(&self.slot.result).is_some()
+    }
+
+    pub fn join(self) -> thread::Result<T> {
+        self.shared.wait_until(&self.slot.result, &self.slot.done, Option::is_some);
+        let result = lock(&self.slot.result).take().expect("Job result missing");
+        if let (Err(_), Some(scope)) = (&result, &self.scope) {
+            // The panic was handled by the caller so the scope won't repeat it
+            scope.panics.fetch_sub(1, Ordering::SeqCst);
+        }
+        result
+    }
+}
+
+#[derive(Default)]
+struct ScopeState {
+    pending : Mutex<usize>,
+    finished : Condvar,
+    panics : AtomicUsize
+}
+
+impl ScopeState {
+    fn finish(&self) {
+        let mut pending = lock(&self.pending);
+        *pending -= 1;
+        if *pending == 0 {
+            self.finished.notify_all();
+        }
+    }
+}
+
+/* Jobs spawned in a scope can borrow from the stack of the caller of
+ * ThreadPool::scope because the scope does not return until every one of
+ * them has finished.
+ */
+pub struct Scope<'scope, 'env : 'scope> {
+    pool : &'scope ThreadPool,
+    state : Arc<ScopeState>,
+    scope : PhantomData<&'scope mut &'scope ()>,
+    env : PhantomData<&'env mut &'env ()>
+}
+
+impl<'scope> Scope<'scope, '_> {
+    pub fn spawn<F, T>(&'scope self, job : F) -> JobHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'scope,
+        T: Send + 'scope
+    {
+        *lock(&self.state.pending) += 1;
+        let (handle, job) = package(job, &self.pool.shared, Some(Arc::clone(&self.state)));
+        let job : Box<dyn FnOnce() + Send + 'scope> = Box::new(job);
+        // SAFETY: ThreadPool::scope waits for pending to reach zero before it
+        // returns, and the job does not touch anything it borrowed after it
+        // calls ScopeState::finish.
+        let job = unsafe { std::mem::transmute::<Box<dyn FnOnce() + Send + 'scope>, Job>(job) };
+        self.pool.shared.push(job);
+        handle
+    }
+}
+
+// Wrap a job so its result (or panic) is stored for the JobHandle
+fn package<F, T>(job : F, shared : &Arc<Shared>, scope : Option<Arc<ScopeState>>) -> (JobHandle<T>, impl FnOnce() + Send)
+where
+    F: FnOnce() -> T + Send,
+    T: Send
+{
+    let slot = Arc::new(Slot { result : Mutex::new(None), done : Condvar::new() });
+    let handle = JobHandle { slot : Arc::clone(&slot), shared : Arc::clone(shared), scope : scope.clone() };
+    let shared = Arc::clone(shared);
+    let job = move || {
+        let result = panic::catch_unwind(AssertUnwindSafe(job));
+        let panicked = result.is_err();
+        if panicked {
+            // Counted before the handle can see the result
+            shared.panicked.fetch_add(1, Ordering::SeqCst);
+            if let Some(scope) = &scope {
+                scope.panics.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        *lock(&slot.result) = Some(result);
+        slot.done.notify_all();
+        // The result may borrow from the scope so it must be dropped (if the
+        // handle is gone) before the scope is told the job is finished
+        drop(slot);
+        if let Some(scope) = scope {
+            scope.finish();
+        }
+        if panicked {
+            panic::resume_unwind(Box::new(JobPanicked) as Box<dyn Any + Send>);
+        }
+    };
+    (handle, job)
+}
+
+/* A fixed number of worker threads, each with its own deque of jobs.  A job
+ * submitted by a worker goes on that worker's deque, other jobs go on a
+ * shared injector queue.  Idle workers steal from the other deques so one
+ * job that spawns many jobs keeps every worker busy.  Dropping the pool runs
+ * every queued job and then joins the workers (call cancel first to skip
+ * the cancellable ones).
+ */
+pub struct ThreadPool {
+    shared : Arc<Shared>,
+    workers : Vec<JoinHandle<()>>,
+    // Parent of the tokens of the cancellable jobs
+    token : Mutex<CancellationToken>
+}
+
+impl ThreadPool {
+    pub fn new(workers : usize) -> Self {
+        let workers = workers.max(1);
+        let shared = Arc::new(Shared {
+            injector : Mutex::new(VecDeque::new()),
+            deques : (0..workers).map(|_| Mutex::new(VecDeque::new())).collect(),
+            queued : AtomicUsize::new(0),
+            sleeping : AtomicUsize::new(0),
+            closing : Mutex::new(false),
+            wake : Condvar::new(),
+            completed : AtomicU64::new(0),
+            stolen : AtomicU64::new(0),
+            panicked : AtomicU64::new(0),
+            cancelled : AtomicU64::new(0),
+            timed_out : AtomicU64::new(0)
+        });
+        let workers = (0..workers).map(|index| {
+            let shared = Arc::clone(&shared);
+            thread::Builder::new()
+                .name(format!("pool-worker-{index}"))
+                .spawn(move || shared.work(index))
+                .expect("Failed to spawn pool worker")
+        }).collect();
+        ThreadPool { shared, workers, token : Mutex::new(CancellationToken::new()) }
+    }
+
+    // Pool shared by the whole process (one worker per CPU).  It is created
+    // on first use and never dropped.
+    pub fn global() -> &'static ThreadPool {
+        static GLOBAL : OnceLock<ThreadPool> = OnceLock::new();
+        GLOBAL.get_or_init(ThreadPool::default)
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers.len()
+    }
+
+    // Run a job without waiting for it.  A panic is counted in the stats.
+    pub fn execute<F>(&self, job : F)
+    where
+        F: FnOnce() + Send + 'static
+    {
+        self.shared.push(Box::new(job));
+    }
+
+    pub fn spawn<F, T>(&self, job : F) -> JobHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static
+    {
+        let (handle, job) = package(job, &self.shared, None);
+        self.shared.push(Box::new(job));
+        handle
+    }
+
+    /* Run a job that can be stopped with its token.  The job gets the token
+     * to poll (token.check()? between steps) and returns Err to stop early.
+     * The token can be any token, but only children of token() are
+     * cancelled by cancel.
+     */
+    pub fn spawn_with_token<F, T>(&self, token : CancellationToken, job : F) -> JobHandle<Outcome<T>>
+    where
+        F: FnOnce(&CancellationToken) -> Result<T, Interrupted> + Send + 'static,
+        T: Send + 'static
+    {
+        let shared = Arc::clone(&self.shared);
+        self.spawn(move || {
+            let result = token.check().and_then(|()| job(&token));
+            match result {
+                Ok(value) => Outcome::Completed(value),
+                Err(Interrupted::Cancelled) => {
+                    shared.cancelled.fetch_add(1, Ordering::SeqCst);
+                    Outcome::Cancelled
+                }
+                Err(Interrupted::TimedOut) => {
+                    shared.timed_out.fetch_add(1, Ordering::SeqCst);
+                    Outcome::TimedOut
+                }
+            }
+        })
+    }
+
+    // Job that is stopped by cancel
+    pub fn spawn_cancellable<F, T>(&self, job : F) -> JobHandle<Outcome<T>>
+    where
+        F: FnOnce(&CancellationToken) -> Result<T, Interrupted> + Send + 'static,
+        T: Send + 'static
+    {
+        self.spawn_with_token(self.token().child(), job)
+    }
+
+    // Job that is stopped by cancel or when the deadline passes
+    pub fn spawn_with_deadline<F, T>(&self, deadline : Instant, job : F) -> JobHandle<Outcome<T>>
+    where
+        F: FnOnce(&CancellationToken) -> Result<T, Interrupted> + Send + 'static,
+        T: Send + 'static
+    {
+        self.spawn_with_token(self.token().child_with_deadline(deadline), job)
+    }
+
+    // Parent token of the cancellable jobs.  Make children of it for jobs
+    // that should also stop when the pool is cancelled.
+    pub fn token(&self) -> CancellationToken {
+        lock(&self.token).clone()
+    }
+
+    /* Cancel every cancellable job (queued or running).  Queued ones finish
+     * as Cancelled without running.  Jobs spawned after this are not
+     * affected.
+     */
+    pub fn cancel(&self) {
+        let token = std::mem::take(&mut *lock(&self.token));
+        token.cancel();
+    }
+
+    /* Run body with a Scope for spawning jobs that borrow local data.  Waits
+     * for every job spawned in the scope.  Panics if body panicked or if a
+     * job panicked and its handle was not joined.
+     */
+    pub fn scope<'env, F, R>(&self, body : F) -> R
+    where
+        F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> R
+    {
+        let scope = Scope {
+            pool : self,
+            state : Arc::new(ScopeState::default()),
+            scope : PhantomData,
+            env : PhantomData
+        };
+        let result = panic::catch_unwind(AssertUnwindSafe(|| body(&scope)));
+        self.shared.wait_until(&scope.state.pending, &scope.state.finished, |pending| *pending == 0);
+        match result {
+            Err(payload) => panic::resume_unwind(payload),
+            Ok(_) if scope.state.panics.load(Ordering::SeqCst) > 0 => panic!("A scoped job panicked"),
+            Ok(result) => result
+        }
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            workers : self.workers.len(),
+            queued : self.shared.queued.load(Ordering::SeqCst),
+            completed : self.shared.completed.load(Ordering::SeqCst),
+            stolen : self.shared.stolen.load(Ordering::SeqCst),
+            panicked : self.shared.panicked.load(Ordering::SeqCst),
+            cancelled : self.shared.cancelled.load(Ordering::SeqCst),
+            timed_out : self.shared.timed_out.load(Ordering::SeqCst)
+        }
+    }
+}
+
+impl Default for ThreadPool {
+    // One worker per CPU
+    fn default() -> Self {
+        ThreadPool::new(thread::available_parallelism().map(|count| count.get()).unwrap_or(1))
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        *lock(&self.shared.closing) = true;
+        self.shared.wake.notify_all();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factory::SharedData;
+
+    #[test]
+    fn test_spawn_join() {
+        let pool = ThreadPool::new(4);
+        let handles = (0..100_u64).map(|n| pool.spawn(move || n * n)).collect::<Vec<_>>();
+        let total = handles.into_iter().map(|handle| handle.join().unwrap()).sum::<u64>();
+        assert_eq!(total, (0..100_u64).map(|n| n * n).sum::<u64>());
+    }
+
+    #[test]
+    fn test_panic() {
+        // One worker so the last job only runs after the panicking one
+        let pool = ThreadPool::new(1);
+        let handle = pool.spawn(|| -> i32 { panic!("Job panic (expected in test)") });
+        let payload = handle.join().unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"Job panic (expected in test)"));
+        assert_eq!(pool.stats().panicked, 1);
+        pool.execute(|| panic!("Job panic (expected in test)"));
+
+        // The workers survive
+        assert_eq!(pool.spawn(|| 5).join().unwrap(), 5);
+        assert_eq!(pool.stats().panicked, 2);
+    }
+
+    #[test]
+    fn test_scope_borrows() {
+        let pool = ThreadPool::new(4);
+        let mut data = (0..1000).collect::<Vec<i32>>();
+        let offset = 10;
+        let sum = pool.scope(|scope| {
+            for chunk in data.chunks_mut(100) {
+                scope.spawn(|| chunk.iter_mut().for_each(|value| *value += offset));
+            }
+            let handle = scope.spawn(|| offset * 2);
+            handle.join().unwrap()
+        });
+        assert_eq!(sum, 20);
+        assert_eq!(data, (10..1010).collect::<Vec<i32>>());
+    }
+
+    #[test]
+    #[should_panic(expected = "A scoped job panicked")]
+    fn test_scope_panic() {
+        let pool = ThreadPool::new(2);
+        pool.scope(|scope| {
+            scope.spawn(|| panic!("Job panic (expected in test)"));
+        });
+    }
+
+    fn sum(pool : &ThreadPool, values : &[u64]) -> u64 {
+        if values.len() <= 16 {
+            return values.iter().sum();
+        }
+        let (left, right) = values.split_at(values.len() / 2);
+        pool.scope(|scope| {
+            let left = scope.spawn(|| sum(pool, left));
+            let right = sum(pool, right);
+            left.join().unwrap() + right
+        })
+    }
+
+    #[test]
+    fn test_nested_scopes() {
+        // Each level waits on the level below from inside a worker
+        let pool = ThreadPool::new(2);
+        let values = (0..10_000).collect::<Vec<u64>>();
+        let total = pool.scope(|scope| scope.spawn(|| sum(&pool, &values)).join().unwrap());
+        assert_eq!(total, values.iter().sum::<u64>());
+    }
+
+    #[test]
+    fn test_work_stealing() {
+        // Every job lands on the deque of the worker running the outer job
+        let pool = ThreadPool::new(4);
+        let pool_ref = &pool;
+        pool.scope(|scope| {
+            scope.spawn(|| pool_ref.scope(|inner| {
+                for _ in 0..64 {
+                    inner.spawn(|| thread::sleep(Duration::from_millis(2)));
+                }
+            }));
+        });
+        assert!(pool.stats().stolen > 0);
+    }
+
+    #[test]
+    fn test_drop_runs_queued_jobs() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let pool = ThreadPool::new(3);
+        for _ in 0..500 {
+            let counter = Arc::clone(&counter);
+            pool.execute(move || { counter.fetch_add(1, Ordering::SeqCst); });
+        }
+        drop(pool);
+        assert_eq!(counter.load(Ordering::SeqCst), 500);
+    }
+
+    #[test]
+    fn test_cancel_pending_jobs() {
+        // 5000 jobs of 10ms on 4 workers would take more than 10 seconds
+        let pool = ThreadPool::new(4);
+        let handles = (0..5000).map(|n| pool.spawn_cancellable(move |token| {
+            token.sleep(Duration::from_millis(10))?;
+            Ok(n)
+        })).collect::<Vec<_>>();
+        thread::sleep(Duration::from_millis(30));
+        pool.cancel();
+
+        let start = Instant::now();
+        let outcomes = handles.into_iter().map(|handle| handle.join().unwrap()).collect::<Vec<_>>();
+        assert!(start.elapsed() < Duration::from_secs(5), "{:?}", start.elapsed());
+        let completed = outcomes.iter().filter(|outcome| matches!(outcome, Outcome::Completed(_))).count();
+        let cancelled = outcomes.iter().filter(|outcome| **outcome == Outcome::Cancelled).count();
+        assert_eq!(completed + cancelled, 5000);
+        assert!(cancelled > 4000, "{completed} completed");
+        assert_eq!(pool.stats().cancelled, cancelled as u64);
+
+        // The pool still runs jobs spawned after the cancel
+        assert_eq!(pool.spawn_cancellable(|_| Ok(7)).join().unwrap(), Outcome::Completed(7));
+    }
+
+    #[test]
+    fn test_deadline_and_token() {
+        let pool = ThreadPool::new(2);
+        let slow = pool.spawn_with_deadline(Instant::now() + Duration::from_millis(20), |token| {
+            loop {
+                token.check()?;
+                thread::sleep(Duration::from_millis(1));
+            }
+        });
+        assert_eq!(slow.join().unwrap(), Outcome::<()>::TimedOut);
+
+        // A token from another tree is only cancelled by its owner
+        let request = CancellationToken::new();
+        let waiting = pool.spawn_with_token(request.child(), |token| token.sleep(Duration::from_secs(60)));
+        pool.cancel();
+        thread::sleep(Duration::from_millis(10));
+        assert!(!waiting.is_finished());
+        request.cancel();
+        assert_eq!(waiting.join().unwrap(), Outcome::Cancelled);
+
+        // A job that is already past its deadline is not run
+        let stale = pool.spawn_with_deadline(Instant::now(), |_| -> Result<(), Interrupted> { panic!("Should not run") });
+        assert_eq!(stale.join().unwrap(), Outcome::TimedOut);
+        let stats = pool.stats();
+        assert_eq!((stats.cancelled, stats.timed_out, stats.panicked), (1, 2, 0));
+    }
+
+    // The factory design: every worker pops from one Arc<Mutex<Vec>>
+    fn mutex_vector(workers : usize, jobs : Vec<Job>) {
+        let jobs : SharedData<Job> = Arc::new(Mutex::new(jobs));
+        let threads = (0..workers).map(|_| {
+            let jobs = Arc::clone(&jobs);
+            thread::spawn(move || loop {
+                let job = jobs.lock().unwrap().pop();
+                match job {
+                    Some(job) => job(),
+                    None => break
+                }
+            })
+        }).collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+
+    /* Benchmark the ThreadPool against the mutex vector.  Run with:
+     *
+     *     cargo test --release -- --ignored --nocapture
+     */
+    #[test]
+    #[ignore]
+    fn bench_pool_vs_mutex_vector() {
+        const JOBS : usize = 200_000;
+        let workers = thread::available_parallelism().map(|count| count.get()).unwrap_or(4);
+        for work in [0_u64, 1_000, 100_000] {
+            let job = move |counter : Arc<AtomicUsize>, index : usize| -> Job { Box::new(move || {
+                // Every 64th job is 64 times longer so the load is uneven
+                let work = if index.is_multiple_of(64) { work * 64 } else { work };
+                let mut total = 0_u64;
+                for i in 0..work {
+                    total = total.wrapping_add(std::hint::black_box(i * i));
+                }
+                std::hint::black_box(total);
+                counter.fetch_add(1, Ordering::SeqCst);
+            })};
+            let jobs = if work >= 100_000 { JOBS / 100 } else { JOBS };
+
+            let counter = Arc::new(AtomicUsize::new(0));
+            let start = Instant::now();
+            mutex_vector(workers, (0..jobs).map(|index| job(Arc::clone(&counter), index)).collect());
+            let vector_time = start.elapsed();
+            assert_eq!(counter.load(Ordering::SeqCst), jobs);
+
+            let counter = Arc::new(AtomicUsize::new(0));
+            let start = Instant::now();
+            let pool = ThreadPool::new(workers);
+            pool.scope(|scope| {
+                // Submitted from a worker so the jobs start on one deque and are stolen
+                scope.spawn(|| pool.scope(|inner| {
+                    for index in 0..jobs {
+                        let job = job(Arc::clone(&counter), index);
+                        inner.spawn(job);
+                    }
+                }));
+            });
+            let stats = pool.stats();
+            drop(pool);
+            let pool_time = start.elapsed();
+            assert_eq!(counter.load(Ordering::SeqCst), jobs);
+            println!("{jobs} jobs (work={work}, {workers} workers): mutex vector {vector_time:?}  ThreadPool {pool_time:?}  {stats:?}");
+        }
+    }
+}
@@ -1,43 +1,17 @@
-use std::sync::{Arc, Mutex};
+use crate::parallel::par_map;
+use crate::pool::ThreadPool;
 
-pub type SharedData<T> = Arc<Mutex<Vec<T>>>;
+// The same type as before, kept for existing callers
+pub use crate::factory::SharedData;
 
+// Map every element on the shared pool.  Kept for existing callers, see
+// parallel::par_map.
 pub fn thread_map<F, T>(lambda : F, data : Vec<T>) -> Vec<T>
 where 
-    F: Fn(T) -> T + Clone + Send + 'static,
-    T: Clone + Send + 'static,
+    F: Fn(T) -> T + Sync,
+    T: Send,
 {
-    let shared_data = Arc::new(Mutex::new(data.clone()));
-    let mut threads = Vec::new();
-    for index in 0..data.len() {
-        let shared_data = shared_data.clone();
-        let lambda = lambda.clone();
-        let handle = std::thread::spawn(
-            move || one_map(index, lambda, &shared_data));
-        threads.push(handle);
-    }
-    for thread in threads {
-        thread.join().unwrap();
-    } 
-    // This works because all of my references to shared data are out of scope now
-    Arc::into_inner(shared_data).unwrap().into_inner().unwrap()
-}
-
-fn one_map<F, T>(index : usize, lambda : F, data : &SharedData<T>)
-where 
-    F: Fn(T) -> T + Clone + Send + 'static,
-    T: Clone + Send + 'static,
-{
-    let input = {
-        let data = data.lock().unwrap();
-        match data.get(index) {
-            Some(input) => input.clone(),
-            None => return
-        }
-    };
-    let result = lambda(input);
-    let mut data = data.lock().unwrap();
-    data[index] = result;
+    par_map(ThreadPool::global(), data, lambda)
 }
 
 #[cfg(test)]
@@ -58,4 +32,11 @@ mod tests {
         data = thread_map(|x| x.to_uppercase(), data);
         assert_eq!(data, vec!["CAT".to_string(), "DOG".to_string(), "PIG".to_string(), "COW".to_string()])
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_large() {
+        let data = (0..100_000).collect::<Vec<u64>>();
+        let data = thread_map(|x| x + 1, data);
+        assert_eq!(data, (1..=100_000).collect::<Vec<u64>>());
+    }
+}
@@ -0,0 +1,111 @@
+use crate::graph::{GraphError, WeightedGraph, INF};
+use crate::graph_heap::GraphHeap;
+use crate::path_result::{PathResult, Route};
+
+/* A* search from start to goal.  Like Dijkstra but the queue is ordered by
+ * the distance so far plus heuristic(vertex), an estimate of the distance
+ * left to the goal, so the search heads towards the goal instead of growing
+ * in every direction.  The heuristic must never overestimate (with 0 this is
+ * Dijkstra that stops at the goal) and the weights must not be negative.
+ * If a better path to a vertex that was already expanded is found (possible
+ * when the heuristic is not consistent) the vertex goes back on the queue.
+ * Err is returned if start or goal is invalid.
+ */
+pub fn astar<G, H>(g : &G, start : usize, goal : usize, heuristic : H) -> Result<Route, GraphError>
+where
+    G: WeightedGraph,
+    H: Fn(usize) -> f64
+{
+    if start >= g.size() || goal >= g.size() {
+        return Err(GraphError::InvalidVertex);
+    }
+    let mut distance = vec![INF; g.size()];
+    let mut pred = vec![None; g.size()];
+    let mut queued = vec![false; g.size()];
+    let mut expanded = 0;
+    distance[start] = 0.0;
+
+    let mut queue = GraphHeap::default();
+    queue.enqueue(start, heuristic(start));
+    queued[start] = true;
+
+    while let Some(vertex) = queue.dequeue() {
+        queued[vertex] = false;
+        expanded += 1;
+        if vertex == goal {
+            break;
+        }
+        for (dest_id, weight) in g.weighted_edges(vertex)? {
+            if distance[vertex] + weight < distance[dest_id] {
+                distance[dest_id] = distance[vertex] + weight;
+                pred[dest_id] = Some(vertex);
+                let estimate = distance[dest_id] + heuristic(dest_id);
+                if queued[dest_id] {
+                    let _ = queue.decrease_distance(dest_id, estimate);
+                }
+                else {
+                    queue.enqueue(dest_id, estimate);
+                    queued[dest_id] = true;
+                }
+            }
+        }
+    }
+    let path = PathResult::new(start, distance, pred);
+    Ok(Route { distance : path.distance[goal], path : path.path_to(goal), expanded })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dijkstra_shortest_path;
+    use crate::graph::Graph;
+    use crate::search_testing::{check_random_graphs, grid};
+
+    #[test]
+    fn test_astar() {
+        let mut graph = Graph::new(5);
+        let _ = graph.add_edge(0, 1, 6.0);
+        let _ = graph.add_edge(0, 3, 4.0);
+        let _ = graph.add_edge(1, 2, 3.0);
+        let _ = graph.add_edge(1, 3, 2.0);
+        let _ = graph.add_edge(2, 4, 4.0);
+        let _ = graph.add_edge(3, 1, 1.0);
+        let _ = graph.add_edge(3, 2, 9.0);
+        let _ = graph.add_edge(3, 4, 3.0);
+        let _ = graph.add_edge(4, 2, 5.0);
+        let _ = graph.add_edge(4, 0, 7.0);
+
+        let result = astar(&graph, 1, 0, |_| 0.0);
+        assert!(result.is_ok());
+        let route = result.unwrap();
+        assert_eq!(route.distance, 12.0);
+        assert_eq!(route.path, Some(vec![1, 3, 4, 0]));
+
+        let mut graph = Graph::new(3);
+        let _ = graph.add_edge(0, 1, 1.0);
+        assert_eq!(astar(&graph, 0, 2, |_| 0.0), Ok(Route { distance : INF, path : None, expanded : 2 }));
+        assert_eq!(astar(&graph, 0, 3, |_| 0.0), Err(GraphError::InvalidVertex));
+    }
+
+    #[test]
+    fn test_same_as_dijkstra() {
+        check_random_graphs(false, |graph, start, goal| astar(graph, start, goal, |_| 0.0).unwrap());
+    }
+
+    #[test]
+    fn test_grid() {
+        // Every weight is at least 1 so the Manhattan distance never overestimates
+        let (width, height) = (40, 40);
+        let graph = grid(width, height);
+        let (start, goal) = (20 * width + 2, 20 * width + 37);
+        let manhattan = |id : usize| ((id % width).abs_diff(goal % width) + (id / width).abs_diff(goal / width)) as f64;
+
+        let expected = dijkstra_shortest_path::shortest_path(&graph, start).unwrap();
+        let guided = astar(&graph, start, goal, manhattan).unwrap();
+        let unguided = astar(&graph, start, goal, |_| 0.0).unwrap();
+        assert_eq!(guided.distance, expected.distance[goal]);
+        assert_eq!(unguided.distance, expected.distance[goal]);
+        assert!(guided.expanded < unguided.expanded, "{} >= {}", guided.expanded, unguided.expanded);
+        assert!(unguided.expanded < graph.size());
+    }
+}
@@ -1,6 +1,7 @@
-use crate::graph::{Graph, INF, GraphError};
+use crate::graph::{WeightedGraph, INF, GraphError};
+use crate::path_result::PathResult;
 
-pub fn shortest_path(g : &Graph, start : usize) -> Result<(Vec<f64>, Vec<Option<usize>>), GraphError> {
+pub fn shortest_path<G : WeightedGraph>(g : &G, start : usize) -> Result<PathResult, GraphError> {
     if start >= g.size() {
         return Err(GraphError::InvalidVertex);
     }
@@ -11,14 +12,14 @@ pub fn shortest_path(g : &Graph, start : usize) -> Result<(Vec<f64>, Vec<Option<
     for i in 0..g.size() {
         let mut changed = false;
         for vertex in 0..g.size() {
-            for edge in g.edges(vertex).unwrap() {
-                if distance[vertex] + edge.weight < distance[edge.dest_id] {
+            for (dest_id, weight) in g.weighted_edges(vertex).unwrap() {
+                if distance[vertex] + weight < distance[dest_id] {
                     if i == g.size()-1 {
                         return Err(GraphError::NegativeCycle)
                     }
                     changed = true;
-                    distance[edge.dest_id] = distance[vertex] + edge.weight;
-                    pred[edge.dest_id] = Some(vertex);
+                    distance[dest_id] = distance[vertex] + weight;
+                    pred[dest_id] = Some(vertex);
                 }
             }
         }
@@ -26,13 +27,14 @@ pub fn shortest_path(g : &Graph, start : usize) -> Result<(Vec<f64>, Vec<Option<
             break;
         }
     }
-    Ok((distance, pred))
+    Ok(PathResult::new(start, distance, pred))
 
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::graph::Graph;
 
     #[test]
     fn test1_no_negative_cycle() {
@@ -50,7 +52,7 @@ mod tests {
 
         let result = shortest_path(&graph, 0);
         assert!(result.is_ok());
-        let (dist,pred) = result.unwrap();
+        let PathResult { distance : dist, pred, .. } = result.unwrap();
         assert_eq!(dist, vec![0.0, 2.0, 4.0, 7.0, -2.0]);
         assert_eq!(pred, vec![None, Some(2), Some(3), Some(0), Some(1)]);
     }
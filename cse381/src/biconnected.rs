@@ -0,0 +1,139 @@
+use std::vec::IntoIter;
+use crate::graph::{Adjacency, GraphError};
+
+struct Cuts {
+    articulation_points : Vec<usize>,
+    bridges : Vec<(usize, usize)>
+}
+
+struct Call {
+    vertex : usize,
+    parent : Option<usize>,
+    // The first edge back to the parent is the tree edge, any others are not
+    skipped_parent : bool,
+    edges : IntoIter<usize>
+}
+
+/* Depth first search of an undirected graph that finds the low point of
+ * every vertex: the earliest discovery time it can reach through its
+ * descendants and one back edge.  A child that can't get above its parent
+ * makes the parent an articulation point, and one that can't even get back
+ * to the parent makes the edge between them a bridge.  The root is an
+ * articulation point if it has more than one child.
+ */
+fn low_points<G : Adjacency>(g : &G) -> Result<Cuts, GraphError> {
+    if g.is_directed() {
+        return Err(GraphError::NotUndirected);
+    }
+    let neighbors = |vertex : usize| -> IntoIter<usize> {
+        g.neighbors(vertex).unwrap().collect::<Vec<_>>().into_iter()
+    };
+    let mut discovered = vec![None; g.size()];
+    let mut low = vec![0; g.size()];
+    let mut articulation = vec![false; g.size()];
+    let mut bridges = Vec::<(usize, usize)>::new();
+    let mut time = 0;
+
+    for root in 0..g.size() {
+        if discovered[root].is_some() {
+            continue;
+        }
+        discovered[root] = Some(time);
+        low[root] = time;
+        time += 1;
+        let mut root_children = 0;
+        let mut calls = vec![Call { vertex : root, parent : None, skipped_parent : false, edges : neighbors(root) }];
+
+        while let Some(call) = calls.last_mut() {
+            let vertex = call.vertex;
+            if let Some(next) = call.edges.next() {
+                if Some(next) == call.parent && !call.skipped_parent {
+                    call.skipped_parent = true;
+                    continue;
+                }
+                match discovered[next] {
+                    Some(next_time) => low[vertex] = low[vertex].min(next_time),
+                    None => {
+                        discovered[next] = Some(time);
+                        low[next] = time;
+                        time += 1;
+                        calls.push(Call { vertex : next, parent : Some(vertex), skipped_parent : false, edges : neighbors(next) });
+                    }
+                }
+                continue;
+            }
+
+            // Finished with vertex, so update its parent
+            calls.pop();
+            if let Some(parent) = calls.last() {
+                let parent_time = discovered[parent.vertex].unwrap();
+                low[parent.vertex] = low[parent.vertex].min(low[vertex]);
+                if low[vertex] > parent_time {
+                    bridges.push((parent.vertex.min(vertex), parent.vertex.max(vertex)));
+                }
+                if parent.parent.is_none() {
+                    root_children += 1;
+                }
+                else if low[vertex] >= parent_time {
+                    articulation[parent.vertex] = true;
+                }
+            }
+        }
+        articulation[root] = root_children > 1;
+    }
+
+    let articulation_points = (0..g.size()).filter(|vertex| articulation[*vertex]).collect();
+    bridges.sort_unstable();
+    Ok(Cuts { articulation_points, bridges })
+}
+
+/* Return the vertices (sorted) whose removal disconnects part of an
+ * undirected graph.  Err is returned if the graph is directed.
+ */
+pub fn articulation_points<G : Adjacency>(g : &G) -> Result<Vec<usize>, GraphError> {
+    Ok(low_points(g)?.articulation_points)
+}
+
+/* Return the edges (sorted, smaller id first) whose removal disconnects part
+ * of an undirected graph.  Err is returned if the graph is directed.
+ */
+pub fn bridges<G : Adjacency>(g : &G) -> Result<Vec<(usize, usize)>, GraphError> {
+    Ok(low_points(g)?.bridges)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Graph;
+
+    #[test]
+    fn test_articulation_points_and_bridges() {
+        // Two triangles joined through 1-3, with 6 hanging off 1 and 7 on its own
+        let graph = Graph::undirected_from_edges(8, &[(0, 1, 1.0), (1, 2, 1.0), (2, 0, 1.0), (1, 3, 1.0),
+            (3, 4, 1.0), (4, 5, 1.0), (5, 3, 1.0), (1, 6, 1.0)]).unwrap();
+        assert_eq!(articulation_points(&graph), Ok(vec![1, 3]));
+        assert_eq!(bridges(&graph), Ok(vec![(1, 3), (1, 6)]));
+
+        // A path: every inner vertex and every edge
+        let graph = Graph::undirected_from_edges(4, &[(0, 1, 1.0), (1, 2, 1.0), (2, 3, 1.0)]).unwrap();
+        assert_eq!(articulation_points(&graph), Ok(vec![1, 2]));
+        assert_eq!(bridges(&graph), Ok(vec![(0, 1), (1, 2), (2, 3)]));
+
+        // A root with two children
+        let graph = Graph::undirected_from_edges(3, &[(0, 1, 1.0), (0, 2, 1.0)]).unwrap();
+        assert_eq!(articulation_points(&graph), Ok(vec![0]));
+    }
+
+    #[test]
+    fn test_parallel_edges() {
+        // Two edges between 0 and 1 are not a bridge
+        let graph = Graph::undirected_from_edges(3, &[(0, 1, 1.0), (0, 1, 1.0), (1, 2, 1.0)]).unwrap();
+        assert_eq!(bridges(&graph), Ok(vec![(1, 2)]));
+        assert_eq!(articulation_points(&graph), Ok(vec![1]));
+
+        let cycle = Graph::undirected_from_edges(4, &[(0, 1, 1.0), (1, 2, 1.0), (2, 3, 1.0), (3, 0, 1.0)]).unwrap();
+        assert_eq!(articulation_points(&cycle), Ok(vec![]));
+        assert_eq!(bridges(&cycle), Ok(vec![]));
+        assert_eq!(bridges(&Graph::new(2)), Err(GraphError::NotUndirected));
+    }
+}
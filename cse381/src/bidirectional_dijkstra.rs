@@ -0,0 +1,152 @@
+use crate::graph::{Graph, GraphError, INF};
+use crate::graph_heap::GraphHeap;
+use crate::path_result::{PathResult, Route};
+
+// One direction of the search
+struct Side {
+    distance : Vec<f64>,
+    pred : Vec<Option<usize>>,
+    settled : Vec<bool>,
+    queue : GraphHeap
+}
+
+impl Side {
+    fn new(size : usize, source : usize) -> Self {
+        let mut side = Self { distance : vec![INF; size], pred : vec![None; size], settled : vec![false; size], queue : GraphHeap::default() };
+        side.distance[source] = 0.0;
+        side.queue.enqueue(source, 0.0);
+        side
+    }
+
+    fn relax(&mut self, vertex : usize, dest_id : usize, weight : f64) {
+        let distance = self.distance[vertex] + weight;
+        if distance < self.distance[dest_id] {
+            // Vertices are only put on the queue once they are reached
+            if self.distance[dest_id] == INF {
+                self.queue.enqueue(dest_id, distance);
+            }
+            else {
+                let _ = self.queue.decrease_distance(dest_id, distance);
+            }
+            self.distance[dest_id] = distance;
+            self.pred[dest_id] = Some(vertex);
+        }
+    }
+}
+
+/* Dijkstra from start along the edges and from goal along the edges in
+ * reverse (with Graph::in_edges), taking turns.  Every time an edge is
+ * relaxed the best path through its end is checked, and the search stops
+ * once a vertex is settled by both sides: no path can be shorter than the
+ * best one found by then.  Each side only explores about half the distance,
+ * so much less of the graph is expanded than with Dijkstra.  The weights
+ * must not be negative.  Err is returned if start or goal is invalid.
+ */
+pub fn bidirectional_dijkstra<V, E>(g : &Graph<V, E>, start : usize, goal : usize) -> Result<Route, GraphError>
+where
+    E: Copy + Into<f64>
+{
+    if start >= g.size() || goal >= g.size() {
+        return Err(GraphError::InvalidVertex);
+    }
+    let mut forward = Side::new(g.size(), start);
+    let mut backward = Side::new(g.size(), goal);
+    let mut best = if start == goal { 0.0 } else { INF };
+    let mut meet = if start == goal { Some(start) } else { None };
+    let mut expanded = 0;
+    let mut forward_turn = true;
+
+    loop {
+        let (side, other) = if forward_turn { (&mut forward, &backward) } else { (&mut backward, &forward) };
+        // If one side runs out, everything it can reach has been checked
+        let Some(vertex) = side.queue.dequeue() else {
+            break;
+        };
+        expanded += 1;
+        side.settled[vertex] = true;
+        if other.settled[vertex] {
+            break;
+        }
+        let edges = if forward_turn {
+            g.edges(vertex)?.map(|edge| (edge.dest_id, edge.weight.into())).collect::<Vec<(usize, f64)>>()
+        }
+        else {
+            g.in_edges(vertex)?.map(|(src_id, weight)| (src_id, (*weight).into())).collect()
+        };
+        for (next, weight) in edges {
+            side.relax(vertex, next, weight);
+            if side.distance[next] + other.distance[next] < best {
+                best = side.distance[next] + other.distance[next];
+                meet = Some(next);
+            }
+        }
+        forward_turn = !forward_turn;
+    }
+
+    // start .. meet from the forward search, then meet .. goal from the backward one
+    let path = meet.and_then(|meet| {
+        let mut path = PathResult::new(start, forward.distance, forward.pred).path_to(meet)?;
+        let mut rest = PathResult::new(goal, backward.distance, backward.pred).path_to(meet)?;
+        rest.pop();
+        path.extend(rest.into_iter().rev());
+        Some(path)
+    });
+    Ok(Route { distance : best, path, expanded })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dijkstra_shortest_path;
+    use crate::astar_shortest_path::astar;
+    use crate::search_testing::{check_random_graphs, grid};
+
+    #[test]
+    fn test_bidirectional_dijkstra() {
+        let mut graph = Graph::new(5);
+        let _ = graph.add_edge(0, 1, 6.0);
+        let _ = graph.add_edge(0, 3, 4.0);
+        let _ = graph.add_edge(1, 2, 3.0);
+        let _ = graph.add_edge(1, 3, 2.0);
+        let _ = graph.add_edge(2, 4, 4.0);
+        let _ = graph.add_edge(3, 1, 1.0);
+        let _ = graph.add_edge(3, 2, 9.0);
+        let _ = graph.add_edge(3, 4, 3.0);
+        let _ = graph.add_edge(4, 2, 5.0);
+        let _ = graph.add_edge(4, 0, 7.0);
+
+        let result = bidirectional_dijkstra(&graph, 1, 0);
+        assert!(result.is_ok());
+        let route = result.unwrap();
+        assert_eq!(route.distance, 12.0);
+        assert_eq!(route.path, Some(vec![1, 3, 4, 0]));
+
+        let route = bidirectional_dijkstra(&graph, 2, 2).unwrap();
+        assert_eq!((route.distance, route.path), (0.0, Some(vec![2])));
+
+        let mut graph = Graph::new(3);
+        let _ = graph.add_edge(0, 1, 1.0);
+        let route = bidirectional_dijkstra(&graph, 0, 2).unwrap();
+        assert_eq!((route.distance, route.path), (INF, None));
+        assert_eq!(bidirectional_dijkstra(&graph, 3, 0), Err(GraphError::InvalidVertex));
+    }
+
+    #[test]
+    fn test_same_as_dijkstra() {
+        check_random_graphs(true, |graph, start, goal| bidirectional_dijkstra(graph, start, goal).unwrap());
+    }
+
+    #[test]
+    fn test_grid() {
+        let (width, height) = (40, 40);
+        let graph = grid(width, height);
+        let (start, goal) = (20 * width + 2, 20 * width + 37);
+
+        let expected = dijkstra_shortest_path::shortest_path(&graph, start).unwrap();
+        let route = bidirectional_dijkstra(&graph, start, goal).unwrap();
+        // Dijkstra that stops at the goal
+        let unguided = astar(&graph, start, goal, |_| 0.0).unwrap();
+        assert_eq!(route.distance, expected.distance[goal]);
+        assert!(route.expanded < unguided.expanded, "{} >= {}", route.expanded, unguided.expanded);
+    }
+}
@@ -0,0 +1,67 @@
+use crate::graph::{Adjacency, GraphError};
+use crate::graph_search::Bfs;
+
+// The vertices on each side, sorted
+pub type Sides = (Vec<usize>, Vec<usize>);
+
+/* Split the vertices of an undirected graph into two sides so that every
+ * edge goes between the sides.  Each connected component is colored by a
+ * breadth first search (even distances on one side, odd on the other), and
+ * the graph is bipartite if no edge joins two vertices of the same color.
+ * Ok(None) is returned if the graph is not bipartite (it has an odd cycle)
+ * and Err if the graph is directed.
+ */
+pub fn bipartition<G : Adjacency>(g : &G) -> Result<Option<Sides>, GraphError> {
+    if g.is_directed() {
+        return Err(GraphError::NotUndirected);
+    }
+    let mut side = vec![None; g.size()];
+    for start in 0..g.size() {
+        if side[start].is_none() {
+            for visit in Bfs::new(g, start)? {
+                side[visit.vertex] = Some(visit.distance % 2);
+            }
+        }
+    }
+    for vertex in 0..g.size() {
+        if g.neighbors(vertex)?.any(|next| side[next] == side[vertex]) {
+            return Ok(None);
+        }
+    }
+    let (left, right) : Sides = (0..g.size()).partition(|vertex| side[*vertex] == Some(0));
+    Ok(Some((left, right)))
+}
+
+pub fn is_bipartite<G : Adjacency>(g : &G) -> Result<bool, GraphError> {
+    Ok(bipartition(g)?.is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Graph;
+
+    #[test]
+    fn test_bipartite() {
+        // A square and a separate edge
+        let graph = Graph::undirected_from_edges(6, &[(0, 1, 1.0), (1, 2, 1.0), (2, 3, 1.0), (3, 0, 1.0), (4, 5, 1.0)]).unwrap();
+        assert_eq!(bipartition(&graph), Ok(Some((vec![0, 2, 4], vec![1, 3, 5]))));
+        assert_eq!(is_bipartite(&graph), Ok(true));
+
+        // Every tree is bipartite
+        let tree = Graph::undirected_from_edges(5, &[(0, 1, 1.0), (0, 2, 1.0), (2, 3, 1.0), (2, 4, 1.0)]).unwrap();
+        assert_eq!(bipartition(&tree), Ok(Some((vec![0, 3, 4], vec![1, 2]))));
+        assert_eq!(is_bipartite(&Graph::<(), f64>::undirected()), Ok(true));
+    }
+
+    #[test]
+    fn test_not_bipartite() {
+        // A pentagon is an odd cycle
+        let graph = Graph::undirected_from_edges(5, &[(0, 1, 1.0), (1, 2, 1.0), (2, 3, 1.0), (3, 4, 1.0), (4, 0, 1.0)]).unwrap();
+        assert_eq!(bipartition(&graph), Ok(None));
+
+        let graph = Graph::undirected_from_edges(2, &[(0, 1, 1.0), (1, 1, 1.0)]).unwrap();
+        assert_eq!(is_bipartite(&graph), Ok(false));
+        assert_eq!(is_bipartite(&Graph::new(2)), Err(GraphError::NotUndirected));
+    }
+}
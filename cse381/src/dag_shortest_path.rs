@@ -1,59 +1,32 @@
-use crate::graph::{Graph, GraphError, INF};
+use crate::graph::{WeightedGraph, GraphError, INF};
+use crate::path_result::PathResult;
+use crate::topological_sort::topological_sort;
 
-fn sort(g : &Graph) -> Vec<usize> {
-    let mut in_degree = vec![0; g.size()];
-    let mut linear_order = Vec::<usize>::new();
-    let mut stack = Vec::<usize>::new();
-
-    for vertex in 0..g.size() {
-        for edge in g.edges(vertex).unwrap() {
-            in_degree[edge.dest_id] += 1;
-        }
-    }
-
-    for (i, v) in in_degree.iter().enumerate() {
-        if *v == 0 {
-            stack.push(i);
-        }
-    }
-
-    while let Some(vertex) = stack.pop() {
-        linear_order.push(vertex);
-        for edge in g.edges(vertex).unwrap() {
-            in_degree[edge.dest_id] -= 1;
-            if in_degree[edge.dest_id] == 0 {
-                stack.push(edge.dest_id);
-            }
-        }
-    }
-
-    linear_order
-}
-
-pub fn shortest_path(g : &Graph, start : usize) -> Result<(Vec<f64>, Vec<Option<usize>>), GraphError> {
+pub fn shortest_path<G : WeightedGraph>(g : &G, start : usize) -> Result<PathResult, GraphError> {
     if start >= g.size() {
         return Err(GraphError::InvalidVertex);
     }
-    let topo = sort(g);
+    let topo = topological_sort(g)?;
     let mut distance = vec![INF; g.size()];
     let mut pred = vec![None; g.size()];
     distance[start] = 0.0;
     for vertex in topo {
         if distance[vertex] != INF {
-            for edge in g.edges(vertex).unwrap() {
-                if distance[vertex] + edge.weight < distance[edge.dest_id] {
-                    distance[edge.dest_id] = distance[vertex] + edge.weight;
-                    pred[edge.dest_id] = Some(vertex);
+            for (dest_id, weight) in g.weighted_edges(vertex).unwrap() {
+                if distance[vertex] + weight < distance[dest_id] {
+                    distance[dest_id] = distance[vertex] + weight;
+                    pred[dest_id] = Some(vertex);
                 }
             }
         }
     }
-    Ok((distance, pred))
+    Ok(PathResult::new(start, distance, pred))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::graph::Graph;
 
     #[test]
     fn test_dag_shortest_path() {
@@ -69,8 +42,23 @@ mod tests {
 
         let result = shortest_path(&graph, 2);
         assert!(result.is_ok());
-        let (dist,pred) = result.unwrap();
+        let PathResult { distance : dist, pred, .. } = result.unwrap();
         assert_eq!(dist, vec![INF, INF, 0.0, INF, INF, 5.0, 8.0, INF, 15.0]);
         assert_eq!(pred, vec![None, None, None, None, None, Some(2), Some(5), None, Some(6)]);
     }
+
+    #[test]
+    fn test_cycle() {
+        let mut graph = Graph::new(4);
+        let _ = graph.add_edge(0, 1, 1.0);
+        let _ = graph.add_edge(1, 2, 1.0);
+        let _ = graph.add_edge(2, 3, 1.0);
+        let _ = graph.add_edge(3, 1, 1.0);
+
+        let result = shortest_path(&graph, 0);
+        assert_eq!(result, Err(GraphError::Cycle(vec![1, 2, 3])));
+
+        let graph = Graph::undirected_from_edges(2, &[(0, 1, 1.0)]).unwrap();
+        assert_eq!(shortest_path(&graph, 0), Err(GraphError::NotDirected));
+    }
 }
\ No newline at end of file
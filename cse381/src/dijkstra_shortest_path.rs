@@ -1,7 +1,8 @@
-use crate::graph::{Graph, INF, GraphError};
+use crate::graph::{WeightedGraph, INF, GraphError};
+use crate::path_result::PathResult;
 use crate::graph_heap::GraphHeap;
 
-pub fn shortest_path(g : &Graph, start : usize) -> Result<(Vec<f64>, Vec<Option<usize>>), GraphError> {
+pub fn shortest_path<G : WeightedGraph>(g : &G, start : usize) -> Result<PathResult, GraphError> {
     if start >= g.size() {
         return Err(GraphError::InvalidVertex);
     }
@@ -15,21 +16,22 @@ pub fn shortest_path(g : &Graph, start : usize) -> Result<(Vec<f64>, Vec<Option<
     }
 
     while let Some(vertex) = queue.dequeue() {
-        for edge in g.edges(vertex).unwrap() {
-            if distance[vertex] + edge.weight < distance[edge.dest_id] {
-                distance[edge.dest_id] = distance[vertex] + edge.weight;
-                pred[edge.dest_id] = Some(vertex);
-                let _ = queue.decrease_distance(edge.dest_id, distance[edge.dest_id]);
+        for (dest_id, weight) in g.weighted_edges(vertex).unwrap() {
+            if distance[vertex] + weight < distance[dest_id] {
+                distance[dest_id] = distance[vertex] + weight;
+                pred[dest_id] = Some(vertex);
+                let _ = queue.decrease_distance(dest_id, distance[dest_id]);
             }
         }
     }
-    Ok((distance, pred))
+    Ok(PathResult::new(start, distance, pred))
 
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::graph::Graph;
 
     #[test]
     fn test_dijkstra_shortest_path() {
@@ -47,7 +49,7 @@ mod tests {
 
         let result = shortest_path(&graph, 1);
         assert!(result.is_ok());
-        let (dist,pred) = result.unwrap();
+        let PathResult { distance : dist, pred, .. } = result.unwrap();
         assert_eq!(dist, vec![12.0, 0.0, 3.0, 2.0, 5.0]);
         assert_eq!(pred, vec![Some(4), None, Some(1), Some(1), Some(3)]);
     }
@@ -0,0 +1,107 @@
+/* Maintain a collection of disjoint sets of the elements 0..size.  Each set
+ * is a tree whose root represents the set.  Union by rank keeps the trees
+ * short and find compresses the path it walks so the next find is faster.
+ */
+pub struct DisjointSet {
+    parent : Vec<usize>,
+    rank : Vec<u8>,
+    sets : usize
+}
+
+impl DisjointSet {
+    /* Create size sets containing one element each.
+     */
+    pub fn new(size : usize) -> Self {
+        Self { parent : (0..size).collect(), rank : vec![0; size], sets : size }
+    }
+
+    /* Return the representative of the set containing element.  Panics if the
+     * element is invalid.
+     */
+    pub fn find(&mut self, element : usize) -> usize {
+        let mut root = element;
+        while self.parent[root] != root {
+            root = self.parent[root];
+        }
+        // Point everything on the path directly at the root
+        let mut curr = element;
+        while self.parent[curr] != root {
+            let next = self.parent[curr];
+            self.parent[curr] = root;
+            curr = next;
+        }
+        root
+    }
+
+    /* Merge the sets containing a and b.  Returns false if they were already
+     * in the same set.
+     */
+    pub fn union(&mut self, a : usize, b : usize) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return false;
+        }
+        // Hang the shorter tree under the taller one
+        match self.rank[a].cmp(&self.rank[b]) {
+            std::cmp::Ordering::Less => self.parent[a] = b,
+            std::cmp::Ordering::Greater => self.parent[b] = a,
+            std::cmp::Ordering::Equal => {
+                self.parent[b] = a;
+                self.rank[a] += 1;
+            }
+        }
+        self.sets -= 1;
+        true
+    }
+
+    pub fn same_set(&mut self, a : usize, b : usize) -> bool {
+        self.find(a) == self.find(b)
+    }
+
+    /* Return the number of elements.
+     */
+    pub fn size(&self) -> usize {
+        self.parent.len()
+    }
+
+    /* Return the number of disjoint sets.
+     */
+    pub fn sets(&self) -> usize {
+        self.sets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_union_find() {
+        let mut sets = DisjointSet::new(8);
+        assert_eq!(sets.sets(), 8);
+        assert!(sets.union(0, 1));
+        assert!(sets.union(2, 3));
+        assert!(sets.union(1, 3));
+        assert!(!sets.union(0, 2));
+        assert!(sets.union(5, 6));
+        assert_eq!(sets.sets(), 4);
+        assert!(sets.same_set(0, 3));
+        assert!(sets.same_set(6, 5));
+        assert!(!sets.same_set(3, 4));
+        assert!(!sets.same_set(7, 6));
+        assert_eq!(sets.size(), 8);
+    }
+
+    #[test]
+    fn test_path_compression() {
+        // A long chain is flattened by a single find
+        let mut sets = DisjointSet::new(1000);
+        for element in 1..1000 {
+            sets.union(element - 1, element);
+        }
+        assert_eq!(sets.sets(), 1);
+        let root = sets.find(999);
+        assert!((0..1000).all(|element| sets.parent[element] == root));
+        assert!(sets.rank[root] <= 10);
+    }
+}
@@ -0,0 +1,92 @@
+use crate::graph::{GraphError, WeightedGraph, INF};
+use crate::path_result::{AllPairs, PathResult};
+
+/* Floyd-Warshall: distance[i][j] starts as the lightest edge from i to j and
+ * after step k is the shortest path from i to j that only passes through
+ * the vertices 0..=k.  pred[i][j] is the vertex before j on the path from i.
+ * A negative cycle shows up as a vertex with a negative distance to itself.
+ */
+pub fn all_pairs_shortest_path<G : WeightedGraph>(g : &G) -> Result<AllPairs, GraphError> {
+    let size = g.size();
+    let mut distance = vec![vec![INF; size]; size];
+    let mut pred = vec![vec![None; size]; size];
+    for vertex in 0..size {
+        distance[vertex][vertex] = 0.0;
+        for (dest_id, weight) in g.weighted_edges(vertex)? {
+            if weight < distance[vertex][dest_id] {
+                distance[vertex][dest_id] = weight;
+                pred[vertex][dest_id] = Some(vertex);
+            }
+        }
+    }
+
+    for k in 0..size {
+        for i in 0..size {
+            if distance[i][k] == INF {
+                continue;
+            }
+            for j in 0..size {
+                if distance[i][k] + distance[k][j] < distance[i][j] {
+                    distance[i][j] = distance[i][k] + distance[k][j];
+                    pred[i][j] = pred[k][j];
+                }
+            }
+        }
+    }
+    if (0..size).any(|vertex| distance[vertex][vertex] < 0.0) {
+        return Err(GraphError::NegativeCycle);
+    }
+
+    let from = distance.into_iter().zip(pred).enumerate()
+        .map(|(start, (distance, pred))| PathResult::new(start, distance, pred))
+        .collect();
+    Ok(AllPairs { from })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Graph;
+
+    #[test]
+    fn test_floyd_warshall() {
+        let mut graph = Graph::new(5);
+        let _ = graph.add_edge(0, 1, 3.0);
+        let _ = graph.add_edge(0, 2, 8.0);
+        let _ = graph.add_edge(0, 4, -4.0);
+        let _ = graph.add_edge(1, 3, 1.0);
+        let _ = graph.add_edge(1, 4, 7.0);
+        let _ = graph.add_edge(2, 1, 4.0);
+        let _ = graph.add_edge(3, 0, 2.0);
+        let _ = graph.add_edge(3, 2, -5.0);
+        let _ = graph.add_edge(4, 3, 6.0);
+
+        let result = all_pairs_shortest_path(&graph);
+        assert!(result.is_ok());
+        let paths = result.unwrap();
+        assert_eq!(paths.distances(), vec![
+            vec![0.0, 1.0, -3.0, 2.0, -4.0],
+            vec![3.0, 0.0, -4.0, 1.0, -1.0],
+            vec![7.0, 4.0, 0.0, 5.0, 3.0],
+            vec![2.0, -1.0, -5.0, 0.0, -2.0],
+            vec![8.0, 5.0, 1.0, 6.0, 0.0]]);
+        assert_eq!(paths.path(0, 1), Some(vec![0, 4, 3, 2, 1]));
+        assert_eq!(paths.path(2, 0), Some(vec![2, 1, 3, 0]));
+        assert_eq!(paths.distance(4, 2), Some(1.0));
+        assert_eq!(paths.from(5), None);
+    }
+
+    #[test]
+    fn test_unreachable_and_negative_cycle() {
+        let mut graph = Graph::new(3);
+        let _ = graph.add_edge(0, 1, 2.0);
+        let paths = all_pairs_shortest_path(&graph).unwrap();
+        assert_eq!(paths.distance(0, 1), Some(2.0));
+        assert_eq!(paths.distance(1, 0), None);
+        assert_eq!(paths.path(0, 2), None);
+
+        let _ = graph.add_edge(1, 2, -1.0);
+        let _ = graph.add_edge(2, 1, -1.0);
+        assert_eq!(all_pairs_shortest_path(&graph), Err(GraphError::NegativeCycle));
+    }
+}
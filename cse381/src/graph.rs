@@ -1,7 +1,12 @@
+use std::collections::HashMap;
 use std::slice::Iter;
 
-/* Maintain a directed graph with labels (strings) and weights (f64).  Verticies
- * are represented as indicies in a vertex starting with 0.
+/* Maintain a directed or undirected graph.  Verticies are represented as
+ * indicies starting with 0 and each one has a unique label (string) and data
+ * of type V.  Edges have a weight of type E (f64 by default).
+ *
+ * An undirected edge is kept as two directed edges (one each way) so the
+ * algorithms can treat both kinds of graph the same way.
  */
 
 pub const INF : f64 = f64::INFINITY;
@@ -10,54 +15,366 @@ pub const INF : f64 = f64::INFINITY;
 pub enum GraphError {
     InvalidVertex,
     NegativeCycle,
+    DuplicateLabel,
+    // The vertices of a cycle in order (the last one has an edge to the first)
+    Cycle(Vec<usize>),
+    NotUndirected,
+    NotDirected,
 }
 
-pub struct Graph {
-    pub vertices : Vec<Vec<Edge>>,
+pub struct Graph<V = (), E = f64> {
+    labels : Vec<String>,
+    data : Vec<V>,
+    ids : HashMap<String, usize>,
+    // Edges leaving each vertex
+    out_edges : Vec<Vec<Edge<E>>>,
+    // Source of every edge entering each vertex (once per edge)
+    in_edges : Vec<Vec<usize>>,
+    directed : bool,
+    edge_count : usize,
 }
 
-pub struct Edge {
+pub struct Edge<E = f64> {
     pub dest_id : usize,
-    pub weight : f64
+    pub weight : E
+}
+
+/* What the traversal algorithms need from a graph: the number of vertices
+ * and where the edges leaving a vertex go.
+ */
+pub trait Adjacency {
+    fn size(&self) -> usize;
+
+    fn is_directed(&self) -> bool;
+
+    fn neighbors(&self, vertex_id : usize) -> Result<impl Iterator<Item = usize> + '_, GraphError>;
+}
+
+/* What the shortest path algorithms need from a graph: the weights of the
+ * edges leaving a vertex as f64.
+ */
+pub trait WeightedGraph : Adjacency {
+    fn weighted_edges(&self, vertex_id : usize) -> Result<impl Iterator<Item = (usize, f64)> + '_, GraphError>;
 }
 
 impl Graph {
-    /* Create a new graph using the provided labels as vertices.  No edges are created.
+    /* Create a new directed graph with size vertices labelled "0", "1", ...
+     * No edges are created.
      */
     pub fn new(size : usize) -> Self {
-        let vertices : Vec<Vec<Edge>> = (0..size).map(|_| Vec::new()).collect();
-        Self { vertices }
+        let mut graph = Self::directed();
+        for vertex_id in 0..size {
+            let _ = graph.add_vertex(&vertex_id.to_string(), ());
+        }
+        graph
     }
+}
 
-    /* Add a directed edge between two vertices with a weight.  Err is returned
-     * if the verticies are invalid.
+impl<E : Clone> Graph<(), E> {
+    /* Create an undirected graph with size vertices labelled "0", "1", ...
+     * and an edge for each (src_id, dest_id, weight).  Err is returned if an
+     * edge has an invalid vertex.
      */
-    pub fn add_edge(&mut self, src_id : usize, dest_id : usize, weight : f64) -> Result<(), GraphError> {
-        if src_id >= self.vertices.len() {
-            return Err(GraphError::InvalidVertex);
+    pub fn undirected_from_edges(size : usize, edges : &[(usize, usize, E)]) -> Result<Self, GraphError> {
+        let mut graph = Self::undirected();
+        for vertex_id in 0..size {
+            graph.add_vertex(&vertex_id.to_string(), ())?;
+        }
+        for (src_id, dest_id, weight) in edges {
+            graph.add_edge(*src_id, *dest_id, weight.clone())?;
+        }
+        Ok(graph)
+    }
+}
+
+impl<V, E> Graph<V, E> {
+    /* Create an empty directed graph.
+     */
+    pub fn directed() -> Self {
+        Self {
+            labels : Vec::new(),
+            data : Vec::new(),
+            ids : HashMap::new(),
+            out_edges : Vec::new(),
+            in_edges : Vec::new(),
+            directed : true,
+            edge_count : 0
+        }
+    }
+
+    /* Create an empty undirected graph.
+     */
+    pub fn undirected() -> Self {
+        Self { directed : false, ..Self::directed() }
+    }
+
+    pub fn is_directed(&self) -> bool {
+        self.directed
+    }
+
+    /* Add a vertex and return its id.  Err is returned if the label is
+     * already used.
+     */
+    pub fn add_vertex(&mut self, label : &str, data : V) -> Result<usize, GraphError> {
+        if self.ids.contains_key(label) {
+            return Err(GraphError::DuplicateLabel);
         }
-        if dest_id >= self.vertices.len() {
+        let vertex_id = self.labels.len();
+        self.ids.insert(label.to_string(), vertex_id);
+        self.labels.push(label.to_string());
+        self.data.push(data);
+        self.out_edges.push(Vec::new());
+        self.in_edges.push(Vec::new());
+        Ok(vertex_id)
+    }
+
+    fn check(&self, vertex_id : usize) -> Result<(), GraphError> {
+        if vertex_id >= self.labels.len() {
             return Err(GraphError::InvalidVertex);
         }
-        let edge = Edge { dest_id, weight };
-        self.vertices[src_id].push(edge);
         Ok(())
     }
 
-    /* Return an iterator cotaining the edges for a vertex.  Err is returned if the 
-     * vertex is invalid.
+    /* Return the id of the vertex with the label.
      */
-    pub fn edges(&self, vertex_id : usize) -> Result<Iter<Edge>, GraphError> {
-        if vertex_id >= self.vertices.len() {
-            return Err(GraphError::InvalidVertex);
+    pub fn id(&self, label : &str) -> Option<usize> {
+        self.ids.get(label).copied()
+    }
+
+    pub fn label(&self, vertex_id : usize) -> Result<&str, GraphError> {
+        self.check(vertex_id)?;
+        Ok(&self.labels[vertex_id])
+    }
+
+    pub fn vertex(&self, vertex_id : usize) -> Result<&V, GraphError> {
+        self.check(vertex_id)?;
+        Ok(&self.data[vertex_id])
+    }
+
+    pub fn vertex_mut(&mut self, vertex_id : usize) -> Result<&mut V, GraphError> {
+        self.check(vertex_id)?;
+        Ok(&mut self.data[vertex_id])
+    }
+
+    /* Add an edge between two vertices with a weight.  In an undirected
+     * graph the edge goes both ways.  Err is returned if the verticies are
+     * invalid.
+     */
+    pub fn add_edge(&mut self, src_id : usize, dest_id : usize, weight : E) -> Result<(), GraphError>
+    where
+        E: Clone
+    {
+        self.check(src_id)?;
+        self.check(dest_id)?;
+        if !self.directed && src_id != dest_id {
+            self.out_edges[dest_id].push(Edge { dest_id : src_id, weight : weight.clone() });
+            self.in_edges[src_id].push(dest_id);
+        }
+        self.out_edges[src_id].push(Edge { dest_id, weight });
+        self.in_edges[dest_id].push(src_id);
+        self.edge_count += 1;
+        Ok(())
+    }
+
+    // Remove one directed edge and its entry in the reverse adjacency
+    fn unlink(&mut self, src_id : usize, dest_id : usize) -> Option<E> {
+        let index = self.out_edges[src_id].iter().position(|edge| edge.dest_id == dest_id)?;
+        let edge = self.out_edges[src_id].remove(index);
+        if let Some(index) = self.in_edges[dest_id].iter().position(|id| *id == src_id) {
+            self.in_edges[dest_id].remove(index);
+        }
+        Some(edge.weight)
+    }
+
+    /* Remove an edge between two vertices and return its weight (both ways in
+     * an undirected graph).  If there are several edges between them the
+     * oldest one is removed.  Ok(None) is returned if there is no edge and
+     * Err if the vertices are invalid.
+     */
+    pub fn remove_edge(&mut self, src_id : usize, dest_id : usize) -> Result<Option<E>, GraphError> {
+        self.check(src_id)?;
+        self.check(dest_id)?;
+        let weight = self.unlink(src_id, dest_id);
+        if weight.is_some() {
+            if !self.directed && src_id != dest_id {
+                self.unlink(dest_id, src_id);
+            }
+            self.edge_count -= 1;
         }
-        Ok(self.vertices[vertex_id].iter())
+        Ok(weight)
+    }
+
+    /* Return an iterator cotaining the edges for a vertex.  Err is returned if the
+     * vertex is invalid.
+     */
+    pub fn edges(&self, vertex_id : usize) -> Result<Iter<'_, Edge<E>>, GraphError> {
+        self.check(vertex_id)?;
+        Ok(self.out_edges[vertex_id].iter())
+    }
+
+    /* Return an iterator of (source id, weight) for the edges entering a
+     * vertex.  Err is returned if the vertex is invalid.
+     */
+    pub fn in_edges(&self, vertex_id : usize) -> Result<impl Iterator<Item = (usize, &E)> + '_, GraphError> {
+        self.check(vertex_id)?;
+        // A source appears once per edge, so visit each source once and take
+        // all of its edges to the vertex
+        let mut sources = self.in_edges[vertex_id].clone();
+        sources.sort_unstable();
+        sources.dedup();
+        Ok(sources.into_iter().flat_map(move |src_id| {
+            self.out_edges[src_id].iter()
+                .filter(move |edge| edge.dest_id == vertex_id)
+                .map(move |edge| (src_id, &edge.weight))
+        }))
+    }
+
+    /* Return the number of edges leaving a vertex (the degree in an
+     * undirected graph).
+     */
+    pub fn out_degree(&self, vertex_id : usize) -> Result<usize, GraphError> {
+        self.check(vertex_id)?;
+        Ok(self.out_edges[vertex_id].len())
+    }
+
+    /* Return the number of edges entering a vertex (the degree in an
+     * undirected graph).
+     */
+    pub fn in_degree(&self, vertex_id : usize) -> Result<usize, GraphError> {
+        self.check(vertex_id)?;
+        Ok(self.in_edges[vertex_id].len())
+    }
+
+    /* Return an iterator of (source id, dest id, weight) for every edge.  An
+     * undirected edge is only returned once, with the smaller id first.
+     */
+    pub fn all_edges(&self) -> impl Iterator<Item = (usize, usize, &E)> + '_ {
+        self.out_edges.iter().enumerate().flat_map(move |(src_id, edges)| {
+            edges.iter()
+                .filter(move |edge| self.directed || src_id <= edge.dest_id)
+                .map(move |edge| (src_id, edge.dest_id, &edge.weight))
+        })
     }
 
     /* Return the number of vertices in the graph.
      */
     pub fn size(&self) -> usize {
-        self.vertices.len()
+        self.labels.len()
+    }
+
+    /* Return the number of edges in the graph (an undirected edge counts once).
+     */
+    pub fn edge_count(&self) -> usize {
+        self.edge_count
+    }
+}
+
+impl<V, E> Adjacency for Graph<V, E> {
+    fn size(&self) -> usize {
+        self.labels.len()
     }
 
-}
\ No newline at end of file
+    fn is_directed(&self) -> bool {
+        self.directed
+    }
+
+    fn neighbors(&self, vertex_id : usize) -> Result<impl Iterator<Item = usize> + '_, GraphError> {
+        Ok(self.edges(vertex_id)?.map(|edge| edge.dest_id))
+    }
+}
+
+impl<V, E : Copy + Into<f64>> WeightedGraph for Graph<V, E> {
+    fn weighted_edges(&self, vertex_id : usize) -> Result<impl Iterator<Item = (usize, f64)> + '_, GraphError> {
+        Ok(self.edges(vertex_id)?.map(|edge| (edge.dest_id, edge.weight.into())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_labels() {
+        let mut graph = Graph::<u32, u32>::directed();
+        let dayton = graph.add_vertex("Dayton", 137_000).unwrap();
+        let cincinnati = graph.add_vertex("Cincinnati", 309_000).unwrap();
+        assert_eq!(graph.add_vertex("Dayton", 0), Err(GraphError::DuplicateLabel));
+        assert_eq!(graph.id("Cincinnati"), Some(cincinnati));
+        assert_eq!(graph.id("Columbus"), None);
+        assert_eq!(graph.label(dayton), Ok("Dayton"));
+        *graph.vertex_mut(dayton).unwrap() += 1;
+        assert_eq!(graph.vertex(dayton), Ok(&137_001));
+        assert_eq!(graph.vertex(5), Err(GraphError::InvalidVertex));
+
+        // Vertices made by new are labelled with their ids
+        let graph = Graph::new(3);
+        assert_eq!(graph.id("2"), Some(2));
+    }
+
+    #[test]
+    fn test_directed() {
+        let mut graph = Graph::new(4);
+        let _ = graph.add_edge(0, 1, 2.0);
+        let _ = graph.add_edge(0, 2, 4.0);
+        let _ = graph.add_edge(1, 2, 1.0);
+        let _ = graph.add_edge(3, 2, 7.0);
+        let _ = graph.add_edge(3, 2, 8.0);
+        assert_eq!(graph.add_edge(0, 4, 1.0), Err(GraphError::InvalidVertex));
+        assert!(graph.is_directed());
+        assert_eq!(graph.edge_count(), 5);
+
+        assert_eq!(graph.out_degree(0), Ok(2));
+        assert_eq!(graph.in_degree(0), Ok(0));
+        assert_eq!(graph.in_degree(2), Ok(4));
+        let mut incoming = graph.in_edges(2).unwrap().map(|(src, weight)| (src, *weight)).collect::<Vec<_>>();
+        incoming.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(incoming, vec![(0, 4.0), (1, 1.0), (3, 7.0), (3, 8.0)]);
+
+        // The oldest of the two edges from 3 to 2 is removed first
+        assert_eq!(graph.remove_edge(3, 2), Ok(Some(7.0)));
+        assert_eq!(graph.remove_edge(2, 3), Ok(None));
+        assert_eq!(graph.remove_edge(9, 3), Err(GraphError::InvalidVertex));
+        assert_eq!(graph.in_degree(2), Ok(3));
+        let edges = graph.all_edges().map(|(src, dest, weight)| (src, dest, *weight)).collect::<Vec<_>>();
+        assert_eq!(edges, vec![(0, 1, 2.0), (0, 2, 4.0), (1, 2, 1.0), (3, 2, 8.0)]);
+        assert_eq!(graph.edge_count(), 4);
+    }
+
+    #[test]
+    fn test_undirected() {
+        let mut graph = Graph::<(), i32>::undirected();
+        for label in ["a", "b", "c"] {
+            let _ = graph.add_vertex(label, ());
+        }
+        let _ = graph.add_edge(0, 1, 5);
+        let _ = graph.add_edge(2, 1, 3);
+        let _ = graph.add_edge(2, 2, 1);
+        assert!(!graph.is_directed());
+        assert_eq!(graph.edge_count(), 3);
+        assert_eq!(graph.out_degree(1), Ok(2));
+        assert_eq!(graph.in_degree(1), Ok(2));
+        assert_eq!(graph.edges(1).unwrap().map(|edge| edge.dest_id).collect::<Vec<_>>(), vec![0, 2]);
+        assert_eq!(graph.all_edges().map(|(src, dest, weight)| (src, dest, *weight)).collect::<Vec<_>>(),
+            vec![(0, 1, 5), (1, 2, 3), (2, 2, 1)]);
+
+        // Removing either way removes both halves
+        assert_eq!(graph.remove_edge(1, 2), Ok(Some(3)));
+        assert_eq!(graph.remove_edge(2, 1), Ok(None));
+        assert_eq!(graph.out_degree(2), Ok(1));
+        assert_eq!(graph.in_edges(1).unwrap().collect::<Vec<_>>(), vec![(0, &5)]);
+        assert_eq!(graph.edge_count(), 2);
+
+        // Integer weights work with the shortest path algorithms
+        let distances = graph.weighted_edges(0).unwrap().collect::<Vec<_>>();
+        assert_eq!(distances, vec![(1, 5.0)]);
+    }
+
+    #[test]
+    fn test_undirected_from_edges() {
+        let graph = Graph::undirected_from_edges(3, &[(0, 1, 5), (2, 1, 3)]).unwrap();
+        assert!(!graph.is_directed());
+        assert_eq!(graph.label(2), Ok("2"));
+        assert_eq!(graph.edges(1).unwrap().map(|edge| (edge.dest_id, edge.weight)).collect::<Vec<_>>(), vec![(0, 5), (2, 3)]);
+        assert_eq!(Graph::undirected_from_edges(2, &[(0, 2, 1.0)]).err(), Some(GraphError::InvalidVertex));
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::VecDeque;
+use std::vec::IntoIter;
+use crate::graph::{Adjacency, GraphError};
+
+/* Breadth first and depth first search as iterators, so the caller can stop
+ * early or act on each vertex as it is reached.
+ */
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct BfsVisit {
+    pub vertex : usize,
+    pub parent : Option<usize>,
+    // Number of edges from the start
+    pub distance : usize
+}
+
+pub struct Bfs<'a, G> {
+    g : &'a G,
+    queue : VecDeque<BfsVisit>,
+    discovered : Vec<bool>
+}
+
+impl<'a, G : Adjacency> Bfs<'a, G> {
+    /* Visit the vertices reachable from start in order of distance.  Err is
+     * returned if start is invalid.
+     */
+    pub fn new(g : &'a G, start : usize) -> Result<Self, GraphError> {
+        if start >= g.size() {
+            return Err(GraphError::InvalidVertex);
+        }
+        let mut discovered = vec![false; g.size()];
+        discovered[start] = true;
+        let queue = VecDeque::from([BfsVisit { vertex : start, parent : None, distance : 0 }]);
+        Ok(Self { g, queue, discovered })
+    }
+}
+
+impl<G : Adjacency> Iterator for Bfs<'_, G> {
+    type Item = BfsVisit;
+
+    fn next(&mut self) -> Option<BfsVisit> {
+        let visit = self.queue.pop_front()?;
+        for next in self.g.neighbors(visit.vertex).unwrap() {
+            if !self.discovered[next] {
+                self.discovered[next] = true;
+                self.queue.push_back(BfsVisit { vertex : next, parent : Some(visit.vertex), distance : visit.distance + 1 });
+            }
+        }
+        Some(visit)
+    }
+}
+
+/* A vertex is discovered when the search reaches it and finished when all of
+ * its edges have been explored.  Both share one clock that starts at 1.
+ */
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DfsEvent {
+    Discover { vertex : usize, parent : Option<usize>, time : usize },
+    Finish { vertex : usize, time : usize }
+}
+
+pub struct Dfs<'a, G> {
+    g : &'a G,
+    // Vertices to start a new tree from once the stack is empty
+    roots : IntoIter<usize>,
+    // Each vertex being explored and the neighbors it has left
+    stack : Vec<(usize, IntoIter<usize>)>,
+    discovered : Vec<bool>,
+    time : usize
+}
+
+impl<'a, G : Adjacency> Dfs<'a, G> {
+    /* Search the vertices reachable from start.  Err is returned if start is
+     * invalid.
+     */
+    pub fn new(g : &'a G, start : usize) -> Result<Self, GraphError> {
+        if start >= g.size() {
+            return Err(GraphError::InvalidVertex);
+        }
+        Ok(Self::with_roots(g, vec![start]))
+    }
+
+    /* Search the whole graph, starting a new tree from the lowest vertex not
+     * yet discovered (a depth first forest).
+     */
+    pub fn all(g : &'a G) -> Self {
+        Self::with_roots(g, (0..g.size()).collect())
+    }
+
+    fn with_roots(g : &'a G, roots : Vec<usize>) -> Self {
+        let discovered = vec![false; g.size()];
+        Self { g, roots : roots.into_iter(), stack : Vec::new(), discovered, time : 0 }
+    }
+
+    fn discover(&mut self, vertex : usize, parent : Option<usize>) -> DfsEvent {
+        self.discovered[vertex] = true;
+        self.time += 1;
+        let neighbors = self.g.neighbors(vertex).unwrap().collect::<Vec<_>>();
+        self.stack.push((vertex, neighbors.into_iter()));
+        DfsEvent::Discover { vertex, parent, time : self.time }
+    }
+}
+
+impl<G : Adjacency> Iterator for Dfs<'_, G> {
+    type Item = DfsEvent;
+
+    fn next(&mut self) -> Option<DfsEvent> {
+        if let Some((vertex, neighbors)) = self.stack.last_mut() {
+            let vertex = *vertex;
+            // Go deeper if there is an undiscovered neighbor, otherwise finish
+            return match neighbors.find(|next| !self.discovered[*next]) {
+                Some(next) => Some(self.discover(next, Some(vertex))),
+                None => {
+                    self.stack.pop();
+                    self.time += 1;
+                    Some(DfsEvent::Finish { vertex, time : self.time })
+                }
+            };
+        }
+        let root = self.roots.find(|root| !self.discovered[*root])?;
+        Some(self.discover(root, None))
+    }
+}
+
+/* Return the discovery and finish time of every vertex for a depth first
+ * search of the whole graph.
+ */
+pub fn dfs_times<G : Adjacency>(g : &G) -> (Vec<usize>, Vec<usize>) {
+    let mut discovered = vec![0; g.size()];
+    let mut finished = vec![0; g.size()];
+    for event in Dfs::all(g) {
+        match event {
+            DfsEvent::Discover { vertex, time, .. } => discovered[vertex] = time,
+            DfsEvent::Finish { vertex, time } => finished[vertex] = time
+        }
+    }
+    (discovered, finished)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Graph;
+
+    #[test]
+    fn test_bfs() {
+        // r s t u v w x y
+        let mut graph = Graph::<(), f64>::undirected();
+        for label in ["r", "s", "t", "u", "v", "w", "x", "y"] {
+            let _ = graph.add_vertex(label, ());
+        }
+        let _ = graph.add_edge(0, 1, 1.0);
+        let _ = graph.add_edge(0, 4, 1.0);
+        let _ = graph.add_edge(1, 5, 1.0);
+        let _ = graph.add_edge(5, 2, 1.0);
+        let _ = graph.add_edge(5, 6, 1.0);
+        let _ = graph.add_edge(2, 6, 1.0);
+        let _ = graph.add_edge(2, 3, 1.0);
+        let _ = graph.add_edge(6, 3, 1.0);
+        let _ = graph.add_edge(6, 7, 1.0);
+        let _ = graph.add_edge(3, 7, 1.0);
+
+        let visits = Bfs::new(&graph, 1).unwrap().collect::<Vec<_>>();
+        let order = visits.iter().map(|visit| visit.vertex).collect::<Vec<_>>();
+        assert_eq!(order, vec![1, 0, 5, 4, 2, 6, 3, 7]);
+        let mut distance = vec![0; 8];
+        let mut pred = vec![None; 8];
+        for visit in visits {
+            distance[visit.vertex] = visit.distance;
+            pred[visit.vertex] = visit.parent;
+        }
+        assert_eq!(distance, vec![1, 0, 2, 3, 2, 1, 2, 3]);
+        assert_eq!(pred, vec![Some(1), None, Some(5), Some(2), Some(0), Some(1), Some(5), Some(6)]);
+        assert!(Bfs::new(&graph, 8).is_err());
+    }
+
+    #[test]
+    fn test_dfs() {
+        // u v w x y z
+        let mut graph = Graph::new(6);
+        let _ = graph.add_edge(0, 1, 1.0);
+        let _ = graph.add_edge(0, 3, 1.0);
+        let _ = graph.add_edge(1, 4, 1.0);
+        let _ = graph.add_edge(2, 4, 1.0);
+        let _ = graph.add_edge(2, 5, 1.0);
+        let _ = graph.add_edge(3, 1, 1.0);
+        let _ = graph.add_edge(4, 3, 1.0);
+        let _ = graph.add_edge(5, 5, 1.0);
+
+        let (discovered, finished) = dfs_times(&graph);
+        assert_eq!(discovered, vec![1, 2, 9, 4, 3, 10]);
+        assert_eq!(finished, vec![8, 7, 12, 5, 6, 11]);
+
+        // Only the vertices reachable from w
+        let events = Dfs::new(&graph, 2).unwrap().collect::<Vec<_>>();
+        assert_eq!(events, vec![
+            DfsEvent::Discover { vertex : 2, parent : None, time : 1 },
+            DfsEvent::Discover { vertex : 4, parent : Some(2), time : 2 },
+            DfsEvent::Discover { vertex : 3, parent : Some(4), time : 3 },
+            DfsEvent::Discover { vertex : 1, parent : Some(3), time : 4 },
+            DfsEvent::Finish { vertex : 1, time : 5 },
+            DfsEvent::Finish { vertex : 3, time : 6 },
+            DfsEvent::Finish { vertex : 4, time : 7 },
+            DfsEvent::Discover { vertex : 5, parent : Some(2), time : 8 },
+            DfsEvent::Finish { vertex : 5, time : 9 },
+            DfsEvent::Finish { vertex : 2, time : 10 },
+        ]);
+    }
+}
@@ -0,0 +1,154 @@
+use crate::graph::{Adjacency, GraphError, WeightedGraph, INF};
+use crate::path_result::{AllPairs, PathResult};
+use crate::{bellman_ford_shortest_path, dijkstra_shortest_path};
+
+/* The graph with one extra vertex (id size) that has an edge of weight 0 to
+ * every other vertex, so every vertex is reachable from it.
+ */
+struct WithSource<'a, G> {
+    g : &'a G
+}
+
+impl<G : WeightedGraph> Adjacency for WithSource<'_, G> {
+    fn size(&self) -> usize {
+        self.g.size() + 1
+    }
+
+    fn is_directed(&self) -> bool {
+        true
+    }
+
+    fn neighbors(&self, vertex_id : usize) -> Result<impl Iterator<Item = usize> + '_, GraphError> {
+        Ok(self.weighted_edges(vertex_id)?.map(|(dest_id, _)| dest_id))
+    }
+}
+
+impl<G : WeightedGraph> WeightedGraph for WithSource<'_, G> {
+    fn weighted_edges(&self, vertex_id : usize) -> Result<impl Iterator<Item = (usize, f64)> + '_, GraphError> {
+        let edges : Box<dyn Iterator<Item = (usize, f64)>> = if vertex_id == self.g.size() {
+            Box::new((0..self.g.size()).map(|dest_id| (dest_id, 0.0)))
+        } else {
+            Box::new(self.g.weighted_edges(vertex_id)?)
+        };
+        Ok(edges)
+    }
+}
+
+/* The graph with every edge (u, v) reweighted to w + h[u] - h[v], which is
+ * never negative when h is a shortest distance.  Any path from u to v
+ * changes by h[u] - h[v], so the shortest paths stay the same.
+ */
+struct Reweighted<'a, G> {
+    g : &'a G,
+    h : Vec<f64>
+}
+
+impl<G : WeightedGraph> Adjacency for Reweighted<'_, G> {
+    fn size(&self) -> usize {
+        self.g.size()
+    }
+
+    fn is_directed(&self) -> bool {
+        self.g.is_directed()
+    }
+
+    fn neighbors(&self, vertex_id : usize) -> Result<impl Iterator<Item = usize> + '_, GraphError> {
+        self.g.neighbors(vertex_id)
+    }
+}
+
+impl<G : WeightedGraph> WeightedGraph for Reweighted<'_, G> {
+    fn weighted_edges(&self, vertex_id : usize) -> Result<impl Iterator<Item = (usize, f64)> + '_, GraphError> {
+        let h = &self.h;
+        Ok(self.g.weighted_edges(vertex_id)?.map(move |(dest_id, weight)| (dest_id, weight + h[vertex_id] - h[dest_id])))
+    }
+}
+
+/* Johnson: run Bellman-Ford once from an extra vertex to find h (and any
+ * negative cycle), then Dijkstra from every vertex on the reweighted graph
+ * and undo the reweighting.  Faster than Floyd-Warshall on sparse graphs.
+ */
+pub fn all_pairs_shortest_path<G : WeightedGraph>(g : &G) -> Result<AllPairs, GraphError> {
+    let size = g.size();
+    let mut h = bellman_ford_shortest_path::shortest_path(&WithSource { g }, size)?.distance;
+    h.truncate(size);
+    let reweighted = Reweighted { g, h };
+
+    let mut from = Vec::with_capacity(size);
+    for start in 0..size {
+        let mut result = dijkstra_shortest_path::shortest_path(&reweighted, start)?;
+        for (dest_id, distance) in result.distance.iter_mut().enumerate() {
+            if *distance != INF {
+                *distance += reweighted.h[dest_id] - reweighted.h[start];
+            }
+        }
+        from.push(PathResult::new(start, result.distance, result.pred));
+    }
+    Ok(AllPairs { from })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Graph;
+    use crate::floyd_warshall_shortest_path;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn test_johnson() {
+        let mut graph = Graph::new(5);
+        let _ = graph.add_edge(0, 1, 3.0);
+        let _ = graph.add_edge(0, 2, 8.0);
+        let _ = graph.add_edge(0, 4, -4.0);
+        let _ = graph.add_edge(1, 3, 1.0);
+        let _ = graph.add_edge(1, 4, 7.0);
+        let _ = graph.add_edge(2, 1, 4.0);
+        let _ = graph.add_edge(3, 0, 2.0);
+        let _ = graph.add_edge(3, 2, -5.0);
+        let _ = graph.add_edge(4, 3, 6.0);
+
+        let result = all_pairs_shortest_path(&graph);
+        assert!(result.is_ok());
+        let paths = result.unwrap();
+        assert_eq!(paths.distances(), vec![
+            vec![0.0, 1.0, -3.0, 2.0, -4.0],
+            vec![3.0, 0.0, -4.0, 1.0, -1.0],
+            vec![7.0, 4.0, 0.0, 5.0, 3.0],
+            vec![2.0, -1.0, -5.0, 0.0, -2.0],
+            vec![8.0, 5.0, 1.0, 6.0, 0.0]]);
+        assert_eq!(paths.path(0, 1), Some(vec![0, 4, 3, 2, 1]));
+        assert_eq!(paths.path(1, 2), Some(vec![1, 3, 2]));
+    }
+
+    #[test]
+    fn test_negative_cycle() {
+        let mut graph = Graph::new(3);
+        let _ = graph.add_edge(0, 1, 2.0);
+        let _ = graph.add_edge(1, 2, -1.0);
+        let _ = graph.add_edge(2, 1, -1.0);
+        assert_eq!(all_pairs_shortest_path(&graph), Err(GraphError::NegativeCycle));
+    }
+
+    #[test]
+    fn test_same_as_floyd_warshall() {
+        // Integer weights so the sums are exact
+        let mut rng = StdRng::seed_from_u64(49);
+        for _ in 0..50 {
+            let size = rng.gen_range(1..20);
+            let mut graph = Graph::new(size);
+            for _ in 0..rng.gen_range(0..size * 3) {
+                let _ = graph.add_edge(rng.gen_range(0..size), rng.gen_range(0..size), rng.gen_range(-2..10) as f64);
+            }
+            let johnson = all_pairs_shortest_path(&graph);
+            let floyd_warshall = floyd_warshall_shortest_path::all_pairs_shortest_path(&graph);
+            match (johnson, floyd_warshall) {
+                (Ok(johnson), Ok(floyd_warshall)) => assert_eq!(johnson.distances(), floyd_warshall.distances()),
+                (johnson, floyd_warshall) => {
+                    assert_eq!(johnson, Err(GraphError::NegativeCycle));
+                    assert_eq!(floyd_warshall, Err(GraphError::NegativeCycle));
+                }
+            }
+        }
+    }
+}
@@ -6,6 +6,20 @@ pub mod dag_shortest_path;
 pub mod graph_heap;
 pub mod dijkstra_shortest_path;
 pub mod bellman_ford_shortest_path;
+pub mod path_result;
+pub mod floyd_warshall_shortest_path;
+pub mod johnson_shortest_path;
+pub mod astar_shortest_path;
+pub mod bidirectional_dijkstra;
+#[cfg(test)]
+mod search_testing;
+pub mod graph_search;
+pub mod topological_sort;
+pub mod strongly_connected_components;
+pub mod biconnected;
+pub mod bipartite;
+pub mod disjoint_set;
+pub mod minimum_spanning_tree;
 pub mod string_matcher;
 pub mod rsa;
 pub mod huffman_tree;
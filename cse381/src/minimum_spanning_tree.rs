@@ -0,0 +1,165 @@
+use crate::disjoint_set::DisjointSet;
+use crate::graph::{GraphError, WeightedGraph, INF};
+use crate::graph_heap::GraphHeap;
+
+/* A minimum spanning tree of each connected component.  Edges are
+ * (vertex id, vertex id, weight) with the smaller id first, so a graph with
+ * size vertices and c components has size - c edges.
+ */
+#[derive(Debug, PartialEq)]
+pub struct SpanningForest {
+    pub weight : f64,
+    pub edges : Vec<(usize, usize, f64)>
+}
+
+impl SpanningForest {
+    fn new() -> Self {
+        Self { weight : 0.0, edges : Vec::new() }
+    }
+
+    fn add(&mut self, a : usize, b : usize, weight : f64) {
+        self.weight += weight;
+        self.edges.push((a.min(b), a.max(b), weight));
+    }
+}
+
+/* Kruskal: go through the edges from lightest to heaviest and keep each one
+ * that joins two different trees.  The trees are tracked with a disjoint
+ * set.  Err is returned if the graph is directed.
+ */
+pub fn kruskal<G : WeightedGraph>(g : &G) -> Result<SpanningForest, GraphError> {
+    if g.is_directed() {
+        return Err(GraphError::NotUndirected);
+    }
+    // Each undirected edge is listed from both ends, so keep one of them
+    let mut edges = Vec::<(usize, usize, f64)>::new();
+    for vertex in 0..g.size() {
+        for (dest_id, weight) in g.weighted_edges(vertex)? {
+            if vertex < dest_id {
+                edges.push((vertex, dest_id, weight));
+            }
+        }
+    }
+    edges.sort_by(|a, b| a.2.total_cmp(&b.2));
+
+    let mut trees = DisjointSet::new(g.size());
+    let mut forest = SpanningForest::new();
+    for (src_id, dest_id, weight) in edges {
+        if trees.union(src_id, dest_id) {
+            forest.add(src_id, dest_id, weight);
+            if trees.sets() == 1 {
+                break;
+            }
+        }
+    }
+    Ok(forest)
+}
+
+/* Prim: grow a tree one vertex at a time, always adding the vertex with the
+ * lightest edge to the tree.  The queue holds every vertex not yet in the
+ * tree keyed by that edge's weight.  When the lightest key is INF the
+ * component is done and the vertex starts a new tree.  Err is returned if
+ * the graph is directed.
+ */
+pub fn prim<G : WeightedGraph>(g : &G) -> Result<SpanningForest, GraphError> {
+    if g.is_directed() {
+        return Err(GraphError::NotUndirected);
+    }
+    let mut key = vec![INF; g.size()];
+    let mut pred = vec![None; g.size()];
+    let mut in_tree = vec![false; g.size()];
+    let mut forest = SpanningForest::new();
+
+    let mut queue = GraphHeap::default();
+    for vertex in 0..g.size() {
+        queue.enqueue(vertex, INF);
+    }
+    // Start the first tree from vertex 0
+    if g.size() > 0 {
+        key[0] = 0.0;
+        let _ = queue.decrease_distance(0, 0.0);
+    }
+
+    while let Some(vertex) = queue.dequeue() {
+        in_tree[vertex] = true;
+        if let Some(pred) = pred[vertex] {
+            forest.add(pred, vertex, key[vertex]);
+        }
+        for (dest_id, weight) in g.weighted_edges(vertex)? {
+            if !in_tree[dest_id] && weight < key[dest_id] {
+                key[dest_id] = weight;
+                pred[dest_id] = Some(vertex);
+                let _ = queue.decrease_distance(dest_id, weight);
+            }
+        }
+    }
+    Ok(forest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Graph;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    fn sorted(forest : &SpanningForest) -> Vec<(usize, usize, f64)> {
+        let mut edges = forest.edges.clone();
+        edges.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        edges
+    }
+
+    #[test]
+    fn test_minimum_spanning_tree() {
+        // a b c d e f g h i
+        let graph = Graph::undirected_from_edges(9, &[(0, 1, 4.0), (0, 7, 8.0), (1, 2, 8.0), (1, 7, 11.0), (2, 3, 7.0),
+            (2, 5, 4.0), (2, 8, 2.0), (3, 4, 9.0), (3, 5, 14.0), (4, 5, 10.0), (5, 6, 2.0), (6, 7, 1.0),
+            (6, 8, 6.0), (7, 8, 7.0)]).unwrap();
+
+        let result = kruskal(&graph);
+        assert!(result.is_ok());
+        let forest = result.unwrap();
+        assert_eq!(forest.weight, 37.0);
+        assert_eq!(forest.edges.len(), 8);
+        // Lightest first
+        assert_eq!(forest.edges[..3], [(6, 7, 1.0), (2, 8, 2.0), (5, 6, 2.0)]);
+
+        let result = prim(&graph);
+        assert!(result.is_ok());
+        let forest = result.unwrap();
+        assert_eq!(forest.weight, 37.0);
+        assert_eq!(sorted(&forest), vec![(0, 1, 4.0), (0, 7, 8.0), (2, 3, 7.0), (2, 5, 4.0),
+            (2, 8, 2.0), (3, 4, 9.0), (5, 6, 2.0), (6, 7, 1.0)]);
+    }
+
+    #[test]
+    fn test_spanning_forest() {
+        // Two triangles and a vertex on its own
+        let graph = Graph::undirected_from_edges(7, &[(0, 1, 1.0), (1, 2, 2.0), (2, 0, 3.0), (3, 4, 5.0),
+            (4, 5, 4.0), (5, 3, 6.0)]).unwrap();
+        let expected = vec![(0, 1, 1.0), (1, 2, 2.0), (3, 4, 5.0), (4, 5, 4.0)];
+        for forest in [kruskal(&graph).unwrap(), prim(&graph).unwrap()] {
+            assert_eq!(forest.weight, 12.0);
+            assert_eq!(sorted(&forest), expected);
+        }
+        assert_eq!(prim(&Graph::<(), f64>::undirected()), Ok(SpanningForest::new()));
+        assert_eq!(kruskal(&Graph::new(3)), Err(GraphError::NotUndirected));
+        assert_eq!(prim(&Graph::new(3)), Err(GraphError::NotUndirected));
+    }
+
+    #[test]
+    fn test_random_graphs() {
+        // The trees can differ when weights tie but the total weight can't
+        let mut rng = StdRng::seed_from_u64(48);
+        for _ in 0..50 {
+            let size = rng.gen_range(1..40);
+            let edges = (0..rng.gen_range(0..size * 3))
+                .map(|_| (rng.gen_range(0..size), rng.gen_range(0..size), rng.gen_range(1..20) as f64))
+                .collect::<Vec<_>>();
+            let graph = Graph::undirected_from_edges(size, &edges).unwrap();
+            let (by_kruskal, by_prim) = (kruskal(&graph).unwrap(), prim(&graph).unwrap());
+            assert_eq!(by_kruskal.weight, by_prim.weight);
+            assert_eq!(by_kruskal.edges.len(), by_prim.edges.len());
+        }
+    }
+}
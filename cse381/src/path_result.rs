@@ -0,0 +1,117 @@
+use crate::graph::INF;
+
+/* The result of a single source shortest path algorithm.  distance[v] is the
+ * length of the shortest path from start to v (INF if there is none) and
+ * pred[v] is the vertex before v on that path.
+ */
+#[derive(Debug, PartialEq, Clone)]
+pub struct PathResult {
+    pub start : usize,
+    pub distance : Vec<f64>,
+    pub pred : Vec<Option<usize>>
+}
+
+impl PathResult {
+    pub fn new(start : usize, distance : Vec<f64>, pred : Vec<Option<usize>>) -> Self {
+        Self { start, distance, pred }
+    }
+
+    /* Return true if there is a path from start to the vertex.
+     */
+    pub fn reachable(&self, vertex : usize) -> bool {
+        self.distance.get(vertex).is_some_and(|distance| *distance != INF)
+    }
+
+    /* Return the length of the shortest path to the vertex.  None is returned
+     * if the vertex is unreachable or invalid.
+     */
+    pub fn distance_to(&self, vertex : usize) -> Option<f64> {
+        if !self.reachable(vertex) {
+            return None;
+        }
+        Some(self.distance[vertex])
+    }
+
+    /* Return the vertices on the shortest path from start to the vertex
+     * (both included) by walking back through pred.  None is returned if the
+     * vertex is unreachable or invalid.
+     */
+    pub fn path_to(&self, vertex : usize) -> Option<Vec<usize>> {
+        if !self.reachable(vertex) {
+            return None;
+        }
+        let mut path = vec![vertex];
+        let mut curr = vertex;
+        while curr != self.start {
+            curr = self.pred[curr]?;
+            path.push(curr);
+            // A path can't visit a vertex twice
+            if path.len() > self.pred.len() {
+                return None;
+            }
+        }
+        path.reverse();
+        Some(path)
+    }
+}
+
+/* The result of an all pairs shortest path algorithm: one PathResult for
+ * each start vertex.
+ */
+#[derive(Debug, PartialEq, Clone)]
+pub struct AllPairs {
+    pub from : Vec<PathResult>
+}
+
+impl AllPairs {
+    /* Return the shortest paths from a start vertex (None if invalid).
+     */
+    pub fn from(&self, start : usize) -> Option<&PathResult> {
+        self.from.get(start)
+    }
+
+    pub fn distance(&self, start : usize, dest : usize) -> Option<f64> {
+        self.from(start)?.distance_to(dest)
+    }
+
+    pub fn path(&self, start : usize, dest : usize) -> Option<Vec<usize>> {
+        self.from(start)?.path_to(dest)
+    }
+
+    /* Return the matrix of distances (INF where there is no path).
+     */
+    pub fn distances(&self) -> Vec<Vec<f64>> {
+        self.from.iter().map(|result| result.distance.clone()).collect()
+    }
+}
+
+/* The result of a search from a start vertex to a goal.  distance is INF
+ * and path is None if the goal is unreachable.  expanded counts the
+ * vertices taken off the queue, which is how much of the graph was explored.
+ */
+#[derive(Debug, PartialEq, Clone)]
+pub struct Route {
+    pub distance : f64,
+    pub path : Option<Vec<usize>>,
+    pub expanded : usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_result() {
+        // 0 -> 1 -> 3 and 0 -> 2, with 4 unreachable
+        let result = PathResult::new(0, vec![0.0, 2.0, 5.0, 3.5, INF], vec![None, Some(0), Some(0), Some(1), None]);
+        assert!(result.reachable(3));
+        assert!(!result.reachable(4));
+        assert!(!result.reachable(5));
+        assert_eq!(result.distance_to(3), Some(3.5));
+        assert_eq!(result.distance_to(4), None);
+        assert_eq!(result.path_to(3), Some(vec![0, 1, 3]));
+        assert_eq!(result.path_to(0), Some(vec![0]));
+        assert_eq!(result.path_to(4), None);
+        assert_eq!(result.path_to(9), None);
+    }
+}
@@ -0,0 +1,67 @@
+/* Checks shared by the tests of the single pair searches (A* and
+ * bidirectional Dijkstra).  Only built for tests.
+ */
+use crate::dijkstra_shortest_path;
+use crate::graph::{Graph, INF};
+use crate::path_result::Route;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+/* Width x height grid with an edge between neighbors and vertex id
+ * y * width + x.  The weights are 1 to 3 from a fixed seed so the number
+ * of expanded vertices is the same on every run.
+ */
+pub fn grid(width : usize, height : usize) -> Graph<(), f64> {
+    let mut rng = StdRng::seed_from_u64(381);
+    let mut edges = Vec::new();
+    for id in 0..width * height {
+        if id % width + 1 < width {
+            edges.push((id, id + 1, rng.gen_range(1..4) as f64));
+        }
+        if id + width < width * height {
+            edges.push((id, id + width, rng.gen_range(1..4) as f64));
+        }
+    }
+    Graph::undirected_from_edges(width * height, &edges).unwrap()
+}
+
+/* Compare search with Dijkstra on random graphs with integer weights
+ * (so the sums are exact), only directed ones unless undirected is set.
+ * The graphs come from a fixed seed so a failure can be reproduced.
+ */
+pub fn check_random_graphs<F>(undirected : bool, search : F)
+where
+    F: Fn(&Graph<(), f64>, usize, usize) -> Route
+{
+    let mut rng = StdRng::seed_from_u64(50);
+    for _ in 0..100 {
+        let size = rng.gen_range(1..30);
+        let edges = (0..rng.gen_range(0..size * 4))
+            .map(|_| (rng.gen_range(0..size), rng.gen_range(0..size), rng.gen_range(0..10) as f64))
+            .collect::<Vec<_>>();
+        let graph = if undirected && rng.gen_bool(0.5) {
+            Graph::undirected_from_edges(size, &edges).unwrap()
+        } else {
+            let mut graph = Graph::new(size);
+            for (src_id, dest_id, weight) in edges {
+                let _ = graph.add_edge(src_id, dest_id, weight);
+            }
+            graph
+        };
+        let (start, goal) = (rng.gen_range(0..size), rng.gen_range(0..size));
+        let expected = dijkstra_shortest_path::shortest_path(&graph, start).unwrap();
+
+        let route = search(&graph, start, goal);
+        assert_eq!(route.distance, expected.distance[goal]);
+        assert_eq!(route.path.is_some(), expected.reachable(goal));
+        // The path goes from start to goal and really has that length
+        if let Some(path) = route.path {
+            assert_eq!((path[0], path[path.len() - 1]), (start, goal));
+            let length = path.windows(2).map(|pair| graph.edges(pair[0]).unwrap()
+                .filter(|edge| edge.dest_id == pair[1])
+                .map(|edge| edge.weight)
+                .fold(INF, f64::min)).sum::<f64>();
+            assert_eq!(length, route.distance);
+        }
+    }
+}
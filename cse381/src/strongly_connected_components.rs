@@ -0,0 +1,115 @@
+use std::vec::IntoIter;
+use crate::graph::Adjacency;
+
+/* Find the strongly connected components with Tarjan's algorithm.  Every
+ * vertex gets an index in the order it is discovered and a low link, the
+ * lowest index it can reach through its descendants and one more edge while
+ * that vertex is still on the stack.  A vertex whose low link is its own
+ * index is the root of a component, which is everything above it on the
+ * stack.
+ *
+ * The components are returned in reverse topological order (no edge leaves a
+ * component for a later one) with the vertices of each one sorted.
+ */
+pub fn strongly_connected_components<G : Adjacency>(g : &G) -> Vec<Vec<usize>> {
+    let neighbors = |vertex : usize| -> IntoIter<usize> {
+        g.neighbors(vertex).unwrap().collect::<Vec<_>>().into_iter()
+    };
+    let mut index = vec![None; g.size()];
+    let mut low = vec![0; g.size()];
+    let mut on_stack = vec![false; g.size()];
+    let mut stack = Vec::<usize>::new();
+    let mut components = Vec::<Vec<usize>>::new();
+    let mut next_index = 0;
+
+    for root in 0..g.size() {
+        if index[root].is_some() {
+            continue;
+        }
+        // Instead of recursion, each call is a vertex and its remaining edges
+        let mut calls = Vec::<(usize, IntoIter<usize>)>::new();
+        let mut visit = Some(root);
+        loop {
+            if let Some(vertex) = visit.take() {
+                index[vertex] = Some(next_index);
+                low[vertex] = next_index;
+                next_index += 1;
+                stack.push(vertex);
+                on_stack[vertex] = true;
+                calls.push((vertex, neighbors(vertex)));
+            }
+            let Some((vertex, edges)) = calls.last_mut() else {
+                break;
+            };
+            let vertex = *vertex;
+            if let Some(next) = edges.next() {
+                match index[next] {
+                    None => visit = Some(next),
+                    Some(next_index) if on_stack[next] => low[vertex] = low[vertex].min(next_index),
+                    Some(_) => {}
+                }
+                continue;
+            }
+
+            // Finished with vertex
+            calls.pop();
+            if let Some((parent, _)) = calls.last() {
+                low[*parent] = low[*parent].min(low[vertex]);
+            }
+            if Some(low[vertex]) == index[vertex] {
+                let mut component = Vec::new();
+                while let Some(member) = stack.pop() {
+                    on_stack[member] = false;
+                    component.push(member);
+                    if member == vertex {
+                        break;
+                    }
+                }
+                component.sort_unstable();
+                components.push(component);
+            }
+        }
+    }
+    components
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Graph;
+
+    #[test]
+    fn test_strongly_connected_components() {
+        // a b c d e f g h
+        let mut graph = Graph::new(8);
+        let _ = graph.add_edge(0, 1, 1.0);
+        let _ = graph.add_edge(1, 2, 1.0);
+        let _ = graph.add_edge(1, 4, 1.0);
+        let _ = graph.add_edge(1, 5, 1.0);
+        let _ = graph.add_edge(2, 3, 1.0);
+        let _ = graph.add_edge(2, 6, 1.0);
+        let _ = graph.add_edge(3, 2, 1.0);
+        let _ = graph.add_edge(3, 7, 1.0);
+        let _ = graph.add_edge(4, 0, 1.0);
+        let _ = graph.add_edge(4, 5, 1.0);
+        let _ = graph.add_edge(5, 6, 1.0);
+        let _ = graph.add_edge(6, 5, 1.0);
+        let _ = graph.add_edge(6, 7, 1.0);
+        let _ = graph.add_edge(7, 7, 1.0);
+
+        let components = strongly_connected_components(&graph);
+        assert_eq!(components, vec![vec![7], vec![5, 6], vec![2, 3], vec![0, 1, 4]]);
+    }
+
+    #[test]
+    fn test_dag() {
+        // Without cycles every vertex is its own component
+        let mut graph = Graph::new(4);
+        let _ = graph.add_edge(0, 1, 1.0);
+        let _ = graph.add_edge(0, 2, 1.0);
+        let _ = graph.add_edge(2, 3, 1.0);
+        let components = strongly_connected_components(&graph);
+        assert_eq!(components, vec![vec![1], vec![3], vec![2], vec![0]]);
+        assert!(strongly_connected_components(&Graph::new(0)).is_empty());
+    }
+}
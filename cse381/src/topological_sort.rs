@@ -0,0 +1,112 @@
+use std::vec::IntoIter;
+use crate::graph::{Adjacency, GraphError};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Colour {
+    White,  // Not discovered
+    Gray,   // Discovered but not finished
+    Black   // Finished
+}
+
+/* Order the vertices so that every edge goes from an earlier vertex to a
+ * later one.  This is the reverse of the order the vertices finish in a
+ * depth first search.  If the graph has a cycle there is no such order and
+ * Err(GraphError::Cycle) is returned with the vertices of the first cycle
+ * found.  Err(GraphError::NotDirected) is returned for an undirected graph.
+ */
+pub fn topological_sort<G : Adjacency>(g : &G) -> Result<Vec<usize>, GraphError> {
+    if !g.is_directed() {
+        return Err(GraphError::NotDirected);
+    }
+    let neighbors = |vertex : usize| -> IntoIter<usize> {
+        g.neighbors(vertex).unwrap().collect::<Vec<_>>().into_iter()
+    };
+    let mut colour = vec![Colour::White; g.size()];
+    let mut order = Vec::<usize>::with_capacity(g.size());
+
+    for root in 0..g.size() {
+        if colour[root] != Colour::White {
+            continue;
+        }
+        colour[root] = Colour::Gray;
+        let mut stack = vec![(root, neighbors(root))];
+        while let Some((vertex, edges)) = stack.last_mut() {
+            let vertex = *vertex;
+            match edges.next() {
+                Some(next) if colour[next] == Colour::White => {
+                    colour[next] = Colour::Gray;
+                    stack.push((next, neighbors(next)));
+                }
+                Some(next) if colour[next] == Colour::Gray => {
+                    // A back edge: the stack from next up to vertex is a cycle
+                    let start = stack.iter().position(|(id, _)| *id == next).unwrap();
+                    return Err(GraphError::Cycle(stack[start..].iter().map(|(id, _)| *id).collect()));
+                }
+                Some(_) => {}
+                None => {
+                    colour[vertex] = Colour::Black;
+                    order.push(vertex);
+                    stack.pop();
+                }
+            }
+        }
+    }
+    order.reverse();
+    Ok(order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Graph;
+
+    #[test]
+    fn test_topological_sort() {
+        // Getting dressed
+        let mut graph = Graph::<(), f64>::directed();
+        for label in ["undershorts", "pants", "belt", "shirt", "tie", "jacket", "socks", "shoes", "watch"] {
+            let _ = graph.add_vertex(label, ());
+        }
+        let edges = [("undershorts", "pants"), ("undershorts", "shoes"), ("pants", "belt"), ("pants", "shoes"),
+            ("belt", "jacket"), ("shirt", "belt"), ("shirt", "tie"), ("tie", "jacket"), ("socks", "shoes")];
+        for (src, dest) in edges {
+            let _ = graph.add_edge(graph.id(src).unwrap(), graph.id(dest).unwrap(), 1.0);
+        }
+
+        let result = topological_sort(&graph);
+        assert!(result.is_ok());
+        let order = result.unwrap();
+        let labels = order.iter().map(|id| graph.label(*id).unwrap()).collect::<Vec<_>>();
+        assert_eq!(labels, vec!["watch", "socks", "shirt", "tie", "undershorts", "pants", "shoes", "belt", "jacket"]);
+
+        let mut position = vec![0; graph.size()];
+        for (index, id) in order.iter().enumerate() {
+            position[*id] = index;
+        }
+        assert!(graph.all_edges().all(|(src, dest, _)| position[src] < position[dest]));
+    }
+
+    #[test]
+    fn test_cycle() {
+        let mut graph = Graph::new(5);
+        let _ = graph.add_edge(0, 1, 1.0);
+        let _ = graph.add_edge(1, 2, 1.0);
+        let _ = graph.add_edge(2, 3, 1.0);
+        let _ = graph.add_edge(3, 4, 1.0);
+        let _ = graph.add_edge(3, 1, 1.0);
+        assert_eq!(topological_sort(&graph), Err(GraphError::Cycle(vec![1, 2, 3])));
+
+        // A self loop is a cycle too
+        let mut graph = Graph::new(2);
+        let _ = graph.add_edge(1, 1, 1.0);
+        assert_eq!(topological_sort(&graph), Err(GraphError::Cycle(vec![1])));
+    }
+
+    #[test]
+    fn test_undirected() {
+        // Every undirected edge goes both ways so there is no order
+        let graph = Graph::undirected_from_edges(2, &[(0, 1, 1.0)]).unwrap();
+        assert_eq!(topological_sort(&graph), Err(GraphError::NotDirected));
+        assert_eq!(topological_sort(&Graph::<(), f64>::undirected()), Err(GraphError::NotDirected));
+    }
+}
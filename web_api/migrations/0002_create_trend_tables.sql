@@ -0,0 +1,48 @@
+-- Previous term data copied from Canvas by the trends sync (see src/sync.rs)
+
+CREATE TABLE trend_courses (
+    id INTEGER PRIMARY KEY,
+    code TEXT NOT NULL,
+    term TEXT NOT NULL,
+    students INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE TABLE trend_assignments (
+    id INTEGER PRIMARY KEY,
+    course_id INTEGER NOT NULL REFERENCES trend_courses (id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    points_possible DOUBLE PRECISION NOT NULL DEFAULT 0,
+    assignment_group_id INTEGER NOT NULL DEFAULT 0,
+    due_at TIMESTAMPTZ
+);
+
+-- A student can be enrolled in more than one course
+CREATE TABLE trend_students (
+    id INTEGER NOT NULL,
+    course_id INTEGER NOT NULL REFERENCES trend_courses (id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    curr_grade TEXT NOT NULL DEFAULT '-',
+    curr_score REAL NOT NULL DEFAULT 0,
+    PRIMARY KEY (course_id, id)
+);
+
+CREATE TABLE trend_submissions (
+    assignment_id INTEGER NOT NULL REFERENCES trend_assignments (id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL,
+    attempt INTEGER NOT NULL DEFAULT 0,
+    score DOUBLE PRECISION,
+    missing BOOLEAN NOT NULL DEFAULT FALSE,
+    excused BOOLEAN NOT NULL DEFAULT FALSE,
+    late BOOLEAN NOT NULL DEFAULT FALSE,
+    current_submission BOOLEAN NOT NULL DEFAULT TRUE,
+    submitted_at TIMESTAMPTZ,
+    PRIMARY KEY (assignment_id, user_id)
+);
+
+-- Joins used by routes_trends.rs (course.id = asn.course_id is covered by the primary key)
+--    asn.course_id = ANY($1)
+--    stu.id = sub.user_id (stu.course_id is covered by the primary key)
+--    asn.id = sub.assignment_id (covered by the primary key)
+CREATE INDEX trend_assignments_course_id ON trend_assignments (course_id);
+CREATE INDEX trend_students_id ON trend_students (id);
+CREATE INDEX trend_submissions_user_id ON trend_submissions (user_id);
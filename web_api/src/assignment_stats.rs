@@ -0,0 +1,344 @@
+use rocket::http::Status;
+use rocket::serde::Serialize;
+use rocket_db_pools::Connection;
+use rocket_db_pools::sqlx;
+use utoipa::ToSchema;
+
+use crate::auth::{Auth, Role};
+use crate::config::Config;
+use crate::database::DBPool;
+use crate::response::{ApiResponse, Rows};
+
+const DEFAULT_BIN_WIDTH : f64 = 10.0;
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct QueryAssignment {
+    name : String,
+    points_possible : f64,
+}
+
+// One submission of the assignment.  Grades are percents of the points
+// possible (null when not graded).
+#[derive(Debug, sqlx::FromRow)]
+pub struct QueryAssignmentScore {
+    term : String,
+    grade : Option<f64>,
+    submitted : bool,
+    late : bool,
+}
+
+#[derive(Debug, Serialize, PartialEq, Default, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Statistics {
+    pub count : usize,
+    pub mean : f64,
+    pub median : f64,
+    pub std_dev : f64,
+    pub min : f64,
+    pub q1 : f64,
+    pub q3 : f64,
+    pub max : f64,
+    pub zero_fraction : f64,
+}
+
+#[derive(Debug, Serialize, PartialEq, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Bin {
+    pub start : f64,
+    pub end : f64,
+    pub count : usize,
+}
+
+#[derive(Debug, Serialize, PartialEq, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct TermStatistics {
+    pub term : String,
+    pub statistics : Statistics,
+    pub late_rate : f64,
+}
+
+#[derive(Debug, Serialize, Default, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct AssignmentStats {
+    pub name : String,
+    pub points_possible : f64,
+    pub statistics : Option<Statistics>,
+    pub late_rate : f64,
+    pub histogram : Vec<Bin>,
+    // Same assignment name in the trend data, all prior terms together and
+    // then one entry per term
+    pub prior : Option<Statistics>,
+    pub prior_terms : Vec<TermStatistics>,
+    // Current mean minus the prior mean
+    pub mean_change : Option<f64>,
+}
+
+impl Rows for AssignmentStats {
+    fn rows(&self) -> usize {
+        self.statistics.as_ref().map(|statistics| statistics.count).unwrap_or(0)
+    }
+}
+
+// Linear interpolation between the closest ranks (values must be sorted)
+fn percentile(sorted : &[f64], fraction : f64) -> f64 {
+    let position = fraction * (sorted.len() - 1) as f64;
+    let lower = position.floor() as usize;
+    let upper = position.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (position - lower as f64)
+}
+
+impl Statistics {
+    // Population statistics of the grades (None if there are no grades)
+    pub fn from_grades(grades : &[f64]) -> Option<Statistics> {
+        if grades.is_empty() {
+            return None;
+        }
+        let mut sorted = grades.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let count = sorted.len();
+        let mean = sorted.iter().sum::<f64>() / count as f64;
+        let variance = sorted.iter().map(|grade| (grade - mean).powi(2)).sum::<f64>() / count as f64;
+        Some(Statistics {
+            count,
+            mean,
+            median : percentile(&sorted, 0.5),
+            std_dev : variance.sqrt(),
+            min : sorted[0],
+            q1 : percentile(&sorted, 0.25),
+            q3 : percentile(&sorted, 0.75),
+            max : sorted[count - 1],
+            zero_fraction : sorted.iter().filter(|grade| **grade == 0.0).count() as f64 / count as f64,
+        })
+    }
+}
+
+// Bins from 0 to 100 percent.  Grades above 100 (extra credit) go in the
+// last bin.
+pub fn histogram(grades : &[f64], bin_width : f64) -> Vec<Bin> {
+    let bins = (100.0 / bin_width).ceil() as usize;
+    let mut counts = vec![0; bins];
+    for grade in grades {
+        let index = ((grade / bin_width).floor().max(0.0) as usize).min(bins - 1);
+        counts[index] += 1;
+    }
+    counts.into_iter()
+        .enumerate()
+        .map(|(index, count)| Bin {
+            start : index as f64 * bin_width,
+            end : ((index + 1) as f64 * bin_width).min(100.0),
+            count
+        })
+        .collect()
+}
+
+fn grades(scores : &[&QueryAssignmentScore]) -> Vec<f64> {
+    scores.iter().filter_map(|score| score.grade).collect()
+}
+
+// Fraction of the submitted work that was late
+fn late_rate(scores : &[&QueryAssignmentScore]) -> f64 {
+    let submitted = scores.iter().filter(|score| score.submitted).count();
+    if submitted == 0 {
+        return 0.0;
+    }
+    scores.iter().filter(|score| score.submitted && score.late).count() as f64 / submitted as f64
+}
+
+pub fn assignment_stats(name : String, points_possible : f64, bin_width : f64,
+                        current : &[QueryAssignmentScore], prior : &[QueryAssignmentScore]) -> AssignmentStats {
+    let current = current.iter().collect::<Vec<_>>();
+    let current_grades = grades(&current);
+    let statistics = Statistics::from_grades(&current_grades);
+
+    // Prior rows are ordered by term so each term is a consecutive run
+    let mut terms : Vec<(String, Vec<&QueryAssignmentScore>)> = Vec::new();
+    for score in prior {
+        match terms.last_mut() {
+            Some((term, list)) if *term == score.term => list.push(score),
+            _ => terms.push((score.term.clone(), vec![score]))
+        }
+    }
+    let prior_terms = terms.iter()
+        .filter_map(|(term, list)| Statistics::from_grades(&grades(list))
+            .map(|statistics| TermStatistics {term : term.clone(), statistics, late_rate : late_rate(list)}))
+        .collect::<Vec<TermStatistics>>();
+    let prior = Statistics::from_grades(&grades(&prior.iter().collect::<Vec<_>>()));
+
+    let mean_change = match (&statistics, &prior) {
+        (Some(current), Some(prior)) => Some(current.mean - prior.mean),
+        _ => None
+    };
+    AssignmentStats {
+        name,
+        points_possible,
+        statistics,
+        late_rate : late_rate(&current),
+        histogram : histogram(&current_grades, bin_width),
+        prior,
+        prior_terms,
+        mean_change,
+    }
+}
+
+/// Score distribution of one assignment compared with the same assignment in prior terms (viewer role).
+#[utoipa::path(
+    tag = "current",
+    params(("course" = String, Path, description = "Course from [current_courses]"),
+           ("assignment" = i32, Path, description = "Canvas assignment id"),
+           ("bin_width" = Option<f64>, Query, description = "Histogram bin width in percent (1 to 50, default 10)")),
+    responses(
+        (status = 200, description = "Assignment statistics", body = AssignmentResponse),
+        (status = 400, description = "Invalid bin_width", body = AssignmentResponse),
+        (status = 401, description = "Missing or invalid token", body = AssignmentResponse),
+        (status = 403, description = "Role or course not allowed", body = AssignmentResponse),
+        (status = 404, description = "Unknown course or assignment", body = AssignmentResponse),
+        (status = 500, description = "Server error (details are logged)", body = AssignmentResponse)),
+    security(("bearer" = []))
+)]
+#[get("/current/assignment/<course>/<assignment>?<bin_width>")]
+pub async fn route_current_assignment(auth : Auth,
+                                      mut db: Connection<DBPool>,
+                                      config : &Config,
+                                      course : &str,
+                                      assignment : i32,
+                                      bin_width : Option<&str>) -> Result<ApiResponse<AssignmentStats>, Status> {
+    auth.require(course, Role::Viewer)?;
+    let bin_width = match bin_width.map(|width| width.parse::<f64>()) {
+        None => DEFAULT_BIN_WIDTH,
+        Some(Ok(width)) if (1.0..=50.0).contains(&width) => width,
+        Some(_) => return Ok(ApiResponse::error(Status::BadRequest, "Invalid bin_width (1 to 50)"))
+    };
+    let course_id = match config.current_courses.get(course) {
+        Some(id) => *id,
+        None => {
+            return Ok(ApiResponse::error(Status::NotFound, &format!("Invalid Course: {course}")));
+        }
+    };
+
+    let query_assignment = sqlx::query_as::<_,QueryAssignment>(
+            "
+            SELECT name, points_possible
+            FROM curr_assignments
+            WHERE id = $1 and course_id = $2 and points_possible > 0;
+        ")
+        .bind(assignment)
+        .bind(course_id)
+        .fetch_optional(&mut **db)
+        .await;
+    let found = match query_assignment {
+        Ok(Some(found)) => found,
+        Ok(None) => return Ok(ApiResponse::error(Status::NotFound, &format!("Invalid Assignment: {assignment}"))),
+        Err(error) => return Ok(ApiResponse::server_error("SQL Error", error))
+    };
+
+    let query_current = sqlx::query_as::<_,QueryAssignmentScore>(
+            "
+            SELECT
+                course.term,
+                sub.score / asn.points_possible * 100 as grade,
+                (COALESCE(sub.attempt, 0) > 0 or sub.submitted_at IS NOT NULL) as submitted,
+                sub.late
+            FROM curr_submissions AS sub
+            INNER JOIN curr_assignments AS asn
+                ON asn.id = sub.assignment_id
+            INNER JOIN curr_courses AS course
+                ON course.id = asn.course_id
+            INNER JOIN curr_students AS stu
+                ON stu.id = sub.user_id and stu.course_id = asn.course_id
+            WHERE asn.id = $1 and asn.course_id = $2 and NOT sub.excused;
+        ")
+        .bind(assignment)
+        .bind(course_id)
+        .fetch_all(&mut **db)
+        .await;
+    let current = match query_current {
+        Ok(current) => current,
+        Err(error) => return Ok(ApiResponse::server_error("SQL Error", error))
+    };
+
+    // The same assignment name in the prior terms of this course
+    let sections = config.trends_config.get(course)
+        .map(|sections| sections.iter().map(|section| section.0).filter(|id| *id != course_id).collect::<Vec<i32>>())
+        .unwrap_or_default();
+    let query_prior = sqlx::query_as::<_,QueryAssignmentScore>(
+            "
+            SELECT
+                course.term,
+                sub.score / asn.points_possible * 100 as grade,
+                (COALESCE(sub.attempt, 0) > 0 or sub.submitted_at IS NOT NULL) as submitted,
+                sub.late
+            FROM trend_submissions AS sub
+            INNER JOIN trend_assignments AS asn
+                ON asn.id = sub.assignment_id
+            INNER JOIN trend_courses AS course
+                ON course.id = asn.course_id
+            INNER JOIN trend_students AS stu
+                ON stu.id = sub.user_id and stu.course_id = asn.course_id
+            WHERE asn.name = $1 and asn.course_id = ANY($2) and asn.points_possible > 0 and NOT sub.excused
+            ORDER BY course.term;
+        ")
+        .bind(&found.name)
+        .bind(&sections)
+        .fetch_all(&mut **db)
+        .await;
+    let prior = match query_prior {
+        Ok(prior) => prior,
+        Err(error) => return Ok(ApiResponse::server_error("SQL Error", error))
+    };
+
+    Ok(ApiResponse::ok(assignment_stats(found.name, found.points_possible, bin_width, &current, &prior)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(term : &str, grade : Option<f64>, late : bool) -> QueryAssignmentScore {
+        QueryAssignmentScore {term : term.to_string(), grade, submitted : grade.is_some(), late}
+    }
+
+    #[test]
+    fn test_statistics() {
+        assert_eq!(Statistics::from_grades(&[]), None);
+        let statistics = Statistics::from_grades(&[100.0, 0.0, 80.0, 60.0]).unwrap();
+        assert_eq!(statistics, Statistics {
+            count : 4,
+            mean : 60.0,
+            median : 70.0,
+            std_dev : 1400.0_f64.sqrt(),
+            min : 0.0,
+            q1 : 45.0,
+            q3 : 85.0,
+            max : 100.0,
+            zero_fraction : 0.25,
+        });
+    }
+
+    #[test]
+    fn test_histogram() {
+        let bins = histogram(&[0.0, 9.9, 10.0, 95.0, 100.0, 110.0], 25.0);
+        assert_eq!(bins.iter().map(|bin| bin.count).collect::<Vec<usize>>(), vec![3, 0, 0, 3]);
+        assert_eq!((bins[3].start, bins[3].end), (75.0, 100.0));
+
+        // A width that does not divide 100 ends with a short bin
+        let bins = histogram(&[95.0], 30.0);
+        assert_eq!(bins.len(), 4);
+        assert_eq!((bins[3].start, bins[3].end, bins[3].count), (90.0, 100.0, 1));
+    }
+
+    #[test]
+    fn test_prior_terms() {
+        let current = vec![score("2025-1W", Some(90.0), true), score("2025-1W", Some(70.0), false), score("2025-1W", None, false)];
+        let prior = vec![
+            score("2024-3M", Some(60.0), true),
+            score("2024-3M", Some(80.0), true),
+            score("2024-4F", Some(70.0), false),
+        ];
+        let stats = assignment_stats("W01 Prove".to_string(), 100.0, 10.0, &current, &prior);
+        assert_eq!(stats.rows(), 2);
+        assert_eq!(stats.late_rate, 0.5);
+        assert_eq!(stats.mean_change, Some(10.0));
+        assert_eq!(stats.prior_terms.iter().map(|term| (term.term.as_str(), term.statistics.mean, term.late_rate)).collect::<Vec<_>>(),
+            vec![("2024-3M", 70.0, 1.0), ("2024-4F", 70.0, 0.0)]);
+    }
+}
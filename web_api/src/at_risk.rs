@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+use rocket::http::Status;
+use rocket::serde::Serialize;
+use rocket_db_pools::Connection;
+use rocket_db_pools::sqlx;
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::auth::{Auth, Role};
+use crate::config::Config;
+use crate::database::DBPool;
+use crate::response::ApiResponse;
+
+// Thresholds and weights from the [at_risk] section of Horizons.toml.  A
+// student is flagged for each measure that reaches its threshold.  The risk
+// score is the weighted sum of each measure divided by its threshold (capped
+// at 2) so a measure at its threshold adds its full weight.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RiskConfig {
+    // Fraction of assignments that are missing
+    pub missing_ratio : f64,
+    // Drop in percent between earlier assignments and the recent ones
+    pub score_drop : f64,
+    // Number of assignments (by due date) counted as recent
+    pub recent_assignments : usize,
+    // Days since the last submission
+    pub inactive_days : f64,
+    // Percent below the prior term average on the same assignments
+    pub prior_gap : f64,
+    pub missing_weight : f64,
+    pub trend_weight : f64,
+    pub inactive_weight : f64,
+    pub prior_weight : f64,
+}
+
+impl Default for RiskConfig {
+    fn default() -> Self {
+        RiskConfig {
+            missing_ratio : 0.25,
+            score_drop : 10.0,
+            recent_assignments : 4,
+            inactive_days : 7.0,
+            prior_gap : 10.0,
+            missing_weight : 0.4,
+            trend_weight : 0.2,
+            inactive_weight : 0.2,
+            prior_weight : 0.2,
+        }
+    }
+}
+
+// One submission per row, ordered by student and due date
+#[derive(Debug, sqlx::FromRow)]
+pub struct QuerySubmissionRow {
+    pub user_id : i32,
+    pub name : String,
+    pub curr_score : f32,
+    pub assignment : String,
+    pub points_possible : f64,
+    pub score : Option<f64>,
+    pub missing : bool,
+    pub excused : bool,
+    // Days between the submission and the end of the reporting window
+    pub days_since : Option<f64>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct QueryPriorGrade {
+    name : String,
+    avg_grade : Option<f64>,
+}
+
+#[derive(Debug, Serialize, PartialEq, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct AtRiskStudent {
+    pub name : String,
+    pub curr_score : f32,
+    pub risk_score : f64,
+    pub missing_ratio : f64,
+    // Recent average minus earlier average (negative is a drop)
+    pub score_trend : Option<f64>,
+    pub days_since_submission : Option<f64>,
+    // Prior term average minus the student average on the same assignments
+    pub prior_gap : Option<f64>,
+    pub reasons : Vec<String>,
+}
+
+fn average(values : &[f64]) -> Option<f64> {
+    if values.is_empty() { None } else { Some(values.iter().sum::<f64>() / values.len() as f64) }
+}
+
+fn weighted(value : f64, threshold : f64, weight : f64) -> f64 {
+    if threshold <= 0.0 {
+        return 0.0;
+    }
+    (value / threshold).clamp(0.0, 2.0) * weight
+}
+
+fn assess_student(rows : &[&QuerySubmissionRow], prior : &HashMap<String, f64>, config : &RiskConfig) -> AtRiskStudent {
+    let counted = rows.iter().filter(|row| !row.excused).collect::<Vec<_>>();
+    let missing = counted.iter().filter(|row| row.missing).count();
+    let missing_ratio = if counted.is_empty() { 0.0 } else { missing as f64 / counted.len() as f64 };
+
+    // Percent on each graded assignment in due date order
+    let grades = counted.iter()
+        .filter_map(|row| row.score.map(|score| (row, score / row.points_possible * 100.0)))
+        .collect::<Vec<_>>();
+    let percents = grades.iter().map(|(_, percent)| *percent).collect::<Vec<f64>>();
+    let split = percents.len().saturating_sub(config.recent_assignments);
+    let score_trend = match (average(&percents[..split]), average(&percents[split..])) {
+        (Some(earlier), Some(recent)) => Some(recent - earlier),
+        _ => None
+    };
+
+    let days_since_submission = rows.iter()
+        .filter_map(|row| row.days_since)
+        .min_by(|a, b| a.total_cmp(b));
+
+    let (student, previous) : (Vec<f64>, Vec<f64>) = grades.iter()
+        .filter_map(|(row, percent)| prior.get(&row.assignment).map(|prior| (*percent, *prior)))
+        .unzip();
+    let prior_gap = match (average(&student), average(&previous)) {
+        (Some(student), Some(previous)) => Some(previous - student),
+        _ => None
+    };
+
+    let mut reasons = Vec::new();
+    let mut risk_score = weighted(missing_ratio, config.missing_ratio, config.missing_weight);
+    if missing_ratio >= config.missing_ratio {
+        reasons.push(format!("Missing {missing} of {} assignments", counted.len()));
+    }
+    if let Some(trend) = score_trend {
+        risk_score += weighted(-trend, config.score_drop, config.trend_weight);
+        if -trend >= config.score_drop {
+            reasons.push(format!("Recent scores dropped {:.1}%", -trend));
+        }
+    }
+    match days_since_submission {
+        Some(days) => {
+            risk_score += weighted(days, config.inactive_days, config.inactive_weight);
+            if days >= config.inactive_days {
+                reasons.push(format!("No submissions in {days:.0} days"));
+            }
+        }
+        None => {
+            risk_score += 2.0 * config.inactive_weight;
+            reasons.push("No submissions".to_string());
+        }
+    }
+    if let Some(gap) = prior_gap {
+        risk_score += weighted(gap, config.prior_gap, config.prior_weight);
+        if gap >= config.prior_gap {
+            reasons.push(format!("{gap:.1}% below prior terms on the same assignments"));
+        }
+    }
+
+    AtRiskStudent {
+        name : rows.first().map(|row| row.name.clone()).unwrap_or_default(),
+        curr_score : rows.first().map(|row| row.curr_score).unwrap_or_default(),
+        risk_score,
+        missing_ratio,
+        score_trend,
+        days_since_submission,
+        prior_gap,
+        reasons,
+    }
+}
+
+// Score every student and rank them with the highest risk first.  Rows must
+// be ordered by due date within each student.
+pub fn assess(rows : &[QuerySubmissionRow], prior : &HashMap<String, f64>, config : &RiskConfig) -> Vec<AtRiskStudent> {
+    let mut students : Vec<(i32, Vec<&QuerySubmissionRow>)> = Vec::new();
+    for row in rows {
+        match students.last_mut() {
+            Some((user_id, list)) if *user_id == row.user_id => list.push(row),
+            _ => students.push((row.user_id, vec![row]))
+        }
+    }
+    let mut results = students.iter()
+        .map(|(_, list)| assess_student(list, prior, config))
+        .collect::<Vec<AtRiskStudent>>();
+    results.sort_by(|a, b| b.risk_score.total_cmp(&a.risk_score).then_with(|| a.name.cmp(&b.name)));
+    results
+}
+
+/// Students ranked by risk score with the reasons each was flagged (instructor role).
+/// The thresholds are in the [at_risk] section of Horizons.toml.
+#[utoipa::path(
+    tag = "current",
+    params(("course" = String, Path, description = "Course from [current_courses]")),
+    responses(
+        (status = 200, description = "Students with the highest risk first", body = AtRiskResponse),
+        (status = 401, description = "Missing or invalid token", body = AtRiskResponse),
+        (status = 403, description = "Role or course not allowed", body = AtRiskResponse),
+        (status = 404, description = "Unknown course", body = AtRiskResponse),
+        (status = 500, description = "Server error (details are logged)", body = AtRiskResponse)),
+    security(("bearer" = []))
+)]
+#[get("/current/at_risk/<course>")]
+pub async fn route_current_at_risk(auth : Auth,
+                                   mut db: Connection<DBPool>,
+                                   config : &Config,
+                                   course : &str) -> Result<ApiResponse<Vec<AtRiskStudent>>, Status> {
+    auth.require(course, Role::Instructor)?;
+    let course_id = match config.current_courses.get(course) {
+        Some(id) => *id,
+        None => {
+            return Ok(ApiResponse::error(Status::NotFound, &format!("Invalid Course: {course}")));
+        }
+    };
+
+    // Inactivity is measured to the latest due date that has passed so a
+    // finished term does not flag every student.
+    let query_rows = sqlx::query_as::<_,QuerySubmissionRow>(
+            "
+            WITH window_end AS (
+                SELECT LEAST(now(), COALESCE(MAX(due_at), now())) AS at
+                FROM curr_assignments
+                WHERE course_id = $1 and due_at <= now()
+            )
+            SELECT
+                stu.id as user_id,
+                stu.name,
+                stu.curr_score,
+                asn.name as assignment,
+                asn.points_possible,
+                sub.score,
+                sub.missing,
+                sub.excused,
+                GREATEST(EXTRACT(EPOCH FROM (window_end.at - sub.submitted_at))::float8 / 86400, 0) as days_since
+            FROM curr_students AS stu
+            INNER JOIN curr_submissions AS sub
+                ON stu.id = sub.user_id
+            INNER JOIN curr_assignments AS asn
+                ON asn.id = sub.assignment_id
+            CROSS JOIN window_end
+            WHERE asn.course_id = $1 and stu.course_id = $1 and asn.points_possible > 0
+                and (asn.due_at IS NULL or asn.due_at <= window_end.at or sub.submitted_at IS NOT NULL)
+            ORDER BY stu.id, asn.due_at NULLS LAST, asn.id;
+        ")
+        .bind(course_id)
+        .fetch_all(&mut **db)
+        .await;
+    let rows = match query_rows {
+        Ok(rows) => rows,
+        Err(error) => return Ok(ApiResponse::server_error("SQL Error", error))
+    };
+
+    // Average grade on each assignment in the prior terms of this course
+    let sections = config.trends_config.get(course)
+        .map(|sections| sections.iter().map(|section| section.0).filter(|id| *id != course_id).collect::<Vec<i32>>())
+        .unwrap_or_default();
+    let query_prior = sqlx::query_as::<_,QueryPriorGrade>(
+            "
+            SELECT
+                asn.name,
+                AVG(sub.score / asn.points_possible * 100) as avg_grade
+            FROM trend_assignments AS asn
+            INNER JOIN trend_submissions AS sub
+                ON asn.id = sub.assignment_id
+            WHERE asn.course_id = ANY($1) and asn.points_possible > 0
+            GROUP BY asn.name;
+        ")
+        .bind(&sections)
+        .fetch_all(&mut **db)
+        .await;
+    let prior = match query_prior {
+        Ok(prior) => prior.into_iter()
+            .filter_map(|row| row.avg_grade.map(|grade| (row.name, grade)))
+            .collect::<HashMap<String, f64>>(),
+        Err(error) => return Ok(ApiResponse::server_error("SQL Error", error))
+    };
+
+    Ok(ApiResponse::ok(assess(&rows, &prior, &config.at_risk)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(user_id : i32, assignment : &str, score : Option<f64>, days_since : Option<f64>) -> QuerySubmissionRow {
+        QuerySubmissionRow {
+            user_id,
+            name : format!("Student {user_id}"),
+            curr_score : 80.0,
+            assignment : assignment.to_string(),
+            points_possible : 100.0,
+            score,
+            missing : score.is_none(),
+            excused : false,
+            days_since,
+        }
+    }
+
+    #[test]
+    fn test_steady_student() {
+        let rows = (1..=6).map(|week| row(1, &format!("W0{week}"), Some(90.0), Some(2.0))).collect::<Vec<_>>();
+        let results = assess(&rows, &HashMap::new(), &RiskConfig::default());
+        assert_eq!(results.len(), 1);
+        assert!(results[0].reasons.is_empty());
+        assert_eq!(results[0].score_trend, Some(0.0));
+    }
+
+    #[test]
+    fn test_reasons_and_ranking() {
+        let config = RiskConfig {recent_assignments : 2, ..Default::default()};
+        let mut rows = vec![
+            row(1, "W01", Some(95.0), Some(30.0)),
+            row(1, "W02", Some(95.0), Some(23.0)),
+            row(1, "W03", Some(60.0), Some(16.0)),
+            row(1, "W04", None, None),
+        ];
+        rows.extend((1..=4).map(|week| row(2, &format!("W0{week}"), Some(85.0), Some(1.0))));
+        let prior = HashMap::from([("W01".to_string(), 90.0), ("W02".to_string(), 99.0)]);
+        let results = assess(&rows, &prior, &config);
+
+        assert_eq!(results[0].name, "Student 1");
+        assert_eq!(results[0].missing_ratio, 0.25);
+        assert_eq!(results[0].score_trend, Some(-17.5));
+        assert_eq!(results[0].days_since_submission, Some(16.0));
+        assert_eq!(results[0].reasons, vec![
+            "Missing 1 of 4 assignments".to_string(),
+            "Recent scores dropped 17.5%".to_string(),
+            "No submissions in 16 days".to_string(),
+        ]);
+
+        // 94.5 prior against 85 on the same assignments
+        assert_eq!(results[1].prior_gap, Some(9.5));
+        assert!(results[1].reasons.is_empty());
+        assert!(results[0].risk_score > results[1].risk_score);
+    }
+
+    #[test]
+    fn test_no_submissions() {
+        let rows = vec![row(1, "W01", None, None), row(1, "W02", None, None)];
+        let results = assess(&rows, &HashMap::new(), &RiskConfig::default());
+        assert_eq!(results[0].reasons, vec!["Missing 2 of 2 assignments".to_string(), "No submissions".to_string()]);
+        assert_eq!(results[0].score_trend, None);
+    }
+}
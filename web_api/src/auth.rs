@@ -0,0 +1,324 @@
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hmac::{Hmac, Mac};
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::{Deserialize, Serialize, json::serde_json};
+use sha2::{Digest, Sha256};
+
+use crate::config::{self, Config};
+
+type HmacSha256 = Hmac<Sha256>;
+
+// Environment variable with the JWT signing key.  It replaces jwt_secret from
+// the config file so the key doesn't have to be committed.
+pub const JWT_SECRET_ENV : &str = "HORIZONS_JWT_SECRET";
+
+// The key from the example config, which anyone could use to sign tokens
+pub const PLACEHOLDER_SECRET : &str = "change-this-secret";
+
+// Roles are ordered so that a higher role can do everything a lower role can.
+//   - Viewer: aggregate grades and trends
+//   - Instructor: individual student data
+//   - Admin: every course and the admin routes
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Instructor,
+    Admin
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct ApiKey {
+    pub name : String,
+    pub hash : String,
+    pub role : Role,
+    #[serde(default)]
+    pub courses : Vec<String>
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(crate = "rocket::serde")]
+pub struct AuthConfig {
+    pub jwt_secret : String,
+    #[serde(default)]
+    pub api_keys : Vec<ApiKey>
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct Claims {
+    pub sub : String,
+    pub role : Role,
+    pub courses : Vec<String>,
+    pub exp : u64
+}
+
+// Request guard for an authenticated caller.  Routes call require to check
+// the course and role scope.
+#[derive(Debug, Clone)]
+pub struct Auth {
+    pub subject : String,
+    pub role : Role,
+    pub courses : Vec<String>
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn signature(secret : &str, signing_input : &str) -> Result<HmacSha256, String> {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes())
+        .map_err(|e| format!("Invalid Secret: {e}"))?;
+    mac.update(signing_input.as_bytes());
+    Ok(mac)
+}
+
+// Create an HS256 JWT for the claims
+pub fn mint_token(secret : &str, claims : &Claims) -> Result<String, String> {
+    let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"HS256","typ":"JWT"}"#);
+    let payload = serde_json::to_vec(claims)
+        .map_err(|e| format!("Invalid Claims: {e}"))?;
+    let signing_input = format!("{header}.{}", URL_SAFE_NO_PAD.encode(payload));
+    let mac = signature(secret, &signing_input)?;
+    Ok(format!("{signing_input}.{}", URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())))
+}
+
+// Verify the signature and expiration of an HS256 JWT.  Any other
+// algorithm (including "none") is rejected.
+pub fn verify_token(secret : &str, token : &str) -> Result<Claims, String> {
+    let (signing_input, sig) = token.rsplit_once('.')
+        .ok_or("Malformed Token")?;
+    let (header, payload) = signing_input.split_once('.')
+        .ok_or("Malformed Token")?;
+
+    let header : serde_json::Value = URL_SAFE_NO_PAD.decode(header).ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .ok_or("Malformed Token Header")?;
+    if header.get("alg").and_then(|alg| alg.as_str()) != Some("HS256") {
+        return Err("Unsupported Token Algorithm".to_string());
+    }
+
+    let sig = URL_SAFE_NO_PAD.decode(sig)
+        .map_err(|_| "Malformed Token Signature")?;
+    signature(secret, signing_input)?
+        .verify_slice(&sig)
+        .map_err(|_| "Invalid Token Signature")?;
+
+    let claims : Claims = URL_SAFE_NO_PAD.decode(payload).ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .ok_or("Malformed Token Claims")?;
+    if claims.exp <= now() {
+        return Err("Token Expired".to_string());
+    }
+    Ok(claims)
+}
+
+// API keys are stored in Horizons.toml as a hex SHA-256 hash
+pub fn hash_api_key(key : &str) -> String {
+    Sha256::digest(key.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+impl AuthConfig {
+
+    // Use the key from HORIZONS_JWT_SECRET (if set) instead of the file
+    pub fn secret_from_env(&mut self, secret : Option<String>) {
+        if let Some(secret) = secret.filter(|secret| !secret.is_empty()) {
+            self.jwt_secret = secret;
+        }
+    }
+
+    // A token with three parts is treated as a JWT, anything else as an API key.
+    pub fn authenticate(&self, token : &str) -> Result<Auth, String> {
+        if token.split('.').count() == 3 {
+            if self.jwt_secret.is_empty() {
+                return Err("JWT Authentication Disabled".to_string());
+            }
+            let claims = verify_token(&self.jwt_secret, token)?;
+            return Ok(Auth {subject : claims.sub, role : claims.role, courses : claims.courses});
+        }
+        let hash = hash_api_key(token);
+        self.api_keys.iter()
+            .find(|key| key.hash.eq_ignore_ascii_case(&hash))
+            .map(|key| Auth {subject : key.name.clone(), role : key.role, courses : key.courses.clone()})
+            .ok_or("Invalid API Key".to_string())
+    }
+}
+
+// The secret is never printed (the config is logged at startup)
+impl fmt::Debug for AuthConfig {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AuthConfig")
+            .field("jwt_secret", &config::redacted(&self.jwt_secret))
+            .field("api_keys", &self.api_keys)
+            .finish()
+    }
+}
+
+impl Auth {
+
+    // Admins can access every course.  Everyone else needs the course
+    // listed in their token or key.
+    pub fn require(&self, course : &str, role : Role) -> Result<(), Status> {
+        self.require_role(role)?;
+        if self.allows(course) {
+            Ok(())
+        }
+        else {
+            Err(Status::Forbidden)
+        }
+    }
+
+    // Whether the course is one the token or key can see (any role)
+    pub fn allows(&self, course : &str) -> bool {
+        self.role == Role::Admin || self.courses.iter().any(|c| c == course)
+    }
+
+    pub fn require_role(&self, role : Role) -> Result<(), Status> {
+        if self.role >= role { Ok(()) } else { Err(Status::Forbidden) }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Auth {
+    type Error = String;
+
+    async fn from_request(request : &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let config = match request.guard::<&Config>().await {
+            Outcome::Success(config) => config,
+            _ => return Outcome::Error((Status::InternalServerError, "Config Not Managed".to_string()))
+        };
+        let token = match request.headers().get_one("Authorization")
+                                 .and_then(|value| value.strip_prefix("Bearer ")) {
+            Some(token) => token.trim(),
+            None => return Outcome::Error((Status::Unauthorized, "Missing Bearer Token".to_string()))
+        };
+        match config.auth.authenticate(token) {
+            Ok(auth) => Outcome::Success(auth),
+            Err(error) => Outcome::Error((Status::Unauthorized, error))
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::config::SharedConfig;
+    use rocket::http::Header;
+    use rocket::local::blocking::Client;
+
+    const SECRET : &str = "test-secret";
+
+    fn claims(role : Role, courses : &[&str], exp : u64) -> Claims {
+        Claims {
+            sub : "tester".to_string(),
+            role,
+            courses : courses.iter().map(|c| c.to_string()).collect(),
+            exp
+        }
+    }
+
+    #[get("/probe/<course>")]
+    fn probe(auth : Auth, course : &str) -> Result<String, Status> {
+        auth.require(course, Role::Instructor)?;
+        Ok(auth.subject)
+    }
+
+    // Token for the test config that is valid for a minute
+    pub(crate) fn token(role : Role, courses : &[&str]) -> String {
+        mint_token(SECRET, &claims(role, courses, now() + 60)).unwrap()
+    }
+
+    fn client() -> Client {
+        client_with(routes![probe])
+    }
+
+    // Local client with a test config (the API key is an instructor for cse210)
+    pub(crate) fn client_with(routes : Vec<rocket::Route>) -> Client {
+        let config = Config {
+            current_courses : HashMap::new(),
+            trends_config : HashMap::new(),
+            canvas : Default::default(),
+            at_risk : Default::default(),
+            cache : Default::default(),
+            auth : AuthConfig {
+                jwt_secret : SECRET.to_string(),
+                api_keys : vec![ApiKey {
+                    name : "dashboard".to_string(),
+                    hash : hash_api_key("key-123"),
+                    role : Role::Instructor,
+                    courses : vec!["cse210".to_string()]
+                }]
+            }
+        };
+        let rocket = rocket::build()
+            .manage(SharedConfig::new(config, "Horizons.toml".into()))
+            .mount("/", routes)
+            .register("/", catchers![crate::response::catch_default]);
+        Client::tracked(rocket).unwrap()
+    }
+
+    fn get(client : &Client, uri : &str, token : Option<&str>) -> Status {
+        let mut request = client.get(uri.to_string());
+        if let Some(token) = token {
+            request = request.header(Header::new("Authorization", format!("Bearer {token}")));
+        }
+        request.dispatch().status()
+    }
+
+    #[test]
+    fn test_token_round_trip() {
+        let original = claims(Role::Viewer, &["cse210"], now() + 60);
+        let token = mint_token(SECRET, &original).unwrap();
+        assert_eq!(verify_token(SECRET, &token).unwrap(), original);
+    }
+
+    #[test]
+    fn test_token_rejected() {
+        let token = mint_token(SECRET, &claims(Role::Viewer, &["cse210"], now() + 60)).unwrap();
+        assert!(verify_token("other-secret", &token).is_err());
+
+        // Change the claims without changing the signature
+        let parts = token.split('.').collect::<Vec<&str>>();
+        let forged = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims(Role::Admin, &[], now() + 60)).unwrap());
+        assert!(verify_token(SECRET, &format!("{}.{}.{}", parts[0], forged, parts[2])).is_err());
+
+        // Unsigned token
+        let none = URL_SAFE_NO_PAD.encode(r#"{"alg":"none","typ":"JWT"}"#);
+        assert!(verify_token(SECRET, &format!("{}.{}.", none, parts[1])).is_err());
+
+        let expired = mint_token(SECRET, &claims(Role::Viewer, &["cse210"], now() - 1)).unwrap();
+        assert_eq!(verify_token(SECRET, &expired), Err("Token Expired".to_string()));
+    }
+
+    #[test]
+    fn test_guard_unauthorized() {
+        let client = client();
+        assert_eq!(get(&client, "/probe/cse210", None), Status::Unauthorized);
+        assert_eq!(get(&client, "/probe/cse210", Some("wrong-key")), Status::Unauthorized);
+        assert_eq!(get(&client, "/probe/cse210", Some("a.b.c")), Status::Unauthorized);
+    }
+
+    #[test]
+    fn test_guard_scope() {
+        let client = client();
+        let instructor = mint_token(SECRET, &claims(Role::Instructor, &["cse210"], now() + 60)).unwrap();
+        let viewer = mint_token(SECRET, &claims(Role::Viewer, &["cse210"], now() + 60)).unwrap();
+        let admin = mint_token(SECRET, &claims(Role::Admin, &[], now() + 60)).unwrap();
+
+        assert_eq!(get(&client, "/probe/cse210", Some(&instructor)), Status::Ok);
+        assert_eq!(get(&client, "/probe/cse280", Some(&instructor)), Status::Forbidden);
+        assert_eq!(get(&client, "/probe/cse210", Some(&viewer)), Status::Forbidden);
+        assert_eq!(get(&client, "/probe/cse280", Some(&admin)), Status::Ok);
+        assert_eq!(get(&client, "/probe/cse210", Some("key-123")), Status::Ok);
+        assert_eq!(get(&client, "/probe/cse280", Some("key-123")), Status::Forbidden);
+    }
+}
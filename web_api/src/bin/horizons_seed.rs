@@ -0,0 +1,43 @@
+extern crate web_api;
+
+use clap::Parser;
+use rocket_db_pools::sqlx::PgPool;
+use web_api::config::Config;
+use web_api::database::MIGRATOR;
+use web_api::seed;
+
+// Command Line Setup
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Load fixture courses into a Horizons database")]
+struct Args {
+    #[clap(long, help = "Database URL (default: databases.horizons.url from Rocket.toml)")]
+    url : Option<String>
+}
+
+async fn run(args : Args) -> Result<(), String> {
+    let config = Config::load_config()?;
+    let url = match args.url {
+        Some(url) => url,
+        None => rocket::Config::figment()
+            .extract_inner::<String>("databases.horizons.url")
+            .map_err(|e| format!("No database url in Rocket.toml: {e}"))?
+    };
+    let pool = PgPool::connect(&url)
+        .await
+        .map_err(|e| format!("Unable to connect to {url}: {e}"))?;
+    MIGRATOR.run(&pool)
+        .await
+        .map_err(|e| format!("Migration Failure: {e}"))?;
+    let (current, trends) = seed::seed(&pool, &config).await?;
+    println!("Current: {:?}", current);
+    println!("Trends: {:?}", trends);
+    Ok(())
+}
+
+#[rocket::main]
+async fn main() {
+    if let Err(error) = run(Args::parse()).await {
+        println!("{}", error);
+    }
+}
@@ -0,0 +1,105 @@
+extern crate web_api;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use rand::RngCore;
+use std::time::{SystemTime, UNIX_EPOCH};
+use web_api::auth::{self, Claims, Role};
+use web_api::config::Config;
+
+// Command Line Setup
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Create Horizons API tokens")]
+struct Args {
+    #[command(subcommand)]
+    command : Command
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Sign a JWT with HORIZONS_JWT_SECRET (or the jwt_secret in Horizons.toml)
+    Jwt {
+        #[clap(long, help = "Name of the token owner")]
+        subject : String,
+
+        #[clap(long, value_enum, help = "Role")]
+        role : RoleArg,
+
+        #[clap(long = "course", help = "Course the token can access (repeat for more)")]
+        courses : Vec<String>,
+
+        #[clap(long, default_value_t = 30, help = "Days until the token expires")]
+        days : u64
+    },
+    /// Generate a random API key and the entry to add to Horizons.toml
+    ApiKey {
+        #[clap(long, help = "Name of the key owner")]
+        name : String,
+
+        #[clap(long, value_enum, help = "Role")]
+        role : RoleArg,
+
+        #[clap(long = "course", help = "Course the key can access (repeat for more)")]
+        courses : Vec<String>
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum RoleArg {
+    Viewer,
+    Instructor,
+    Admin
+}
+
+impl From<RoleArg> for Role {
+    fn from(role : RoleArg) -> Self {
+        match role {
+            RoleArg::Viewer => Role::Viewer,
+            RoleArg::Instructor => Role::Instructor,
+            RoleArg::Admin => Role::Admin
+        }
+    }
+}
+
+fn mint_jwt(subject : String, role : Role, courses : Vec<String>, days : u64) -> Result<(), String> {
+    let config = Config::load_config()?;
+    if config.auth.jwt_secret.is_empty() {
+        return Err(format!("No jwt_secret: set {}", auth::JWT_SECRET_ENV));
+    }
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)
+        .map_err(|e| e.to_string())?
+        .as_secs();
+    let claims = Claims {sub : subject, role, courses, exp : now + days * 24 * 60 * 60};
+    println!("{}", auth::mint_token(&config.auth.jwt_secret, &claims)?);
+    Ok(())
+}
+
+fn mint_api_key(name : String, role : Role, courses : Vec<String>) {
+    let mut bytes = [0_u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let key = bytes.iter().map(|byte| format!("{byte:02x}")).collect::<String>();
+    let role = format!("{:?}", role).to_lowercase();
+    let courses = courses.iter().map(|c| format!("\"{c}\"")).collect::<Vec<String>>().join(", ");
+
+    println!("API Key (give to the client, not stored): {key}");
+    println!();
+    println!("[[auth.api_keys]]");
+    println!("name = \"{name}\"");
+    println!("hash = \"{}\"", auth::hash_api_key(&key));
+    println!("role = \"{role}\"");
+    println!("courses = [{courses}]");
+}
+
+fn main() {
+    let args = Args::parse();
+    let result = match args.command {
+        Command::Jwt {subject, role, courses, days} => mint_jwt(subject, role.into(), courses, days),
+        Command::ApiKey {name, role, courses} => {
+            mint_api_key(name, role.into(), courses);
+            Ok(())
+        }
+    };
+    if let Err(error) = result {
+        println!("{}", error);
+    }
+}
@@ -0,0 +1,435 @@
+use std::cell::Cell;
+use std::collections::{HashMap, VecDeque};
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use rocket::State;
+use rocket::http::{ContentType, Header, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::{self, Responder, Response};
+use rocket::serde::{Deserialize, Serialize, json::serde_json};
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+
+use crate::auth::{Auth, Role};
+use crate::config::Config;
+use crate::response::{ApiResponse, Rows};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(crate = "rocket::serde", default)]
+pub struct CacheConfig {
+    // Seconds a response is kept (0 = no caching)
+    pub ttl_seconds : u64,
+    // Most responses kept at once, the oldest is dropped to make room
+    pub max_entries : usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {ttl_seconds : 300, max_entries : 1000}
+    }
+}
+
+// Responses are cached per route and course, with one entry for each parsed
+// query (sort, page, and filters give different responses).  The raw query
+// string is never used: unknown parameters would each add an entry.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    route : &'static str,
+    course : String,
+    query : String,
+}
+
+impl CacheKey {
+    pub fn new(route : &'static str, course : &str, query : &str) -> Self {
+        CacheKey {route, course : course.to_string(), query : query.to_string()}
+    }
+}
+
+struct Entry {
+    body : Arc<String>,
+    etag : Arc<String>,
+    created : Instant,
+    hits : u64,
+}
+
+#[derive(Default)]
+struct Entries {
+    map : HashMap<CacheKey, Entry>,
+    // Keys in the order they were stored (oldest first).  A key that was
+    // removed or stored again is skipped when its turn comes.
+    order : VecDeque<(Instant, CacheKey)>,
+    // Changed by every clear so a response read before it is not stored
+    generation : u64,
+}
+
+// Shared by the routes and the sync (which clears it after an update)
+#[derive(Clone, Default)]
+pub struct ResponseCache {
+    entries : Arc<Mutex<Entries>>,
+}
+
+// A JSON body ready to send.  The ETag and Cache-Control headers are only
+// added to successful responses.
+#[derive(Debug)]
+pub struct CachedJson {
+    status : Status,
+    body : Arc<String>,
+    etag : Option<Arc<String>>,
+    max_age : u64,
+}
+
+#[derive(Debug, Serialize, PartialEq, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct CacheEntry {
+    route : String,
+    course : String,
+    query : String,
+    bytes : usize,
+    age_seconds : u64,
+    hits : u64,
+    etag : String,
+}
+
+fn etag(body : &str) -> String {
+    let digest = Sha256::digest(body.as_bytes());
+    let hex = digest.iter().take(16).map(|byte| format!("{byte:02x}")).collect::<String>();
+    format!("\"{hex}\"")
+}
+
+impl ResponseCache {
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Entries> {
+        match self.entries.lock() {
+            Ok(entries) => entries,
+            Err(poisoned) => poisoned.into_inner()
+        }
+    }
+
+    // Cached response if it is younger than the TTL
+    pub fn get(&self, key : &CacheKey, ttl_seconds : u64) -> Option<CachedJson> {
+        let ttl = Duration::from_secs(ttl_seconds);
+        let mut entries = self.lock();
+        match entries.map.get_mut(key) {
+            Some(entry) if entry.created.elapsed() < ttl => {
+                entry.hits += 1;
+                let remaining = ttl.saturating_sub(entry.created.elapsed()).as_secs();
+                Some(CachedJson {status : Status::Ok, body : entry.body.clone(), etag : Some(entry.etag.clone()), max_age : remaining})
+            }
+            Some(_) => {
+                entries.map.remove(key);
+                None
+            }
+            None => None
+        }
+    }
+
+    // Read before the data for a response so store can tell if the cache
+    // was cleared in between
+    pub fn generation(&self) -> u64 {
+        self.lock().generation
+    }
+
+    // Serialize the response and keep it if it was successful and the cache
+    // has not been cleared since generation was read.  Expired entries are
+    // dropped first, then the oldest ones if the cache is full.
+    pub fn store<T : Serialize>(&self, key : CacheKey, generation : u64, config : &CacheConfig, response : ApiResponse<T>) -> CachedJson {
+        let json = CachedJson::from(response);
+        if json.status != Status::Ok || config.ttl_seconds == 0 || config.max_entries == 0 {
+            return json;
+        }
+        let ttl = Duration::from_secs(config.ttl_seconds);
+        let mut entries = self.lock();
+        if entries.generation != generation {
+            return json;
+        }
+        let etag = Arc::new(etag(&json.body));
+        entries.map.remove(&key);
+        while let Some((created, oldest)) = entries.order.front() {
+            let full = entries.map.len() >= config.max_entries;
+            if !full && created.elapsed() < ttl {
+                break;
+            }
+            let (created, oldest) = (*created, oldest.clone());
+            entries.order.pop_front();
+            if entries.map.get(&oldest).is_some_and(|entry| entry.created == created) {
+                entries.map.remove(&oldest);
+            }
+        }
+        let created = Instant::now();
+        entries.order.push_back((created, key.clone()));
+        entries.map.insert(key, Entry {body : json.body.clone(), etag : etag.clone(), created, hits : 0});
+        CachedJson {etag : Some(etag), max_age : config.ttl_seconds, ..json}
+    }
+
+    // Remove the entries for a route and/or course (everything if neither
+    // is given) and return what was removed.
+    pub fn clear(&self, route : Option<&str>, course : Option<&str>) -> Vec<CacheEntry> {
+        let mut entries = self.lock();
+        entries.generation += 1;
+        let keys = entries.map.keys()
+            .filter(|key| route.is_none_or(|route| key.route == route) && course.is_none_or(|course| key.course == course))
+            .cloned()
+            .collect::<Vec<CacheKey>>();
+        let mut removed = keys.into_iter()
+            .filter_map(|key| entries.map.remove(&key).map(|entry| CacheEntry::new(&key, &entry)))
+            .collect::<Vec<CacheEntry>>();
+        removed.sort_by(|a, b| (&a.route, &a.course, &a.query).cmp(&(&b.route, &b.course, &b.query)));
+        removed
+    }
+
+    // Current entries (expired entries are dropped first)
+    pub fn entries(&self, ttl_seconds : u64) -> Vec<CacheEntry> {
+        let ttl = Duration::from_secs(ttl_seconds);
+        let mut entries = self.lock();
+        entries.map.retain(|_, entry| entry.created.elapsed() < ttl);
+        let mut list = entries.map.iter()
+            .map(|(key, entry)| CacheEntry::new(key, entry))
+            .collect::<Vec<CacheEntry>>();
+        list.sort_by(|a, b| (&a.route, &a.course, &a.query).cmp(&(&b.route, &b.course, &b.query)));
+        list
+    }
+}
+
+// Request guard with the shared cache and the generation it had when the
+// request looked for a cached response
+pub struct Cache<'r> {
+    cache : &'r ResponseCache,
+    generation : Cell<u64>,
+}
+
+impl Cache<'_> {
+    // query is the normalised form of the parameters the route uses
+    pub fn key(&self, route : &'static str, course : &str, query : &str) -> CacheKey {
+        CacheKey::new(route, course, query)
+    }
+
+    // Called before the data is read from the database
+    pub fn get(&self, key : &CacheKey, ttl_seconds : u64) -> Option<CachedJson> {
+        self.generation.set(self.cache.generation());
+        self.cache.get(key, ttl_seconds)
+    }
+
+    pub fn store<T : Serialize>(&self, key : CacheKey, config : &CacheConfig, response : ApiResponse<T>) -> CachedJson {
+        self.cache.store(key, self.generation.get(), config, response)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Cache<'r> {
+    type Error = String;
+
+    async fn from_request(request : &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match request.rocket().state::<ResponseCache>() {
+            Some(cache) => Outcome::Success(Cache {cache, generation : Cell::new(cache.generation())}),
+            None => Outcome::Error((Status::InternalServerError, "Cache Not Managed".to_string()))
+        }
+    }
+}
+
+impl CacheEntry {
+    fn new(key : &CacheKey, entry : &Entry) -> Self {
+        CacheEntry {
+            route : key.route.to_string(),
+            course : key.course.clone(),
+            query : key.query.clone(),
+            bytes : entry.body.len(),
+            age_seconds : entry.created.elapsed().as_secs(),
+            hits : entry.hits,
+            etag : entry.etag.to_string(),
+        }
+    }
+}
+
+impl Rows for CacheEntry {
+    fn rows(&self) -> usize {
+        1
+    }
+}
+
+// Responses that are not cached (errors, other formats)
+impl<T : Serialize> From<ApiResponse<T>> for CachedJson {
+    fn from(response : ApiResponse<T>) -> Self {
+        match serde_json::to_string(&response) {
+            Ok(body) => CachedJson {status : response.status(), body : Arc::new(body), etag : None, max_age : 0},
+            Err(error) => {
+                let error = ApiResponse::<Vec<()>>::server_error("JSON Failure", error);
+                let body = serde_json::to_string(&error).unwrap_or_default();
+                CachedJson {status : error.status(), body : Arc::new(body), etag : None, max_age : 0}
+            }
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for CachedJson {
+    fn respond_to(self, request : &'r Request<'_>) -> response::Result<'static> {
+        let Some(etag) = self.etag else {
+            return Response::build()
+                .status(self.status)
+                .header(ContentType::JSON)
+                .sized_body(self.body.len(), Cursor::new(self.body.to_string()))
+                .ok();
+        };
+        let cache_control = Header::new("Cache-Control", format!("private, max-age={}", self.max_age));
+        let matches = request.headers().get("If-None-Match")
+            .flat_map(|value| value.split(','))
+            .map(|tag| tag.trim().trim_start_matches("W/"))
+            .any(|tag| tag == "*" || tag == etag.as_str());
+        if matches {
+            return Response::build()
+                .status(Status::NotModified)
+                .header(Header::new("ETag", etag.to_string()))
+                .header(cache_control)
+                .ok();
+        }
+        Response::build()
+            .status(self.status)
+            .header(ContentType::JSON)
+            .header(Header::new("ETag", etag.to_string()))
+            .header(cache_control)
+            .sized_body(self.body.len(), Cursor::new(self.body.to_string()))
+            .ok()
+    }
+}
+
+// Routes
+
+/// Cached responses (admin role).
+#[utoipa::path(
+    tag = "admin",
+    responses(
+        (status = 200, description = "Cache entries", body = CacheResponse),
+        (status = 401, description = "Missing or invalid token", body = CacheResponse),
+        (status = 403, description = "Role or course not allowed", body = CacheResponse)),
+    security(("bearer" = []))
+)]
+#[get("/admin/cache")]
+pub fn route_admin_cache(auth : Auth, config : &Config, cache : &State<ResponseCache>) -> Result<ApiResponse<Vec<CacheEntry>>, Status> {
+    auth.require_role(Role::Admin)?;
+    Ok(ApiResponse::ok(cache.entries(config.cache.ttl_seconds)))
+}
+
+/// Remove cached responses (admin role).
+/// Without route or course every entry is removed.
+#[utoipa::path(
+    tag = "admin",
+    params(("route" = Option<String>, Query, description = "grades or trends"),
+           ("course" = Option<String>, Query, description = "Course code")),
+    responses(
+        (status = 200, description = "Removed entries", body = CacheResponse),
+        (status = 401, description = "Missing or invalid token", body = CacheResponse),
+        (status = 403, description = "Role or course not allowed", body = CacheResponse)),
+    security(("bearer" = []))
+)]
+#[delete("/admin/cache?<route>&<course>")]
+pub fn route_admin_cache_clear(auth : Auth,
+                               cache : &State<ResponseCache>,
+                               route : Option<&str>,
+                               course : Option<&str>) -> Result<ApiResponse<Vec<CacheEntry>>, Status> {
+    auth.require_role(Role::Admin)?;
+    Ok(ApiResponse::ok(cache.clear(route, course)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::local::blocking::Client;
+
+    const CONFIG : CacheConfig = CacheConfig {ttl_seconds : 60, max_entries : 10};
+
+    fn key(route : &'static str, course : &str, query : &str) -> CacheKey {
+        CacheKey {route, course : course.to_string(), query : query.to_string()}
+    }
+
+    #[get("/cached/<course>")]
+    fn cached(cache : Cache<'_>, course : &str) -> CachedJson {
+        let key = cache.key("cached", course, "");
+        match cache.get(&key, 60) {
+            Some(hit) => hit,
+            None => cache.store(key, &CONFIG, ApiResponse::ok(vec![course.to_string()]))
+        }
+    }
+
+    #[test]
+    fn test_store_and_clear() {
+        let cache = ResponseCache::default();
+        let stored = cache.store(key("grades", "cse210", ""), 0, &CONFIG, ApiResponse::ok(vec![1, 2]));
+        cache.store(key("grades", "cse280", "sort=name"), 0, &CONFIG, ApiResponse::ok(vec![3]));
+        cache.store(key("trends", "cse280", ""), 0, &CONFIG, ApiResponse::ok(vec![4]));
+        // Errors are not kept
+        cache.store(key("trends", "cse999", ""), 0, &CONFIG, ApiResponse::<Vec<i32>>::error(Status::NotFound, "Invalid Course"));
+
+        let hit = cache.get(&key("grades", "cse210", ""), 60).unwrap();
+        assert_eq!(hit.etag, stored.etag);
+        assert!(cache.get(&key("grades", "cse210", "sort=name"), 60).is_none());
+        assert_eq!(cache.entries(60).len(), 3);
+        assert_eq!(cache.entries(60)[0].hits, 1);
+
+        // Expired entries are not returned
+        assert!(cache.get(&key("grades", "cse210", ""), 0).is_none());
+
+        let removed = cache.clear(None, Some("cse280"));
+        assert_eq!(removed.iter().map(|entry| entry.route.as_str()).collect::<Vec<&str>>(), vec!["grades", "trends"]);
+        assert!(cache.entries(60).is_empty());
+    }
+
+    #[test]
+    fn test_bounded() {
+        let cache = ResponseCache::default();
+        for page in 0..25 {
+            cache.store(key("grades", "cse210", &format!("offset {page}")), 0, &CONFIG, ApiResponse::ok(vec![page]));
+        }
+        // Only the newest entries are kept
+        let entries = cache.entries(60);
+        assert_eq!(entries.len(), 10);
+        assert!(cache.get(&key("grades", "cse210", "offset 14"), 60).is_none());
+        assert!(cache.get(&key("grades", "cse210", "offset 24"), 60).is_some());
+
+        // Storing a key again makes it the newest
+        cache.store(key("grades", "cse210", "offset 15"), 0, &CONFIG, ApiResponse::ok(vec![15]));
+        cache.store(key("grades", "cse210", "offset 25"), 0, &CONFIG, ApiResponse::ok(vec![25]));
+        assert!(cache.get(&key("grades", "cse210", "offset 15"), 60).is_some());
+        assert!(cache.get(&key("grades", "cse210", "offset 16"), 60).is_none());
+        assert_eq!(cache.entries(60).len(), 10);
+
+        // Storing drops the expired entries
+        let short = CacheConfig {ttl_seconds : 1, ..CONFIG};
+        std::thread::sleep(Duration::from_millis(1100));
+        cache.store(key("trends", "cse210", ""), 0, &short, ApiResponse::ok(vec![1]));
+        assert_eq!(cache.entries(60).len(), 1);
+    }
+
+    #[test]
+    fn test_cleared_while_reading() {
+        // A response read before a sync cleared the cache is sent but not kept
+        let cache = ResponseCache::default();
+        let generation = cache.generation();
+        cache.clear(None, None);
+        let json = cache.store(key("grades", "cse210", ""), generation, &CONFIG, ApiResponse::ok(vec![1]));
+        assert_eq!((json.status, json.etag), (Status::Ok, None));
+        assert!(cache.entries(60).is_empty());
+
+        cache.store(key("grades", "cse210", ""), cache.generation(), &CONFIG, ApiResponse::ok(vec![2]));
+        assert_eq!(cache.entries(60).len(), 1);
+    }
+
+    #[test]
+    fn test_etag() {
+        let client = Client::tracked(rocket::build()
+            .manage(ResponseCache::default())
+            .mount("/", routes![cached])).unwrap();
+
+        let response = client.get("/cached/cse210").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("Cache-Control"), Some("private, max-age=60"));
+        let etag = response.headers().get_one("ETag").unwrap().to_string();
+        assert_eq!(response.into_string().unwrap(), r#"{"count":1,"status":200,"message":"OK","data":["cse210"]}"#);
+
+        let response = client.get("/cached/cse210").header(Header::new("If-None-Match", etag.clone())).dispatch();
+        assert_eq!(response.status(), Status::NotModified);
+        assert!(response.into_string().is_none());
+
+        let response = client.get("/cached/cse280").header(Header::new("If-None-Match", etag)).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+}
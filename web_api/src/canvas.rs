@@ -0,0 +1,329 @@
+use std::fmt;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use rocket::serde::json::serde_json;
+
+use crate::macros::err;
+
+#[derive(Deserialize, Clone, Default)]
+pub struct CanvasConfig {
+    pub server : String,
+    pub token : String,
+    // Minutes between scheduled syncs (0 = only sync on demand)
+    #[serde(default)]
+    pub sync_minutes : u64,
+}
+
+// The token is never printed (the config is logged at startup)
+impl fmt::Debug for CanvasConfig {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CanvasConfig")
+            .field("server", &self.server)
+            .field("token", &crate::config::redacted(&self.token))
+            .field("sync_minutes", &self.sync_minutes)
+            .finish()
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Course {
+    pub id : i32,
+    pub course_code : String,
+    pub term : Option<CourseTerm>,
+    pub total_students : Option<i32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CourseTerm {
+    pub name : String
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Assignment {
+    pub id : i32,
+    pub name : String,
+    pub points_possible : Option<f64>,
+    pub assignment_group_id : Option<i32>,
+    pub due_at : Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Submission {
+    pub assignment_id : i32,
+    pub user_id : i32,
+    pub attempt : Option<i32>,
+    pub score : Option<f64>,
+    #[serde(default)]
+    pub missing : bool,
+    pub excused : Option<bool>,
+    #[serde(default)]
+    pub late : bool,
+    pub grade_matches_current_submission : Option<bool>,
+    pub submitted_at : Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Student {
+    pub id : i32,
+    pub name : String,
+    #[serde(default)]
+    pub enrollments : Vec<Enrollment>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Enrollment {
+    pub grades : Option<Grades>
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Grades {
+    pub current_grade : Option<String>,
+    pub current_score : Option<f32>
+}
+
+// Everything needed from Canvas to fill the *_courses, *_assignments,
+// *_submissions and *_students tables for one course.
+#[derive(Debug)]
+pub struct CourseSnapshot {
+    pub course : Course,
+    pub assignments : Vec<Assignment>,
+    pub submissions : Vec<Submission>,
+    pub students : Vec<Student>,
+}
+
+pub struct Canvas {
+    server : String,
+    token : String,
+    client : reqwest::Client,
+}
+
+// Convert a Canvas term name ("Fall 2024") into the sortable form used by
+// the trend tables ("2024-4F").  Unknown terms become an empty string.
+pub fn convert_term(term : &str) -> String {
+    let mut parts = term.split_whitespace();
+    let season = parts.next().unwrap_or("");
+    let year = parts.next().unwrap_or("");
+    let period = match season {
+        "Winter" => "1W",
+        "Spring" => "2S",
+        "Summer" => "3M",
+        "Fall" => "4F",
+        _ => return String::new()
+    };
+    format!("{}-{}",year,period)
+}
+
+impl Canvas {
+    pub fn new(config : &CanvasConfig) -> Self {
+        Canvas {
+            server : config.server.trim_end_matches('/').to_string(),
+            token : config.token.clone(),
+            client : reqwest::Client::new()
+        }
+    }
+
+    async fn get_text(&self, url : &str) -> Result<(String, Option<String>), String> {
+        let res = self.client.get(url)
+            .header("Authorization", format!("Bearer {}", self.token))
+            .send()
+            .await
+            .map_err(|e| err!("Canvas Request Failure", e))?;
+        if !res.status().is_success() {
+            return Err(err!("Canvas Request Failure", format!("{} returned {}", url, res.status())));
+        }
+        let link = res.headers().get("link")
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string());
+        // Note that text() consumes the response
+        let data = res.text()
+            .await
+            .map_err(|e| err!("Canvas Response Failure", e))?;
+        Ok((data, link))
+    }
+
+    // Get a list from the Canvas API following the pages until the link
+    // header no longer has a next page.  The rel_url must already include
+    // a query string.
+    pub async fn json_api_get<T>(&self, rel_url : &str) -> Result<Vec<T>, String>
+        where T : DeserializeOwned
+    {
+        let mut page = 1;
+        let mut all_results = Vec::<T>::new();
+
+        loop {
+            let url = format!("{}{}&page={}&per_page=100", self.server, rel_url, page);
+            let (data, link) = self.get_text(&url).await?;
+            let results : Vec<T> = serde_json::from_str(&data)
+                .map_err(|e| err!("Canvas JSON Failure", e))?;
+            all_results.extend(results);
+            match link {
+                Some(value) if value.contains("rel=\"next\"") => page += 1,
+                _ => break
+            }
+        }
+        Ok(all_results)
+    }
+
+    // Get a single object from the Canvas API
+    pub async fn json_api_get_one<T>(&self, rel_url : &str) -> Result<T, String>
+        where T : DeserializeOwned
+    {
+        let (data, _) = self.get_text(&format!("{}{}", self.server, rel_url)).await?;
+        serde_json::from_str(&data)
+            .map_err(|e| err!("Canvas JSON Failure", e))
+    }
+
+    pub async fn get_course(&self, course_id : i32) -> Result<CourseSnapshot, String> {
+        let mut course = self.json_api_get_one::<Course>(&format!(
+            "/api/v1/courses/{course_id}\
+             ?include[]=term\
+             &include[]=total_students"))
+            .await?;
+        if let Some(term) = course.term.as_mut() {
+            term.name = convert_term(&term.name);
+        }
+        let assignments = self.json_api_get::<Assignment>(&format!(
+            "/api/v1/courses/{course_id}/assignments\
+             ?order_by=position"))
+            .await?;
+        let submissions = self.json_api_get::<Submission>(&format!(
+            "/api/v1/courses/{course_id}/students/submissions\
+             ?student_ids[]=all\
+             &workflow_state[]=submitted\
+             &workflow_state[]=unsubmitted\
+             &workflow_state[]=graded\
+             &workflow_state[]=pending_review"))
+            .await?;
+        let students = self.json_api_get::<Student>(&format!(
+            "/api/v1/courses/{course_id}/users\
+             ?enrollment_type[]=student\
+             &include[]=total_scores\
+             &include[]=enrollments\
+             &enrollment_state[]=active\
+             &enrollment_state[]=invited\
+             &enrollment_state[]=completed"))
+            .await?;
+        Ok(CourseSnapshot {course, assignments, submissions, students})
+    }
+}
+
+#[cfg(test)]
+pub mod mock {
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    // A minimal Canvas server for tests.  Each path (without the query
+    // string) maps to a list of pages.  The page query parameter selects
+    // the page and a next link is sent while there are more pages.
+    pub fn start(routes : HashMap<String, Vec<String>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { break };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut command = String::new();
+                let _ = reader.read_line(&mut command);
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 || line.trim().is_empty() {
+                        break;
+                    }
+                }
+                let target = command.split(' ').nth(1).unwrap_or("/").to_string();
+                let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+                let page = query.split('&')
+                    .find_map(|pair| pair.strip_prefix("page="))
+                    .and_then(|page| page.parse::<usize>().ok())
+                    .unwrap_or(1);
+                let response = match routes.get(path).and_then(|pages| pages.get(page - 1).map(|body| (body, pages.len()))) {
+                    Some((body, pages)) => {
+                        let link = if page < pages {
+                            format!("Link: <{path}?page={}>; rel=\"next\"\r\n", page + 1)
+                        } else {
+                            String::new()
+                        };
+                        format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n{link}Content-Length: {}\r\nConnection: close\r\n\r\n{body}", body.len())
+                    }
+                    None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+                };
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+        address
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn canvas(routes : HashMap<String, Vec<String>>) -> Canvas {
+        let server = mock::start(routes);
+        Canvas::new(&CanvasConfig {server, token : "token".to_string(), sync_minutes : 0})
+    }
+
+    #[test]
+    fn test_debug_hides_token() {
+        let config = CanvasConfig {server : "https://canvas".to_string(), token : "secret-token".to_string(), sync_minutes : 5};
+        let debug = format!("{:?}", config);
+        assert!(!debug.contains("secret-token"), "{debug}");
+        assert_eq!(debug, r#"CanvasConfig { server: "https://canvas", token: "<redacted>", sync_minutes: 5 }"#);
+    }
+
+    #[test]
+    fn test_convert_term() {
+        assert_eq!(convert_term("Fall 2024"), "2024-4F");
+        assert_eq!(convert_term("Winter 2025"), "2025-1W");
+        assert_eq!(convert_term("Sandbox"), "");
+    }
+
+    #[rocket::async_test]
+    async fn test_pagination() {
+        let mut routes = HashMap::new();
+        routes.insert("/api/v1/courses/1/assignments".to_string(), vec![
+            r#"[{"id":1,"name":"W01","points_possible":10.0,"assignment_group_id":5,"due_at":null}]"#.to_string(),
+            r#"[{"id":2,"name":"W02","points_possible":null,"assignment_group_id":5,"due_at":"2024-09-10T05:59:59Z"}]"#.to_string(),
+            r#"[{"id":3,"name":"W03","points_possible":20.0,"assignment_group_id":6,"due_at":null}]"#.to_string(),
+        ]);
+        let canvas = canvas(routes);
+        let assignments = canvas.json_api_get::<Assignment>("/api/v1/courses/1/assignments?order_by=position")
+            .await
+            .unwrap();
+        let ids = assignments.iter().map(|a| a.id).collect::<Vec<i32>>();
+        assert_eq!(ids, vec![1, 2, 3]);
+        assert_eq!(assignments[1].points_possible, None);
+    }
+
+    #[rocket::async_test]
+    async fn test_get_course() {
+        let mut routes = HashMap::new();
+        routes.insert("/api/v1/courses/7".to_string(), vec![
+            r#"{"id":7,"course_code":"CSE 210","term":{"name":"Fall 2024"},"total_students":2}"#.to_string()]);
+        routes.insert("/api/v1/courses/7/assignments".to_string(), vec![
+            r#"[{"id":70,"name":"Prove 1","points_possible":100.0,"assignment_group_id":1,"due_at":null}]"#.to_string()]);
+        routes.insert("/api/v1/courses/7/students/submissions".to_string(), vec![
+            r#"[{"assignment_id":70,"user_id":1,"attempt":1,"score":90.0,"missing":false,"excused":null,"late":true,"grade_matches_current_submission":true,"submitted_at":"2024-09-01T00:00:00Z"}]"#.to_string(),
+            r#"[{"assignment_id":70,"user_id":2,"attempt":null,"score":null,"missing":true,"excused":false,"late":false,"grade_matches_current_submission":true,"submitted_at":null}]"#.to_string()]);
+        routes.insert("/api/v1/courses/7/users".to_string(), vec![
+            r#"[{"id":1,"name":"Ann","enrollments":[{"grades":{"current_grade":"A","current_score":95.0}}]},
+                {"id":2,"name":"Bob","enrollments":[]}]"#.to_string()]);
+        let canvas = canvas(routes);
+        let snapshot = canvas.get_course(7).await.unwrap();
+        assert_eq!(snapshot.course.course_code, "CSE 210");
+        assert_eq!(snapshot.course.term.unwrap().name, "2024-4F");
+        assert_eq!(snapshot.assignments.len(), 1);
+        assert_eq!(snapshot.submissions.len(), 2);
+        assert!(snapshot.submissions[1].missing);
+        assert_eq!(snapshot.students.len(), 2);
+    }
+
+    #[rocket::async_test]
+    async fn test_request_failure() {
+        let canvas = canvas(HashMap::new());
+        assert!(canvas.get_course(1).await.is_err());
+    }
+}
@@ -1,29 +1,342 @@
 use std::collections::HashMap;
 use serde::Deserialize;
-use std::fs::File;
+use std::env;
+use std::fs::{self, File};
 use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use rocket::{Orbit, Rocket};
+use rocket::fairing::AdHoc;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::tokio;
+#[cfg(unix)]
+use rocket::tokio::signal::unix::{signal, Signal, SignalKind};
 use crate::macros::err;
+use crate::auth::{self, AuthConfig};
+use crate::canvas::CanvasConfig;
+use crate::at_risk::RiskConfig;
+use crate::cache::CacheConfig;
+
+// Environment variable with the path of the config file
+pub const CONFIG_ENV : &str = "HORIZONS_CONFIG";
+pub const DEFAULT_CONFIG : &str = "Horizons.toml";
+
+// How often the config file is checked for changes
+const WATCH_SECONDS : u64 = 2;
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Config {
     pub current_courses : HashMap<String, i32>,
     pub trends_config : HashMap<String, Vec<(i32, Vec<i32>)>>,
+    #[serde(default)]
+    pub auth : AuthConfig,
+    #[serde(default)]
+    pub canvas : CanvasConfig,
+    #[serde(default)]
+    pub at_risk : RiskConfig,
+    #[serde(default)]
+    pub cache : CacheConfig,
 }
 
 
 impl Config {
+    // Path from HORIZONS_CONFIG or Horizons.toml in the current directory
+    pub fn default_path() -> PathBuf {
+        env::var_os(CONFIG_ENV)
+            .map(PathBuf::from)
+            .unwrap_or(PathBuf::from(DEFAULT_CONFIG))
+    }
+
     pub fn load_config() -> Result<Self,String> {
-        let file = File::open("Horizons.toml")
-            .map_err(|e| err!("TOML File Failure",e))?;
+        Self::load(&Self::default_path())
+    }
+
+    pub fn load(path : &Path) -> Result<Self,String> {
+        let file = File::open(path)
+            .map_err(|e| err!(format!("TOML File Failure: {}", path.display()),e))?;
         let mut reader = BufReader::new(file);
         let mut buffer = Vec::<u8>::new();
         reader.read_to_end(&mut buffer)
             .map_err(|e| err!("TOML File Read Failure",e))?;
         let contents = String::from_utf8(buffer)
             .map_err(|e| err!("TOML Parsing Failure", e))?;
-        toml::from_str(&contents)
-            .map_err(|e| err!("TOML Parsing Failure",e))
+        let mut config : Config = toml::from_str(&contents)
+            .map_err(|e| err!("TOML Parsing Failure",e))?;
+        config.auth.secret_from_env(env::var(auth::JWT_SECRET_ENV).ok());
+        config.validate()
+            .map_err(|e| err!(format!("Config Validation Failure: {}", path.display()), e))?;
+        Ok(config)
+    }
+
+    // Check the references between sections.  Every problem is reported
+    // (one per line) instead of stopping at the first one.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut problems = Vec::new();
+        if self.current_courses.is_empty() {
+            problems.push("No courses in [current_courses]".to_string());
+        }
+        let mut courses = self.current_courses.iter().collect::<Vec<(&String, &i32)>>();
+        courses.sort();
+        for (course, id) in courses {
+            if *id <= 0 {
+                problems.push(format!("Invalid course id for {course} in [current_courses]: {id}"));
+            }
+        }
+        let mut trends = self.trends_config.iter().collect::<Vec<_>>();
+        trends.sort_by(|a, b| a.0.cmp(b.0));
+        for (course, sections) in trends {
+            if !self.current_courses.contains_key(course) {
+                problems.push(format!("Unknown course in [trends_config]: {course}"));
+            }
+            if sections.is_empty() {
+                problems.push(format!("No sections for {course} in [trends_config]"));
+            }
+        }
+        for key in self.auth.api_keys.iter() {
+            for course in key.courses.iter().filter(|course| !self.current_courses.contains_key(*course)) {
+                problems.push(format!("Unknown course for API key {}: {course}", key.name));
+            }
+        }
+        if self.auth.jwt_secret.is_empty() {
+            problems.push(format!("No jwt_secret in [auth]: set {}", auth::JWT_SECRET_ENV));
+        }
+        else if self.auth.jwt_secret == auth::PLACEHOLDER_SECRET {
+            problems.push(format!("jwt_secret in [auth] is the example key: set {} to a new secret", auth::JWT_SECRET_ENV));
+        }
+        if self.at_risk.recent_assignments == 0 {
+            problems.push("recent_assignments in [at_risk] must be at least 1".to_string());
+        }
+        if problems.is_empty() { Ok(()) } else { Err(problems.join("\n")) }
+    }
+}
+
+// Shown by the Debug impls in place of secrets
+pub fn redacted(secret : &str) -> &'static str {
+    if secret.is_empty() { "<empty>" } else { "<redacted>" }
+}
+
+// The config shared with the routes.  It is replaced when the file changes
+// or the server gets SIGHUP.  A bad file is logged and the old config is
+// kept.
+#[derive(Debug, Clone)]
+pub struct SharedConfig {
+    config : Arc<RwLock<Config>>,
+    path : PathBuf,
+}
+
+impl SharedConfig {
+    pub fn new(config : Config, path : PathBuf) -> Self {
+        SharedConfig {config : Arc::new(RwLock::new(config)), path}
+    }
+
+    // Copy of the current config (the lock is never held across an await)
+    pub fn current(&self) -> Config {
+        match self.config.read() {
+            Ok(config) => config.clone(),
+            Err(poisoned) => poisoned.into_inner().clone()
+        }
+    }
+
+    pub fn reload(&self) -> Result<(), String> {
+        let config = Config::load(&self.path)?;
+        match self.config.write() {
+            Ok(mut current) => *current = config,
+            Err(poisoned) => *poisoned.into_inner() = config
+        }
+        Ok(())
+    }
+
+    fn modified(&self) -> Option<(SystemTime, u64)> {
+        fs::metadata(&self.path).ok()
+            .and_then(|meta| meta.modified().ok().map(|modified| (modified, meta.len())))
+    }
+
+    fn reload_logged(&self, reason : &str) {
+        match self.reload() {
+            Ok(()) => println!("Config Reload ({reason}): {}", self.path.display()),
+            Err(error) => println!("Config Reload ({reason}) Failed, keeping the previous config{error}")
+        }
+    }
+}
+
+// Routes take &Config to get the config for the request.  The copy is made
+// once per request and shared by the other guards (see Auth).
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for &'r Config {
+    type Error = String;
+
+    async fn from_request(request : &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let config = request.local_cache(|| request.rocket().state::<SharedConfig>().map(|shared| shared.current()));
+        match config {
+            Some(config) => Outcome::Success(config),
+            None => Outcome::Error((Status::InternalServerError, "Config Not Managed".to_string()))
+        }
     }
 }
 
-// See Horizons.toml
+// Fairing that reloads the config when the file changes or on SIGHUP (unix only)
+pub fn stage() -> AdHoc {
+    AdHoc::on_liftoff("Config Reload", |rocket| Box::pin(async move {
+        watch(rocket);
+    }))
+}
+
+// SIGHUP listener; elsewhere only the file modification time is polled
+#[cfg(unix)]
+struct Hangup(Option<Signal>);
+
+#[cfg(unix)]
+impl Hangup {
+    fn new() -> Self {
+        match signal(SignalKind::hangup()) {
+            Ok(hangup) => Self(Some(hangup)),
+            Err(error) => {
+                println!("{}", err!("SIGHUP Handler Failure", error));
+                Self(None)
+            }
+        }
+    }
+
+    async fn recv(&mut self) {
+        // Never finishes if there is no handler or it has closed
+        if let Some(hangup) = self.0.as_mut() {
+            if hangup.recv().await.is_some() {
+                return;
+            }
+        }
+        std::future::pending().await
+    }
+}
+
+#[cfg(not(unix))]
+struct Hangup;
+
+#[cfg(not(unix))]
+impl Hangup {
+    fn new() -> Self {
+        Self
+    }
+
+    async fn recv(&mut self) {
+        std::future::pending().await
+    }
+}
+
+fn watch(rocket : &Rocket<Orbit>) {
+    let Some(shared) = rocket.state::<SharedConfig>() else {
+        println!("Config Reload: Not Watching (missing state)");
+        return;
+    };
+    let shared = shared.clone();
+    let shutdown = rocket.shutdown();
+    let mut hangup = Hangup::new();
+    tokio::spawn(async move {
+        let mut last = shared.modified();
+        let mut interval = tokio::time::interval(Duration::from_secs(WATCH_SECONDS));
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let modified = shared.modified();
+                    if modified.is_some() && modified != last {
+                        last = modified;
+                        shared.reload_logged("File Changed");
+                    }
+                }
+                _ = hangup.recv() => {
+                    last = shared.modified();
+                    shared.reload_logged("SIGHUP");
+                }
+                _ = shutdown.clone() => break
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG : &str = r#"
+        [current_courses]
+        cse210 = 295048
+        cse280 = 283852
+
+        [trends_config]
+        cse280 = [[283852, [1765421]]]
+
+        [auth]
+        jwt_secret = "test-secret"
+    "#;
+
+    fn write(name : &str, contents : &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("horizons_{}_{name}.toml", std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_validate() {
+        let path = write("valid", CONFIG);
+        assert_eq!(Config::load(&path).unwrap().current_courses.len(), 2);
+
+        let config : Config = toml::from_str(r#"
+            [current_courses]
+            cse210 = 295048
+
+            [trends_config]
+            cse280 = [[283852, [1765421]]]
+            cse210 = []
+
+            [auth]
+            jwt_secret = ""
+
+            [[auth.api_keys]]
+            name = "dashboard"
+            hash = ""
+            role = "viewer"
+            courses = ["cse999"]
+        "#).unwrap();
+        assert_eq!(config.validate(), Err([
+            "No sections for cse210 in [trends_config]",
+            "Unknown course in [trends_config]: cse280",
+            "Unknown course for API key dashboard: cse999",
+            "No jwt_secret in [auth]: set HORIZONS_JWT_SECRET"
+        ].join("\n")));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_jwt_secret() {
+        let mut config : Config = toml::from_str(&CONFIG.replace("test-secret", auth::PLACEHOLDER_SECRET)).unwrap();
+        assert_eq!(config.validate(), Err("jwt_secret in [auth] is the example key: set HORIZONS_JWT_SECRET to a new secret".to_string()));
+
+        // The environment variable wins over the file, unless it is empty
+        config.auth.secret_from_env(Some(String::new()));
+        assert_eq!(config.auth.jwt_secret, auth::PLACEHOLDER_SECRET);
+        config.auth.secret_from_env(Some("from-env".to_string()));
+        assert_eq!(config.validate(), Ok(()));
+
+        // Secrets never show up in the startup log
+        let debug = format!("{:?}", config);
+        assert!(!debug.contains("from-env"), "{debug}");
+        assert!(debug.contains("jwt_secret: \"<redacted>\""), "{debug}");
+    }
+
+    #[test]
+    fn test_reload() {
+        let path = write("reload", CONFIG);
+        let shared = SharedConfig::new(Config::load(&path).unwrap(), path.clone());
+
+        fs::write(&path, CONFIG.replace("cse210 = 295048", "cse210 = 295048\ncse310 = 283902")).unwrap();
+        shared.reload().unwrap();
+        assert_eq!(shared.current().current_courses.get("cse310"), Some(&283902));
+
+        // A bad file keeps the previous config
+        fs::write(&path, "[current_courses]\n").unwrap();
+        assert!(shared.reload().is_err());
+        assert_eq!(shared.current().current_courses.len(), 3);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -1,8 +1,48 @@
+use rocket::{Rocket, Build};
+use rocket::fairing;
 use rocket_db_pools::Database;
 use rocket_db_pools::sqlx;
+use rocket_db_pools::sqlx::migrate::Migrator;
+
+use crate::macros::err;
 
 #[derive(Database)]
 #[database("horizons")]
 pub struct DBPool(sqlx::PgPool);
 
-// See Rocket.toml
\ No newline at end of file
+// See Rocket.toml
+
+// SQL files in migrations/ are embedded at compile time.  Add a new
+// numbered file to change the schema (never edit one that has been run).
+pub static MIGRATOR : Migrator = sqlx::migrate!("./migrations");
+
+// Used with AdHoc::try_on_ignite after DBPool::init() so the schema is
+// current before any route runs.
+pub async fn run_migrations(rocket : Rocket<Build>) -> fairing::Result {
+    let Some(db) = DBPool::fetch(&rocket) else {
+        println!("{}", err!("Migration Failure", "DBPool is not attached"));
+        return Err(rocket);
+    };
+    match MIGRATOR.run(&**db).await {
+        Ok(()) => Ok(rocket),
+        Err(error) => {
+            println!("{}", err!("Migration Failure", error));
+            Err(rocket)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrations_embedded() {
+        let migrations = MIGRATOR.iter()
+            .map(|migration| (migration.version, migration.description.to_string()))
+            .collect::<Vec<(i64, String)>>();
+        assert_eq!(migrations, vec![
+            (1, "create current tables".to_string()),
+            (2, "create trend tables".to_string())]);
+    }
+}
@@ -0,0 +1,268 @@
+use std::io::Cursor;
+use rocket::http::{ContentType, Header, Status};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use rocket::serde::Serialize;
+use rust_xlsxwriter::{Format as CellFormat, Workbook};
+
+use crate::cache::CachedJson;
+use crate::response::ApiResponse;
+
+// A single value in an exported row.  Grade cells are percentages that get
+// two decimal places in both CSV and XLSX.
+pub enum Cell {
+    Text(String),
+    Integer(i64),
+    Number(Option<f64>),
+    Grade(Option<f64>),
+}
+
+// Query results that can be exported as a spreadsheet
+pub trait Tabular {
+    fn headers() -> &'static [&'static str];
+    fn cells(&self) -> Vec<Cell>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Json,
+    Csv,
+    Xlsx
+}
+
+impl Format {
+    // Read the ?format= query parameter (JSON if not provided)
+    pub fn parse(value : Option<&str>) -> Result<Format, String> {
+        match value.map(|value| value.to_lowercase()).as_deref() {
+            None | Some("json") => Ok(Format::Json),
+            Some("csv") => Ok(Format::Csv),
+            Some("xlsx") => Ok(Format::Xlsx),
+            Some(other) => Err(format!("Invalid Format: {other}"))
+        }
+    }
+}
+
+// The JSON envelope (new or cached) or a file download
+pub enum Report<T> {
+    Json(ApiResponse<Vec<T>>),
+    Cached(CachedJson),
+    File {
+        content_type : ContentType,
+        filename : String,
+        bytes : Vec<u8>
+    }
+}
+
+impl<T> From<ApiResponse<Vec<T>>> for Report<T> {
+    fn from(response : ApiResponse<Vec<T>>) -> Self {
+        Report::Json(response)
+    }
+}
+
+impl<T> From<CachedJson> for Report<T> {
+    fn from(response : CachedJson) -> Self {
+        Report::Cached(response)
+    }
+}
+
+impl<'r, T : Serialize> Responder<'r, 'static> for Report<T> {
+    fn respond_to(self, request : &'r Request<'_>) -> response::Result<'static> {
+        match self {
+            Report::Json(response) => response.respond_to(request),
+            Report::Cached(response) => response.respond_to(request),
+            Report::File {content_type, filename, bytes} => {
+                Response::build()
+                    .status(Status::Ok)
+                    .header(content_type)
+                    .header(Header::new("Content-Disposition", format!("attachment; filename=\"{filename}\"")))
+                    .sized_body(bytes.len(), Cursor::new(bytes))
+                    .ok()
+            }
+        }
+    }
+}
+
+// Only letters, digits, - and _ are kept in file names
+pub fn file_name(parts : &[&str], extension : &str) -> String {
+    let name = parts.iter()
+        .filter(|part| !part.is_empty())
+        .map(|part| part.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+            .collect::<String>())
+        .collect::<Vec<String>>()
+        .join("_");
+    format!("{name}.{extension}")
+}
+
+// Spreadsheets run a CSV cell that starts with one of these as a formula, so
+// text cells that do are written with a leading ' (XLSX cells are typed and
+// are never formulas).
+const FORMULA_START : &[char] = &['=', '+', '-', '@', '\t', '\r'];
+
+fn csv_text(text : String) -> String {
+    if text.starts_with(FORMULA_START) {
+        format!("'{text}")
+    }
+    else {
+        text
+    }
+}
+
+// The whole file is built before it is sent.  The rows are already in memory
+// (one page of one course) and XLSX can only be written once it is complete.
+pub fn to_csv<T : Tabular>(rows : &[T]) -> Result<Vec<u8>, String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(T::headers())
+        .map_err(|e| e.to_string())?;
+    for row in rows {
+        let record = row.cells().into_iter().map(|cell| match cell {
+            Cell::Text(text) => csv_text(text),
+            Cell::Integer(value) => value.to_string(),
+            Cell::Number(value) => value.map(|v| v.to_string()).unwrap_or_default(),
+            Cell::Grade(value) => value.map(|v| format!("{v:.2}")).unwrap_or_default(),
+        });
+        writer.write_record(record)
+            .map_err(|e| e.to_string())?;
+    }
+    writer.into_inner()
+        .map_err(|e| e.to_string())
+}
+
+pub fn to_xlsx<T : Tabular>(rows : &[T], sheet : &str) -> Result<Vec<u8>, String> {
+    let mut workbook = Workbook::new();
+    let bold = CellFormat::new().set_bold();
+    let grade = CellFormat::new().set_num_format("0.00");
+    let worksheet = workbook.add_worksheet();
+    // Sheet names are limited to 31 characters
+    worksheet.set_name(sheet.chars().take(31).collect::<String>())
+        .map_err(|e| e.to_string())?;
+    for (col, header) in T::headers().iter().enumerate() {
+        worksheet.write_string_with_format(0, col as u16, *header, &bold)
+            .map_err(|e| e.to_string())?;
+    }
+    for (index, row) in rows.iter().enumerate() {
+        let line = index as u32 + 1;
+        for (col, cell) in row.cells().into_iter().enumerate() {
+            let col = col as u16;
+            let result = match cell {
+                Cell::Text(text) => worksheet.write_string(line, col, text).map(|_| ()),
+                Cell::Integer(value) => worksheet.write_number(line, col, value as f64).map(|_| ()),
+                Cell::Number(Some(value)) => worksheet.write_number(line, col, value).map(|_| ()),
+                Cell::Grade(Some(value)) => worksheet.write_number_with_format(line, col, value, &grade).map(|_| ()),
+                Cell::Number(None) | Cell::Grade(None) => Ok(())
+            };
+            result.map_err(|e| e.to_string())?;
+        }
+    }
+    worksheet.autofit();
+    workbook.save_to_buffer()
+        .map_err(|e| e.to_string())
+}
+
+// Build the response for the requested format.  The name parts are joined
+// to make the file name (for example cse210_2025-1W_grades.csv).
+pub fn export<T : Tabular>(format : Format, rows : Vec<T>, name : &[&str]) -> Report<T> {
+    let result = match format {
+        Format::Json => return Report::Json(ApiResponse::ok(rows)),
+        Format::Csv => to_csv(&rows)
+            .map(|bytes| (ContentType::CSV, file_name(name, "csv"), bytes)),
+        Format::Xlsx => to_xlsx(&rows, name.last().copied().unwrap_or("report"))
+            .map(|bytes| (ContentType::new("application", "vnd.openxmlformats-officedocument.spreadsheetml.sheet"),
+                          file_name(name, "xlsx"), bytes)),
+    };
+    match result {
+        Ok((content_type, filename, bytes)) => Report::File {content_type, filename, bytes},
+        Err(error) => Report::Json(ApiResponse::server_error("Export Failure", error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::local::blocking::Client;
+
+    #[derive(Serialize)]
+    #[serde(crate = "rocket::serde")]
+    struct Row {
+        name : String,
+        submitted : i64,
+        avg_grade : Option<f64>,
+    }
+
+    impl Tabular for Row {
+        fn headers() -> &'static [&'static str] {
+            &["name", "submitted", "avg_grade"]
+        }
+
+        fn cells(&self) -> Vec<Cell> {
+            vec![Cell::Text(self.name.clone()), Cell::Integer(self.submitted), Cell::Grade(self.avg_grade)]
+        }
+    }
+
+    fn rows() -> Vec<Row> {
+        vec![
+            Row {name : "W01, Prove".to_string(), submitted : 20, avg_grade : Some(84.18751)},
+            Row {name : "W02 Prove".to_string(), submitted : 18, avg_grade : None},
+        ]
+    }
+
+    #[get("/report?<format>")]
+    fn report(format : Option<&str>) -> Report<Row> {
+        match Format::parse(format) {
+            Ok(format) => export(format, rows(), &["cse210", "2025-1W", "grades"]),
+            Err(error) => ApiResponse::error(Status::BadRequest, &error).into()
+        }
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(Format::parse(None), Ok(Format::Json));
+        assert_eq!(Format::parse(Some("CSV")), Ok(Format::Csv));
+        assert_eq!(Format::parse(Some("xlsx")), Ok(Format::Xlsx));
+        assert!(Format::parse(Some("pdf")).is_err());
+    }
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(file_name(&["cse210", "2025-1W", "grades"], "csv"), "cse210_2025-1W_grades.csv");
+        assert_eq!(file_name(&["cse 210", "", "\"a/b\""], "csv"), "cse_210__a_b_.csv");
+    }
+
+    #[test]
+    fn test_csv() {
+        let csv = String::from_utf8(to_csv(&rows()).unwrap()).unwrap();
+        assert_eq!(csv, "name,submitted,avg_grade\n\"W01, Prove\",20,84.19\nW02 Prove,18,\n");
+    }
+
+    #[test]
+    fn test_csv_formulas() {
+        let rows = ["=HYPERLINK(\"http://x\")", "+1", "-2", "@SUM(A1)", "W03 = Prove"].iter()
+            .map(|name| Row {name : name.to_string(), submitted : -1, avg_grade : None})
+            .collect::<Vec<Row>>();
+        let csv = String::from_utf8(to_csv(&rows).unwrap()).unwrap();
+        assert_eq!(csv, "name,submitted,avg_grade\n\"'=HYPERLINK(\"\"http://x\"\")\",-1,\n'+1,-1,\n'-2,-1,\n'@SUM(A1),-1,\nW03 = Prove,-1,\n");
+    }
+
+    #[test]
+    fn test_responses() {
+        let client = Client::tracked(rocket::build().mount("/", routes![report])).unwrap();
+
+        let response = client.get("/report").dispatch();
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+
+        let response = client.get("/report?format=csv").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::CSV));
+        assert_eq!(response.headers().get_one("Content-Disposition"),
+            Some("attachment; filename=\"cse210_2025-1W_grades.csv\""));
+
+        let response = client.get("/report?format=xlsx").dispatch();
+        assert_eq!(response.headers().get_one("Content-Disposition"),
+            Some("attachment; filename=\"cse210_2025-1W_grades.xlsx\""));
+        // XLSX files are zip archives
+        assert!(response.into_bytes().unwrap().starts_with(b"PK"));
+
+        let response = client.get("/report?format=pdf").dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+}
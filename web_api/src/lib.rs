@@ -1,7 +1,34 @@
 #[macro_use] extern crate rocket;
 
 pub mod config;
+pub mod auth;
 pub mod macros;
+pub mod response;
+pub mod cache;
+pub mod export;
+pub mod listing;
 pub mod database;
 pub mod routes_current;
-pub mod routes_trends;
\ No newline at end of file
+pub mod routes_trends;
+pub mod at_risk;
+pub mod assignment_stats;
+pub mod canvas;
+pub mod sync;
+pub mod seed;
+pub mod openapi;
+
+// Every route served by web_api (openapi::ApiDoc must document each one)
+pub fn routes() -> Vec<rocket::Route> {
+    routes![routes_current::route_current_grades,
+            routes_current::route_current_courses,
+            routes_current::route_current_students,
+            at_risk::route_current_at_risk,
+            assignment_stats::route_current_assignment,
+            routes_trends::route_trends_trend,
+            sync::route_update_current,
+            sync::route_update_trends,
+            cache::route_admin_cache,
+            cache::route_admin_cache_clear,
+            openapi::route_openapi,
+            openapi::route_explorer]
+}
@@ -0,0 +1,317 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use rocket_db_pools::sqlx::{self, FromRow, PgConnection, Postgres};
+use rocket_db_pools::sqlx::postgres::{PgArguments, PgRow};
+use rocket_db_pools::sqlx::query::QueryAs;
+use utoipa::IntoParams;
+
+use crate::response::Page;
+
+const MAX_LIMIT : i64 = 500;
+
+// What a list route allows.  Only the SQL in this table (never the query
+// string) is placed in the SQL text, all filter values are bound.
+pub struct Listing {
+    // API name and SQL column for each sortable column
+    pub sort : &'static [(&'static str, &'static str)],
+    pub default_order : &'static str,
+    // Column compared by min_score
+    pub score_column : &'static str,
+    // Whether the group filter applies
+    pub group : bool,
+}
+
+// Raw query string parameters.  They are parsed in ListQuery::parse so bad
+// values are reported instead of ignored.
+// The doc comments describe the parameters in the OpenAPI document.
+#[derive(FromForm, Debug, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListParams<'r> {
+    /// Column to sort by
+    #[param(value_type = Option<String>)]
+    pub sort : Option<&'r str>,
+    /// asc (default) or desc, requires sort
+    #[param(value_type = Option<String>)]
+    pub order : Option<&'r str>,
+    /// Rows per page (1 to 500, default all rows)
+    #[param(value_type = Option<i64>)]
+    pub limit : Option<&'r str>,
+    /// Rows to skip (cannot be used with cursor)
+    #[param(value_type = Option<i64>)]
+    pub offset : Option<&'r str>,
+    /// next_cursor from the previous page
+    #[param(value_type = Option<String>)]
+    pub cursor : Option<&'r str>,
+    /// Minimum average grade (grades) or current score (students)
+    #[param(value_type = Option<f64>)]
+    pub min_score : Option<&'r str>,
+    /// Only rows with more missing submissions than this
+    #[param(value_type = Option<i64>)]
+    pub missing_gt : Option<&'r str>,
+    /// Assignment group id (grades only)
+    #[param(value_type = Option<i32>)]
+    pub group : Option<&'r str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Bind {
+    Float(f64),
+    Integer(i64),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ListQuery {
+    order_by : String,
+    limit : Option<i64>,
+    offset : i64,
+    filters : Vec<(String, Bind)>,
+}
+
+fn parse_number<T : std::str::FromStr>(name : &str, value : &str) -> Result<T, String> {
+    value.parse::<T>().map_err(|_| format!("Invalid {name}: {value}"))
+}
+
+// Cursors are opaque to the client, they hold the offset of the next page
+pub fn encode_cursor(offset : i64) -> String {
+    URL_SAFE_NO_PAD.encode(format!("offset:{offset}"))
+}
+
+fn decode_cursor(cursor : &str) -> Result<i64, String> {
+    URL_SAFE_NO_PAD.decode(cursor).ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .and_then(|text| text.strip_prefix("offset:").and_then(|offset| offset.parse::<i64>().ok()))
+        .filter(|offset| *offset >= 0)
+        .ok_or(format!("Invalid cursor: {cursor}"))
+}
+
+impl ListQuery {
+
+    pub fn parse(params : &ListParams, listing : &Listing) -> Result<ListQuery, String> {
+        let order_by = match params.sort {
+            Some(sort) => {
+                let column = listing.sort.iter()
+                    .find(|(name, _)| *name == sort)
+                    .map(|(_, column)| *column)
+                    .ok_or(format!("Invalid sort: {sort}"))?;
+                let direction = match params.order.map(|order| order.to_lowercase()).as_deref() {
+                    None | Some("asc") => "ASC",
+                    Some("desc") => "DESC",
+                    Some(_) => return Err(format!("Invalid order: {}", params.order.unwrap_or_default()))
+                };
+                format!("{column} {direction} NULLS LAST, name ASC")
+            }
+            None if params.order.is_some() => return Err("The order parameter requires sort".to_string()),
+            None => listing.default_order.to_string()
+        };
+
+        let limit = match params.limit {
+            Some(limit) => {
+                let limit = parse_number::<i64>("limit", limit)?;
+                if !(1..=MAX_LIMIT).contains(&limit) {
+                    return Err(format!("Invalid limit: {limit} (1 to {MAX_LIMIT})"));
+                }
+                Some(limit)
+            }
+            None => None
+        };
+
+        let offset = match (params.offset, params.cursor) {
+            (Some(_), Some(_)) => return Err("Use either offset or cursor, not both".to_string()),
+            (Some(offset), None) => {
+                let offset = parse_number::<i64>("offset", offset)?;
+                if offset < 0 {
+                    return Err(format!("Invalid offset: {offset}"));
+                }
+                offset
+            }
+            (None, Some(cursor)) => decode_cursor(cursor)?,
+            (None, None) => 0
+        };
+
+        let mut filters = Vec::new();
+        if let Some(min_score) = params.min_score {
+            let min_score = parse_number::<f64>("min_score", min_score)?;
+            if !min_score.is_finite() {
+                return Err(format!("Invalid min_score: {min_score}"));
+            }
+            filters.push((format!("{} >=", listing.score_column), Bind::Float(min_score)));
+        }
+        if let Some(missing_gt) = params.missing_gt {
+            filters.push(("missing >".to_string(), Bind::Integer(parse_number::<i64>("missing_gt", missing_gt)?)));
+        }
+        if let Some(group) = params.group {
+            if !listing.group {
+                return Err("The group filter is not supported here".to_string());
+            }
+            filters.push(("\"group\" =".to_string(), Bind::Integer(parse_number::<i64>("group", group)?)));
+        }
+
+        Ok(ListQuery {order_by, limit, offset, filters})
+    }
+
+    // Placeholders start at $2, $1 is always the course id
+    fn where_clause(&self) -> String {
+        if self.filters.is_empty() {
+            return String::new();
+        }
+        let conditions = self.filters.iter()
+            .enumerate()
+            .map(|(index, (condition, _))| format!("{condition} ${}", index + 2))
+            .collect::<Vec<String>>();
+        format!("WHERE {}", conditions.join(" AND "))
+    }
+
+    pub fn select_sql(&self, base : &str) -> String {
+        let next = self.filters.len() + 2;
+        let limit = match self.limit {
+            Some(_) => format!("LIMIT ${}", next + 1),
+            None => String::new()
+        };
+        format!("SELECT * FROM ({base}) AS report {} ORDER BY {} OFFSET ${next} {limit}",
+            self.where_clause(), self.order_by)
+    }
+
+    pub fn count_sql(&self, base : &str) -> String {
+        format!("SELECT COUNT(*) FROM ({base}) AS report {}", self.where_clause())
+    }
+
+    fn bind_filters<'q, O>(&self, mut query : QueryAs<'q, Postgres, O, PgArguments>) -> QueryAs<'q, Postgres, O, PgArguments> {
+        for (_, bind) in self.filters.iter() {
+            query = match *bind {
+                Bind::Float(value) => query.bind(value),
+                Bind::Integer(value) => query.bind(value),
+            };
+        }
+        query
+    }
+
+    // Run the base query (which takes the course id as $1) with the filters,
+    // sort, and page applied.  Returns the page of rows and the total number
+    // of rows that match the filters.
+    pub async fn fetch<T>(&self, db : &mut PgConnection, base : &str, course_id : i32) -> Result<(Vec<T>, i64), sqlx::Error>
+        where T : for<'r> FromRow<'r, PgRow> + Send + Unpin {
+        let select_sql = self.select_sql(base);
+        let mut query = self.bind_filters(sqlx::query_as::<_, T>(&select_sql).bind(course_id))
+            .bind(self.offset);
+        if let Some(limit) = self.limit {
+            query = query.bind(limit);
+        }
+        let rows = query.fetch_all(&mut *db).await?;
+
+        let count_sql = self.count_sql(base);
+        let (total,) = self.bind_filters(sqlx::query_as::<_, (i64,)>(&count_sql).bind(course_id))
+            .fetch_one(&mut *db)
+            .await?;
+        Ok((rows, total))
+    }
+
+    // The parsed values in a fixed form, so requests that give the same
+    // rows (whatever the spelling or extra parameters) share a cache entry.
+    pub fn cache_key(&self) -> String {
+        let mut parts = vec![format!("order by {}", self.order_by), format!("offset {}", self.offset)];
+        if let Some(limit) = self.limit {
+            parts.push(format!("limit {limit}"));
+        }
+        for (condition, bind) in self.filters.iter() {
+            match bind {
+                Bind::Float(value) => parts.push(format!("{condition} {value}")),
+                Bind::Integer(value) => parts.push(format!("{condition} {value}")),
+            }
+        }
+        parts.join("; ")
+    }
+
+    pub fn page(&self, returned : usize, total : i64) -> Page {
+        let end = self.offset + returned as i64;
+        let next_cursor = match self.limit {
+            Some(_) if end < total => Some(encode_cursor(end)),
+            _ => None
+        };
+        Page {total, offset : self.offset, limit : self.limit, next_cursor}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LISTING : Listing = Listing {
+        sort : &[("name", "name"), ("avg_grade", "avg_grade"), ("group", "\"group\"")],
+        default_order : "\"group\", name",
+        score_column : "avg_grade",
+        group : true,
+    };
+
+    fn parse(params : ListParams) -> Result<ListQuery, String> {
+        ListQuery::parse(&params, &LISTING)
+    }
+
+    #[test]
+    fn test_defaults() {
+        let query = parse(ListParams::default()).unwrap();
+        assert_eq!(query.select_sql("BASE"), "SELECT * FROM (BASE) AS report  ORDER BY \"group\", name OFFSET $2 ");
+        assert_eq!(query.count_sql("BASE"), "SELECT COUNT(*) FROM (BASE) AS report ");
+    }
+
+    #[test]
+    fn test_filters_bound() {
+        let query = parse(ListParams {
+            sort : Some("avg_grade"),
+            order : Some("DESC"),
+            limit : Some("10"),
+            min_score : Some("70.5"),
+            missing_gt : Some("2"),
+            group : Some("1"),
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(query.select_sql("BASE"),
+            "SELECT * FROM (BASE) AS report WHERE avg_grade >= $2 AND missing > $3 AND \"group\" = $4 \
+             ORDER BY avg_grade DESC NULLS LAST, name ASC OFFSET $5 LIMIT $6");
+        assert_eq!(query.filters.iter().map(|(_, bind)| *bind).collect::<Vec<Bind>>(),
+            vec![Bind::Float(70.5), Bind::Integer(2), Bind::Integer(1)]);
+    }
+
+    #[test]
+    fn test_rejected() {
+        assert!(parse(ListParams {sort : Some("name; DROP TABLE curr_courses"), ..Default::default()}).is_err());
+        assert!(parse(ListParams {sort : Some("name"), order : Some("sideways"), ..Default::default()}).is_err());
+        assert!(parse(ListParams {order : Some("asc"), ..Default::default()}).is_err());
+        assert!(parse(ListParams {limit : Some("0"), ..Default::default()}).is_err());
+        assert!(parse(ListParams {limit : Some("501"), ..Default::default()}).is_err());
+        assert!(parse(ListParams {offset : Some("-1"), ..Default::default()}).is_err());
+        assert!(parse(ListParams {offset : Some("1"), cursor : Some(&encode_cursor(2)), ..Default::default()}).is_err());
+        assert!(parse(ListParams {cursor : Some("garbage"), ..Default::default()}).is_err());
+        assert!(parse(ListParams {min_score : Some("NaN"), ..Default::default()}).is_err());
+        assert!(parse(ListParams {missing_gt : Some("1 OR 1=1"), ..Default::default()}).is_err());
+
+        let no_group = Listing {group : false, ..LISTING};
+        assert!(ListQuery::parse(&ListParams {group : Some("1"), ..Default::default()}, &no_group).is_err());
+    }
+
+    #[test]
+    fn test_cache_key() {
+        let query = parse(ListParams {sort : Some("avg_grade"), order : Some("DESC"), limit : Some("10"), min_score : Some("70.50"), ..Default::default()}).unwrap();
+        assert_eq!(query.cache_key(), "order by avg_grade DESC NULLS LAST, name ASC; offset 0; limit 10; avg_grade >= 70.5");
+
+        // Same rows, different spelling
+        let same = parse(ListParams {sort : Some("avg_grade"), order : Some("desc"), limit : Some("010"), offset : Some("0"), min_score : Some("70.5"), ..Default::default()}).unwrap();
+        assert_eq!(same.cache_key(), query.cache_key());
+        assert_eq!(parse(ListParams::default()).unwrap().cache_key(), "order by \"group\", name; offset 0");
+    }
+
+    #[test]
+    fn test_pages() {
+        let query = parse(ListParams {limit : Some("10"), ..Default::default()}).unwrap();
+        let page = query.page(10, 25);
+        assert_eq!(page.next_cursor, Some(encode_cursor(10)));
+
+        let query = parse(ListParams {limit : Some("10"), cursor : page.next_cursor.as_deref(), ..Default::default()}).unwrap();
+        assert_eq!(query.offset, 10);
+        assert_eq!(query.page(10, 25).next_cursor, Some(encode_cursor(20)));
+        assert_eq!(query.page(5, 15).next_cursor, None);
+
+        // Without a limit everything is returned in one page
+        let query = parse(ListParams::default()).unwrap();
+        assert_eq!(query.page(25, 25).next_cursor, None);
+    }
+}
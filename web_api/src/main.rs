@@ -1,15 +1,28 @@
 extern crate web_api;
 
-use web_api::config::Config;
-use web_api::database::DBPool;
-use web_api::{routes_current, routes_trends};
-use rocket::routes;
+use std::path::PathBuf;
+use clap::Parser;
+use web_api::config::{self, Config, SharedConfig};
+use web_api::database::{self, DBPool};
+use web_api::{cache, response, sync};
+use rocket::catchers;
+use rocket::fairing::AdHoc;
 use rocket_db_pools::Database;
 
 
+// Command Line Setup
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Horizons API server")]
+struct Args {
+    #[clap(long, env = config::CONFIG_ENV, default_value = config::DEFAULT_CONFIG, help = "Path of the config file")]
+    config : PathBuf
+}
+
 #[rocket::main]
 async fn main() {
-    let config = match Config::load_config() {
+    let args = Args::parse();
+    let config = match Config::load(&args.config) {
         Ok(result) => result,
         Err(error) => {
             println!("{}", error);
@@ -22,11 +35,14 @@ async fn main() {
 
     let launch_result = rocket::build() 
         .attach(DBPool::init())
-        .manage(config)
-        .mount("/", routes![routes_current::route_current_grades, 
-                            routes_current::route_current_courses, 
-                            routes_current::route_current_students,
-                            routes_trends::route_trends_trend])
+        .attach(AdHoc::try_on_ignite("Database Migrations", database::run_migrations))
+        .manage(SharedConfig::new(config, args.config))
+        .attach(config::stage())
+        .manage(sync::SyncState::default())
+        .manage(cache::ResponseCache::default())
+        .attach(sync::stage())
+        .mount("/", web_api::routes())
+        .register("/", catchers![response::catch_default])
         .launch()
         .await;
     if let Err(error) = launch_result {
@@ -36,7 +52,5 @@ async fn main() {
 }
 
 
-// Add routes for update_current and upddate_trends and get courses for trends
-// Add authentication
-// Create app to call update every hour
+// Add route to get courses for trends
 // Create React Front End
@@ -0,0 +1,132 @@
+use rocket::response::content::{RawHtml, RawJson};
+use utoipa::{Modify, OpenApi};
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+
+use crate::{assignment_stats, at_risk, cache, routes_current, routes_trends, sync};
+use crate::assignment_stats::{AssignmentStats, Bin, Statistics, TermStatistics};
+use crate::at_risk::AtRiskStudent;
+use crate::cache::CacheEntry;
+use crate::response::{AssignmentResponse, AtRiskResponse, CacheResponse, CoursesResponse, GradesResponse, Page,
+                      StudentsResponse, SyncResponse, TrendsResponse};
+use crate::routes_current::{QueryCurrentCourses, QueryCurrentGrades, QueryCurrentStudents};
+use crate::routes_trends::QueryTrendsTrend;
+use crate::sync::SyncSummary;
+
+// Every route in crate::routes() must be listed here (see test_routes_documented)
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Horizons API",
+        description = "Grade reports for current courses and trends from prior terms.  \
+                       Every data route returns the same envelope (count, status, message, page, data) \
+                       and needs a bearer token (JWT or API key, see horizons_token)."
+    ),
+    paths(
+        routes_current::route_current_grades,
+        routes_current::route_current_students,
+        routes_current::route_current_courses,
+        at_risk::route_current_at_risk,
+        assignment_stats::route_current_assignment,
+        routes_trends::route_trends_trend,
+        sync::route_update_current,
+        sync::route_update_trends,
+        cache::route_admin_cache,
+        cache::route_admin_cache_clear,
+        route_openapi,
+        route_explorer,
+    ),
+    components(schemas(
+        GradesResponse, StudentsResponse, CoursesResponse, AtRiskResponse, AssignmentResponse,
+        TrendsResponse, SyncResponse, CacheResponse,
+        QueryCurrentGrades, QueryCurrentStudents, QueryCurrentCourses, QueryTrendsTrend,
+        AtRiskStudent, AssignmentStats, Statistics, Bin, TermStatistics,
+        SyncSummary, CacheEntry, Page,
+    )),
+    modifiers(&BearerAuth),
+    tags(
+        (name = "current", description = "Current courses"),
+        (name = "trends", description = "Prior terms"),
+        (name = "admin", description = "Canvas sync and the response cache"),
+        (name = "docs", description = "This document and the explorer"),
+    )
+)]
+pub struct ApiDoc;
+
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi : &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme("bearer", SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT or API key")
+                    .build()));
+        }
+    }
+}
+
+/// OpenAPI 3 document for this server.
+#[utoipa::path(
+    tag = "docs",
+    responses((status = 200, description = "OpenAPI document", content_type = "application/json"))
+)]
+#[get("/openapi.json")]
+pub fn route_openapi() -> RawJson<String> {
+    RawJson(ApiDoc::openapi().to_pretty_json().unwrap_or_default())
+}
+
+/// Page for browsing and trying the routes in /openapi.json.
+#[utoipa::path(
+    tag = "docs",
+    responses((status = 200, description = "Explorer page", content_type = "text/html"))
+)]
+#[get("/docs")]
+pub fn route_explorer() -> RawHtml<&'static str> {
+    RawHtml(include_str!("../static/explorer.html"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use rocket::http::{ContentType, Status};
+    use rocket::local::blocking::Client;
+    use rocket::serde::json::serde_json;
+
+    // Rocket writes path parameters as <name>, OpenAPI as {name}
+    fn openapi_path(path : &str) -> String {
+        path.replace('<', "{").replace('>', "}")
+    }
+
+    #[test]
+    fn test_routes_documented() {
+        let mounted = crate::routes().iter()
+            .map(|route| (route.method.as_str().to_string(), openapi_path(route.uri.path())))
+            .collect::<BTreeSet<(String, String)>>();
+        let documented = ApiDoc::openapi().paths.paths.iter()
+            .flat_map(|(path, item)| item.operations.keys()
+                .map(|method| (serde_json::to_value(method).unwrap().as_str().unwrap().to_uppercase(), path.clone())))
+            .collect::<BTreeSet<(String, String)>>();
+
+        let undocumented = mounted.difference(&documented).collect::<Vec<_>>();
+        assert!(undocumented.is_empty(), "Routes missing from ApiDoc: {undocumented:?}");
+        let stale = documented.difference(&mounted).collect::<Vec<_>>();
+        assert!(stale.is_empty(), "ApiDoc paths that are not mounted: {stale:?}");
+    }
+
+    #[test]
+    fn test_document() {
+        let client = Client::tracked(rocket::build().mount("/", routes![route_openapi, route_explorer])).unwrap();
+        let response = client.get("/openapi.json").dispatch();
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+        let document : serde_json::Value = serde_json::from_str(&response.into_string().unwrap()).unwrap();
+        assert!(document["openapi"].as_str().unwrap().starts_with("3."));
+        assert!(document["components"]["schemas"]["GradesResponse"]["properties"]["data"].is_object());
+        assert!(document["components"]["securitySchemes"]["bearer"].is_object());
+
+        let response = client.get("/docs").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::HTML));
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use rocket::serde::{Serialize, json::Json};
+use utoipa::ToSchema;
+
+use crate::macros::err;
+
+// Number of rows in the data of a response
+pub trait Rows {
+    fn rows(&self) -> usize;
+}
+
+impl<T> Rows for Vec<T> {
+    fn rows(&self) -> usize {
+        self.len()
+    }
+}
+
+// Pivoted data (one list per column) counts the rows in the longest list
+impl<T> Rows for HashMap<String, Vec<T>> {
+    fn rows(&self) -> usize {
+        self.values().map(|list| list.len()).max().unwrap_or(0)
+    }
+}
+
+// Pagination details for list routes.  The next cursor is only set when
+// there are more rows after this page.
+#[derive(Debug, Serialize, PartialEq, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Page {
+    pub total : i64,
+    pub offset : i64,
+    pub limit : Option<i64>,
+    pub next_cursor : Option<String>,
+}
+
+// The JSON envelope used by every route.  The status is also sent as the
+// HTTP status of the response.  The aliases name the envelope of each
+// route in the OpenAPI document (see openapi.rs).
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+#[aliases(GradesResponse = ApiResponse<Vec<crate::routes_current::QueryCurrentGrades>>,
+          StudentsResponse = ApiResponse<Vec<crate::routes_current::QueryCurrentStudents>>,
+          CoursesResponse = ApiResponse<Vec<crate::routes_current::QueryCurrentCourses>>,
+          AtRiskResponse = ApiResponse<Vec<crate::at_risk::AtRiskStudent>>,
+          AssignmentResponse = ApiResponse<crate::assignment_stats::AssignmentStats>,
+          TrendsResponse = ApiResponse<HashMap<String, Vec<crate::routes_trends::QueryTrendsTrend>>>,
+          SyncResponse = ApiResponse<crate::sync::SyncSummary>,
+          CacheResponse = ApiResponse<Vec<crate::cache::CacheEntry>>)]
+pub struct ApiResponse<T> {
+    count : usize,
+    status : u16,
+    message : String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page : Option<Page>,
+    data : T,
+}
+
+impl<T : Rows> ApiResponse<T> {
+    pub fn ok(data : T) -> Self {
+        ApiResponse {count : data.rows(), status : Status::Ok.code, message : "OK".to_string(), page : None, data}
+    }
+}
+
+impl<T : Default> ApiResponse<T> {
+    pub fn error(status : Status, message : &str) -> Self {
+        ApiResponse {count : 0, status : status.code, message : message.to_string(), page : None, data : T::default()}
+    }
+
+    // Log the details on the server and send the client a generic message
+    pub fn server_error(context : &str, error : impl Display) -> Self {
+        println!("{}", err!(context, error));
+        Self::error(Status::InternalServerError, "Internal Server Error")
+    }
+}
+
+impl<T> ApiResponse<T> {
+    pub fn status(&self) -> Status {
+        Status::from_code(self.status).unwrap_or(Status::InternalServerError)
+    }
+
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+
+    pub fn with_page(mut self, page : Page) -> Self {
+        self.page = Some(page);
+        self
+    }
+}
+
+impl<'r, T : Serialize> Responder<'r, 'static> for ApiResponse<T> {
+    fn respond_to(self, request : &'r Request<'_>) -> response::Result<'static> {
+        let status = self.status();
+        Response::build_from(Json(self).respond_to(request)?)
+            .status(status)
+            .ok()
+    }
+}
+
+// Errors raised before a route runs (guards, unknown routes) use the same
+// envelope.
+#[catch(default)]
+pub fn catch_default(status : Status, _request : &Request) -> ApiResponse<Vec<()>> {
+    ApiResponse::error(status, status.reason().unwrap_or("Error"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::local::blocking::Client;
+
+    #[get("/ok")]
+    fn ok() -> ApiResponse<Vec<i32>> {
+        ApiResponse::ok(vec![1, 2, 3])
+    }
+
+    #[get("/missing")]
+    fn missing() -> ApiResponse<Vec<i32>> {
+        ApiResponse::error(Status::NotFound, "Invalid Course: missing")
+    }
+
+    #[get("/broken")]
+    fn broken() -> ApiResponse<Vec<i32>> {
+        ApiResponse::server_error("SQL Error", "relation \"secret_table\" does not exist")
+    }
+
+    #[get("/paged")]
+    fn paged() -> ApiResponse<Vec<i32>> {
+        let page = Page {total : 10, offset : 0, limit : Some(3), next_cursor : Some("next".to_string())};
+        ApiResponse::ok(vec![1, 2, 3]).with_page(page)
+    }
+
+    fn client() -> Client {
+        let rocket = rocket::build()
+            .mount("/", routes![ok, missing, broken, paged])
+            .register("/", catchers![catch_default]);
+        Client::tracked(rocket).unwrap()
+    }
+
+    #[test]
+    fn test_status() {
+        let client = client();
+        let response = client.get("/ok").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_string().unwrap(), r#"{"count":3,"status":200,"message":"OK","data":[1,2,3]}"#);
+
+        let response = client.get("/missing").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(response.into_string().unwrap(), r#"{"count":0,"status":404,"message":"Invalid Course: missing","data":[]}"#);
+    }
+
+    #[test]
+    fn test_page() {
+        let client = client();
+        let response = client.get("/paged").dispatch();
+        assert_eq!(response.into_string().unwrap(),
+            r#"{"count":3,"status":200,"message":"OK","page":{"total":10,"offset":0,"limit":3,"next_cursor":"next"},"data":[1,2,3]}"#);
+    }
+
+    #[test]
+    fn test_server_error_hidden() {
+        let client = client();
+        let response = client.get("/broken").dispatch();
+        assert_eq!(response.status(), Status::InternalServerError);
+        let body = response.into_string().unwrap();
+        assert!(!body.contains("secret_table"));
+    }
+
+    #[test]
+    fn test_catcher() {
+        let client = client();
+        let response = client.get("/nothing/here").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(response.into_string().unwrap(), r#"{"count":0,"status":404,"message":"Not Found","data":[]}"#);
+    }
+
+    #[test]
+    fn test_pivot_rows() {
+        let mut data = HashMap::new();
+        data.insert("a".to_string(), vec![1, 2, 3]);
+        data.insert("b".to_string(), vec![1, 2, 3]);
+        assert_eq!(data.rows(), 3);
+    }
+}
@@ -1,13 +1,19 @@
-use rocket::State;
-use rocket::serde::{Serialize, json::Json};
+use rocket::http::Status;
+use rocket::serde::Serialize;
 use rocket_db_pools::Connection;
 use rocket_db_pools::sqlx;
+use utoipa::ToSchema;
 
+use crate::auth::{Auth, Role};
+use crate::cache::Cache;
 use crate::config::Config;
 use crate::database::DBPool;
+use crate::export::{self, Cell, Format, Report, Tabular};
+use crate::listing::{ListParams, ListQuery, Listing};
+use crate::response::ApiResponse;
 
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 #[serde(crate = "rocket::serde")]
 #[derive(sqlx::FromRow)]
 pub struct QueryCurrentGrades {
@@ -29,16 +35,7 @@ pub struct QueryCurrentGrades {
     ungraded_resubmit : i64
 }
 
-#[derive(Debug, Serialize)]
-#[serde(crate = "rocket::serde")]
-pub struct CurrentGrades {
-    count : usize,
-    status : u32,
-    message : String,
-    data : Vec<QueryCurrentGrades>,
-}
-
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 #[serde(crate = "rocket::serde")]
 #[derive(sqlx::FromRow)]
 pub struct QueryCurrentStudents {
@@ -52,46 +49,18 @@ pub struct QueryCurrentStudents {
     curr_score : f32
 }
 
-#[derive(Debug, Serialize)]
-#[serde(crate = "rocket::serde")]
-pub struct CurrentStudents {
-    count : usize,
-    status : u32,
-    message : String,
-    data : Vec<QueryCurrentStudents>,
-}
-
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 #[serde(crate = "rocket::serde")]
 #[derive(sqlx::FromRow)]
-struct QueryCurrentCourses {
+pub struct QueryCurrentCourses {
     code : String,
     term : String,
     students : i32
 }
 
-#[derive(Debug, Serialize)]
-#[serde(crate = "rocket::serde")]
-pub struct CurrentCourses {
-    count : usize,
-    status : u32,
-    message : String,
-    data : Vec<QueryCurrentCourses>,
-}
-// Routes
-
-#[get("/current/grades/<course>")]
-pub async fn route_current_grades(mut db: Connection<DBPool>, 
-                              config : &State<Config>,
-                              course : &str) -> Json<CurrentGrades> {
-    let course_id = match config.current_courses.get(course) {
-        Some(id) => *id,
-        None => {
-            return Json(CurrentGrades {data : vec![], count : 0, status : 400, message : format!("Invalid Course: {course}")});
-        }
-    };                             
-    let query_data = sqlx::query_as::<_,QueryCurrentGrades>(
-            "
+// Aggregate queries for the list routes.  ListQuery wraps them to apply
+// the filters, sort, and page.
+const GRADES_SQL : &str = "
             SELECT 
                 asn.name,
                 SUM(CASE WHEN sub.attempt > 0 THEN 1 ELSE 0 END) as submitted,
@@ -121,31 +90,18 @@ pub async fn route_current_grades(mut db: Connection<DBPool>,
             INNER JOIN curr_students as stu
                 ON stu.id = sub.user_id
             WHERE asn.course_id = $1 and stu.course_id = $1 and asn.points_possible > 0
-            GROUP BY asn.name, asn.points_possible, asn.assignment_group_id
-            ORDER BY asn.assignment_group_id, asn.name;
-        ")
-        .bind(course_id)
-        .fetch_all(&mut **db)
-        .await;
-    match query_data {
-        Ok(data) => Json(CurrentGrades {count : data.len(), data, status : 200, message : "OK".to_string()}),
-        Err(error) => Json(CurrentGrades {count : 0, data : vec![], status : 500, message : format!("SQL Error: {error}")})
-    }
-}
+            GROUP BY asn.name, asn.points_possible, asn.assignment_group_id";
 
-#[get("/current/students/<course>")]
-pub async fn route_current_students(mut db: Connection<DBPool>, 
-                                config : &State<Config>,
-                                course : &str) -> Json<CurrentStudents> {
+const GRADES_LISTING : Listing = Listing {
+    sort : &[("name", "name"), ("submitted", "submitted"), ("missing", "missing"), ("excused", "excused"),
+             ("avg_score", "avg_score"), ("avg_grade", "avg_grade"), ("avg_grade_nonzero", "avg_grade_nonzero"),
+             ("group", "\"group\""), ("ungraded_init", "ungraded_init"), ("ungraded_resubmit", "ungraded_resubmit")],
+    default_order : "\"group\", name",
+    score_column : "avg_grade",
+    group : true,
+};
 
-    let course_id = match config.current_courses.get(course) {
-        Some(id) => *id,
-        None => {
-            return Json(CurrentStudents {data : vec![], count : 0, status : 400, message : format!("Invalid Course: {course}")});
-        }
-    };    
-    let query_data = sqlx::query_as::<_,QueryCurrentStudents>(
-            "
+const STUDENTS_SQL : &str = "
             SELECT 
                 stu.name,
                 SUM(CASE WHEN sub.attempt > 0 THEN 1 ELSE 0 END) as submitted,
@@ -161,20 +117,190 @@ pub async fn route_current_students(mut db: Connection<DBPool>,
             INNER JOIN curr_assignments as asn
                 ON asn.id = sub.assignment_id
             WHERE asn.course_id = $1 and stu.course_id = $1 and asn.points_possible > 0
-            GROUP BY stu.name, stu.curr_grade, stu.curr_score
-            ORDER BY stu.curr_score DESC, stu.name ASC;
-        ")
+            GROUP BY stu.name, stu.curr_grade, stu.curr_score";
+
+const STUDENTS_LISTING : Listing = Listing {
+    sort : &[("name", "name"), ("submitted", "submitted"), ("missing", "missing"), ("excused", "excused"),
+             ("ungraded_init", "ungraded_init"), ("ungraded_resubmit", "ungraded_resubmit"),
+             ("curr_grade", "curr_grade"), ("curr_score", "curr_score")],
+    default_order : "curr_score DESC, name ASC",
+    score_column : "curr_score",
+    group : false,
+};
+
+impl Tabular for QueryCurrentGrades {
+    fn headers() -> &'static [&'static str] {
+        &["name", "submitted", "missing", "excused", "grade_a", "grade_b", "grade_c", "grade_d", "grade_f",
+          "grade_zero", "avg_score", "avg_grade", "avg_grade_nonzero", "group", "ungraded_init", "ungraded_resubmit"]
+    }
+
+    fn cells(&self) -> Vec<Cell> {
+        vec![Cell::Text(self.name.clone()), Cell::Integer(self.submitted), Cell::Integer(self.missing),
+             Cell::Integer(self.excused), Cell::Integer(self.grade_a), Cell::Integer(self.grade_b),
+             Cell::Integer(self.grade_c), Cell::Integer(self.grade_d), Cell::Integer(self.grade_f),
+             Cell::Integer(self.grade_zero), Cell::Number(self.avg_score), Cell::Grade(self.avg_grade),
+             Cell::Grade(self.avg_grade_nonzero), Cell::Integer(self.group as i64),
+             Cell::Integer(self.ungraded_init), Cell::Integer(self.ungraded_resubmit)]
+    }
+}
+
+impl Tabular for QueryCurrentStudents {
+    fn headers() -> &'static [&'static str] {
+        &["name", "submitted", "missing", "excused", "ungraded_init", "ungraded_resubmit", "curr_grade", "curr_score"]
+    }
+
+    fn cells(&self) -> Vec<Cell> {
+        vec![Cell::Text(self.name.clone()), Cell::Integer(self.submitted), Cell::Integer(self.missing),
+             Cell::Integer(self.excused), Cell::Integer(self.ungraded_init), Cell::Integer(self.ungraded_resubmit),
+             Cell::Text(self.curr_grade.clone()), Cell::Grade(Some(self.curr_score as f64))]
+    }
+}
+
+// Term of the course for export file names (empty if unknown)
+async fn course_term(db : &mut Connection<DBPool>, course_id : i32) -> String {
+    sqlx::query_scalar::<_, String>("SELECT term FROM curr_courses WHERE id = $1")
         .bind(course_id)
-        .fetch_all(&mut **db)
-        .await;
-    match query_data {
-        Ok(data) => Json(CurrentStudents {count : data.len(), data, status : 200, message : "OK".to_string()}),
-        Err(error) => Json(CurrentStudents {count : 0, data : vec![], status : 500, message : format!("SQL Error: {error}")})
+        .fetch_optional(&mut ***db)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+}
+
+// Routes
+
+/// Submission counts, grade buckets, and averages for each assignment (viewer role).
+#[utoipa::path(
+    tag = "current",
+    params(("course" = String, Path, description = "Course from [current_courses]"),
+           ("format" = Option<String>, Query, description = "json (default), csv, or xlsx"),
+           ListParams),
+    responses(
+        (status = 200, description = "Assignment grades", content(
+            ("application/json" = GradesResponse),
+            ("text/csv" = String),
+            ("application/vnd.openxmlformats-officedocument.spreadsheetml.sheet" = String))),
+        (status = 304, description = "Not modified since the ETag in If-None-Match"),
+        (status = 400, description = "Invalid query parameter", body = GradesResponse),
+        (status = 401, description = "Missing or invalid token", body = GradesResponse),
+        (status = 403, description = "Role or course not allowed", body = GradesResponse),
+        (status = 404, description = "Unknown course", body = GradesResponse),
+        (status = 500, description = "Server error (details are logged)", body = GradesResponse)),
+    security(("bearer" = []))
+)]
+#[get("/current/grades/<course>?<format>&<params..>")]
+pub async fn route_current_grades(auth : Auth,
+                              mut db: Connection<DBPool>, 
+                              config : &Config,
+                              cache : Cache<'_>,
+                              course : &str,
+                              format : Option<&str>,
+                              params : ListParams<'_>) -> Result<Report<QueryCurrentGrades>, Status> {
+    auth.require(course, Role::Viewer)?;
+    let format = match Format::parse(format) {
+        Ok(format) => format,
+        Err(error) => return Ok(ApiResponse::error(Status::BadRequest, &error).into())
+    };
+    let course_id = match config.current_courses.get(course) {
+        Some(id) => *id,
+        None => {
+            return Ok(ApiResponse::error(Status::NotFound, &format!("Invalid Course: {course}")).into());
+        }
+    };                             
+    let list = match ListQuery::parse(&params, &GRADES_LISTING) {
+        Ok(list) => list,
+        Err(error) => return Ok(ApiResponse::error(Status::BadRequest, &error).into())
+    };
+    let key = cache.key("grades", course, &list.cache_key());
+    if format == Format::Json {
+        if let Some(hit) = cache.get(&key, config.cache.ttl_seconds) {
+            return Ok(hit.into());
+        }
     }
+    let query_data = list.fetch::<QueryCurrentGrades>(&mut db, GRADES_SQL, course_id).await;
+    let (data, total) = match query_data {
+        Ok(result) => result,
+        Err(error) => return Ok(ApiResponse::server_error("SQL Error", error).into())
+    };
+    if format == Format::Json {
+        let page = list.page(data.len(), total);
+        return Ok(cache.store(key, &config.cache, ApiResponse::ok(data).with_page(page)).into());
+    }
+    let term = course_term(&mut db, course_id).await;
+    Ok(export::export(format, data, &[course, &term, "grades"]))
+}
+
+/// Submission counts and current grade for each student (instructor role).
+#[utoipa::path(
+    tag = "current",
+    params(("course" = String, Path, description = "Course from [current_courses]"),
+           ("format" = Option<String>, Query, description = "json (default), csv, or xlsx"),
+           ListParams),
+    responses(
+        (status = 200, description = "Student grades", content(
+            ("application/json" = StudentsResponse),
+            ("text/csv" = String),
+            ("application/vnd.openxmlformats-officedocument.spreadsheetml.sheet" = String))),
+        (status = 400, description = "Invalid query parameter", body = StudentsResponse),
+        (status = 401, description = "Missing or invalid token", body = StudentsResponse),
+        (status = 403, description = "Role or course not allowed", body = StudentsResponse),
+        (status = 404, description = "Unknown course", body = StudentsResponse),
+        (status = 500, description = "Server error (details are logged)", body = StudentsResponse)),
+    security(("bearer" = []))
+)]
+#[get("/current/students/<course>?<format>&<params..>")]
+pub async fn route_current_students(auth : Auth,
+                                mut db: Connection<DBPool>, 
+                                config : &Config,
+                                course : &str,
+                                format : Option<&str>,
+                                params : ListParams<'_>) -> Result<Report<QueryCurrentStudents>, Status> {
+    auth.require(course, Role::Instructor)?;
+    let format = match Format::parse(format) {
+        Ok(format) => format,
+        Err(error) => return Ok(ApiResponse::error(Status::BadRequest, &error).into())
+    };
+    let course_id = match config.current_courses.get(course) {
+        Some(id) => *id,
+        None => {
+            return Ok(ApiResponse::error(Status::NotFound, &format!("Invalid Course: {course}")).into());
+        }
+    };    
+    let list = match ListQuery::parse(&params, &STUDENTS_LISTING) {
+        Ok(list) => list,
+        Err(error) => return Ok(ApiResponse::error(Status::BadRequest, &error).into())
+    };
+    let query_data = list.fetch::<QueryCurrentStudents>(&mut db, STUDENTS_SQL, course_id).await;
+    let (data, total) = match query_data {
+        Ok(result) => result,
+        Err(error) => return Ok(ApiResponse::server_error("SQL Error", error).into())
+    };
+    if format == Format::Json {
+        let page = list.page(data.len(), total);
+        return Ok(ApiResponse::ok(data).with_page(page).into());
+    }
+    let term = course_term(&mut db, course_id).await;
+    Ok(export::export(format, data, &[course, &term, "students"]))
 }
 
+// Only the courses the token or key can access are listed
+fn visible_courses(auth : &Auth, courses : Vec<QueryCurrentCourses>) -> Vec<QueryCurrentCourses> {
+    courses.into_iter().filter(|course| auth.allows(&course.code)).collect()
+}
+
+/// The courses in the current tables that the token can access (viewer role).
+#[utoipa::path(
+    tag = "current",
+    responses(
+        (status = 200, description = "Courses", body = CoursesResponse),
+        (status = 401, description = "Missing or invalid token", body = CoursesResponse),
+        (status = 403, description = "Role or course not allowed", body = CoursesResponse),
+        (status = 500, description = "Server error (details are logged)", body = CoursesResponse)),
+    security(("bearer" = []))
+)]
 #[get("/current/courses")]
-pub async fn route_current_courses(mut db: Connection<DBPool>) -> Json<CurrentCourses> {
+pub async fn route_current_courses(auth : Auth, mut db: Connection<DBPool>) -> Result<ApiResponse<Vec<QueryCurrentCourses>>, Status> {
+    auth.require_role(Role::Viewer)?;
     let query_data = sqlx::query_as::<_,QueryCurrentCourses>(
             "
             SELECT code, term, students
@@ -184,7 +310,40 @@ pub async fn route_current_courses(mut db: Connection<DBPool>) -> Json<CurrentCo
         .fetch_all(&mut **db)
         .await;
     match query_data {
-        Ok(data) => Json(CurrentCourses {count : data.len(), data, status : 200, message : "OK".to_string()}),
-        Err(error) => Json(CurrentCourses {count : 0, data : vec![], status : 500, message : format!("SQL Error: {error}")})
+        Ok(data) => Ok(ApiResponse::ok(visible_courses(&auth, data))),
+        Err(error) => Ok(ApiResponse::server_error("SQL Error", error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::tests::{client_with, token};
+    use rocket::http::Header;
+
+    // The course list without the database
+    #[get("/courses")]
+    fn courses(auth : Auth) -> Result<String, Status> {
+        auth.require_role(Role::Viewer)?;
+        let rows = ["cse210", "cse280", "cse381"].iter()
+            .map(|code| QueryCurrentCourses {code : code.to_string(), term : "2025-1W".to_string(), students : 30})
+            .collect::<Vec<QueryCurrentCourses>>();
+        let codes = visible_courses(&auth, rows).into_iter().map(|course| course.code).collect::<Vec<String>>();
+        Ok(codes.join(","))
+    }
+
+    #[test]
+    fn test_courses_scoped() {
+        let client = client_with(routes![courses]);
+        let list = |token : &str| client.get("/courses")
+            .header(Header::new("Authorization", format!("Bearer {token}")))
+            .dispatch()
+            .into_string()
+            .unwrap();
+
+        assert_eq!(list(&token(Role::Viewer, &["cse280"])), "cse280");
+        assert_eq!(list(&token(Role::Viewer, &[])), "");
+        assert_eq!(list("key-123"), "cse210");
+        assert_eq!(list(&token(Role::Admin, &[])), "cse210,cse280,cse381");
     }
 }
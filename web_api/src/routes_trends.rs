@@ -1,14 +1,17 @@
 use std::collections::HashMap;
-use rocket::State;
-use rocket::serde::{Serialize, json::Json};
+use rocket::http::Status;
+use rocket::serde::Serialize;
 use rocket_db_pools::Connection;
 use rocket_db_pools::sqlx;
-use rocket_db_pools::sqlx::Row;
+use utoipa::ToSchema;
 
+use crate::auth::{Auth, Role};
+use crate::cache::{Cache, CachedJson};
 use crate::config::Config;
 use crate::database::DBPool;
+use crate::response::ApiResponse;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 #[serde(crate = "rocket::serde")]
 #[derive(sqlx::FromRow)]
 pub struct QueryTrendsTrend {
@@ -16,19 +19,37 @@ pub struct QueryTrendsTrend {
     grade : Option<f64>,
 }
 
-#[derive(Debug, Serialize)]
-#[serde(crate = "rocket::serde")]
-pub struct TrendsTrend {
-    count : usize,
-    status : u32,
-    message : String,
-    data : HashMap<String, Vec<QueryTrendsTrend>>,
+#[derive(Debug, sqlx::FromRow)]
+pub struct QueryTrendRow {
+    name : String,
+    term : String,
+    avg_grade : Option<f64>,
 }
 
+// One list of assignment grades per term (see pivot_trends)
+pub type TrendsTrend = HashMap<String, Vec<QueryTrendsTrend>>;
+
+/// Average grade of each assignment, one list per prior term (viewer role).
+/// Each list is named avg_grade_ followed by the term.
+#[utoipa::path(
+    tag = "trends",
+    params(("course" = String, Path, description = "Course from [trends_config]")),
+    responses(
+        (status = 200, description = "Assignment averages by term", body = TrendsResponse),
+        (status = 304, description = "Not modified since the ETag in If-None-Match"),
+        (status = 401, description = "Missing or invalid token", body = TrendsResponse),
+        (status = 403, description = "Role or course not allowed", body = TrendsResponse),
+        (status = 404, description = "Unknown course", body = TrendsResponse),
+        (status = 500, description = "Server error (details are logged)", body = TrendsResponse)),
+    security(("bearer" = []))
+)]
 #[get("/trends/trend/<course>")]
-pub async fn route_trends_trend(mut db: Connection<DBPool>, 
-                              config : &State<Config>,
-                              course : &str) -> Json<TrendsTrend> {
+pub async fn route_trends_trend(auth : Auth,
+                              mut db: Connection<DBPool>, 
+                              config : &Config,
+                              cache : Cache<'_>,
+                              course : &str) -> Result<CachedJson, Status> {
+    auth.require(course, Role::Viewer)?;
     let sections = match config.trends_config.get(course) {
         Some(sections) => {
             let mut section_ids = Vec::new();
@@ -38,67 +59,32 @@ pub async fn route_trends_trend(mut db: Connection<DBPool>,
             section_ids
         }
         None => {
-            return Json(TrendsTrend {data : HashMap::new(), count : 0, status : 400, message : format!("Invalid Course: {course}")});
+            return Ok(ApiResponse::<TrendsTrend>::error(Status::NotFound, &format!("Invalid Course: {course}")).into());
         }
     };                             
-
-    #[derive(sqlx::FromRow, Debug)]
-    struct TermQuery {
-        term : String
+    let key = cache.key("trends", course, "");
+    if let Some(hit) = cache.get(&key, config.cache.ttl_seconds) {
+        return Ok(hit);
     }
 
-    let query_terms = sqlx::query_as::<_,TermQuery>(
-        "SELECT DISTINCT course.term as term
-                FROM trend_assignments AS asn
-                INNER JOIN trend_submissions AS sub
-                    ON asn.id = sub.assignment_id
-                INNER JOIN trend_students as stu
-                    ON stu.id = sub.user_id
-                INNER JOIN trend_courses as course
-                    ON course.id = asn.course_id
-                WHERE asn.course_id = ANY($1) and stu.course_id = ANY($1) and asn.points_possible > 0
-                ORDER BY course.term
-        ")        
-        .bind(&sections)
-        .fetch_all(&mut **db)
-        .await;
-
-    let terms = match query_terms {
-        Ok(terms) => terms,
-        Err(error) => {
-            return Json(TrendsTrend {data : HashMap::new(), count : 0, status : 400, message : format!("SQL Error (terms): {error}")});
-        }
-    };      
-
-    let mut sql = "SELECT name".to_string();
-    for term in terms.iter() {
-        sql += format!(", MAX(CASE WHEN term = '{}' THEN avg_grade ELSE null END) AS avg_grade_{} ",
-            term.term, term.term.replace('-',"_").to_lowercase()).as_str();
-    }  
-    
-    sql += "FROM (
-                SELECT 
-                    asn.name as name,
-                    course.term as term,
-                    AVG(sub.score) / asn.points_possible * 100 as avg_grade
-                FROM trend_assignments AS asn
-                INNER JOIN trend_submissions AS sub
-                    ON asn.id = sub.assignment_id
-                INNER JOIN trend_students AS stu
-                    ON stu.id = sub.user_id
-                INNER JOIN trend_courses AS course
-                    ON course.id = asn.course_id ";
-    // if zero {
-    sql += "WHERE asn.course_id = ANY($1) and stu.course_id = ANY($1) and asn.points_possible > 0 ";
-    // }
-    // else {
-    //     sql += "WHERE asn.course_id = ANY($1) and stu.course_id = ANY($1) and asn.points_possible > 0 and sub.score > 0 ";
-    // }
-    sql += "GROUP BY asn.name, asn.points_possible, course.term
-            )
-            GROUP BY name
-            ORDER BY name";
-    let query_results = sqlx::query(&sql)
+    // One row per assignment and term.  Terms are never placed in the SQL
+    // text, the pivot into columns happens in pivot_trends.
+    let query_results = sqlx::query_as::<_,QueryTrendRow>(
+        "SELECT 
+                asn.name as name,
+                course.term as term,
+                AVG(sub.score) / asn.points_possible * 100 as avg_grade
+            FROM trend_assignments AS asn
+            INNER JOIN trend_submissions AS sub
+                ON asn.id = sub.assignment_id
+            INNER JOIN trend_students AS stu
+                ON stu.id = sub.user_id
+            INNER JOIN trend_courses AS course
+                ON course.id = asn.course_id
+            WHERE asn.course_id = ANY($1) and stu.course_id = ANY($1) and asn.points_possible > 0
+            GROUP BY asn.name, asn.points_possible, course.term
+            ORDER BY asn.name, course.term
+        ")
         .bind(&sections)
         .fetch_all(&mut **db)
         .await;
@@ -106,23 +92,122 @@ pub async fn route_trends_trend(mut db: Connection<DBPool>,
     let results = match query_results {
         Ok(results) => results,
         Err(error) => {
-            return Json(TrendsTrend {data : HashMap::new(), count : 0, status : 400, message : format!("SQL Error (trends): {error}")});
+            return Ok(ApiResponse::<TrendsTrend>::server_error("SQL Error (trends)", error).into());
         }
     };      
 
-    let mut data = HashMap::new();
-    for term in terms.iter() {
-        let key = format!("avg_grade_{}", term.term.replace('-',"_").to_lowercase());
-        let mut list = Vec::new();
-        for result in results.iter() {
-            let grade: Option<f64> = result.get(key.as_str());
-            let name: String = result.get("name");
-            list.push(QueryTrendsTrend {name, grade});
+    Ok(cache.store(key, &config.cache, ApiResponse::ok(pivot_trends(results))))
+
+}
+
+// The key for a term is avg_grade_ followed by the lowercase term with
+// anything other than letters and digits replaced by _ (2024-4F becomes
+// avg_grade_2024_4f).  If two terms end up with the same key, a number is
+// added to the later one.
+fn term_keys(terms : &[String]) -> Vec<String> {
+    let mut keys = Vec::<String>::new();
+    for term in terms {
+        let normal = term.chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+            .collect::<String>();
+        let base = format!("avg_grade_{normal}");
+        let mut key = base.clone();
+        let mut suffix = 2;
+        while keys.contains(&key) {
+            key = format!("{base}_{suffix}");
+            suffix += 1;
         }
-        data.insert(key, list);
+        keys.push(key);
     }
+    keys
+}
+
+// Turn rows of (name, term, avg_grade) into one list per term.  Every list
+// has every assignment name (in order) with None for terms that did not
+// have the assignment.
+pub fn pivot_trends(rows : Vec<QueryTrendRow>) -> TrendsTrend {
+    let mut terms = rows.iter().map(|row| row.term.clone()).collect::<Vec<String>>();
+    terms.sort();
+    terms.dedup();
+    let mut names = rows.iter().map(|row| row.name.clone()).collect::<Vec<String>>();
+    names.sort();
+    names.dedup();
 
-    Json(TrendsTrend {count : data.len(), data, status : 200, message : "OK".to_string()})
+    // grades[term][name]
+    let mut grades = vec![vec![None::<f64>; names.len()]; terms.len()];
+    for row in rows {
+        let (Ok(term), Ok(name)) = (terms.binary_search(&row.term), names.binary_search(&row.name)) else {
+            continue;
+        };
+        // Same as MAX() when an assignment name has two point values in one term
+        grades[term][name] = match (grades[term][name], row.avg_grade) {
+            (Some(old), Some(new)) => Some(old.max(new)),
+            (old, new) => old.or(new)
+        };
+    }
 
+    term_keys(&terms).into_iter()
+        .zip(grades)
+        .map(|(key, grades)| {
+            let list = names.iter()
+                .zip(grades)
+                .map(|(name, grade)| QueryTrendsTrend {name : name.clone(), grade})
+                .collect();
+            (key, list)
+        })
+        .collect()
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(name : &str, term : &str, avg_grade : Option<f64>) -> QueryTrendRow {
+        QueryTrendRow {name : name.to_string(), term : term.to_string(), avg_grade}
+    }
+
+    fn grades(data : &HashMap<String, Vec<QueryTrendsTrend>>, key : &str) -> Vec<(String, Option<f64>)> {
+        data[key].iter().map(|g| (g.name.clone(), g.grade)).collect()
+    }
+
+    #[test]
+    fn test_pivot() {
+        let data = pivot_trends(vec![
+            row("W01", "2024-1W", Some(80.0)),
+            row("W01", "2024-4F", Some(90.0)),
+            row("W02", "2024-4F", Some(70.0)),
+            row("W02", "2024-4F", Some(75.0)),
+        ]);
+        assert_eq!(data.len(), 2);
+        assert_eq!(grades(&data, "avg_grade_2024_1w"),
+            vec![("W01".to_string(), Some(80.0)), ("W02".to_string(), None)]);
+        assert_eq!(grades(&data, "avg_grade_2024_4f"),
+            vec![("W01".to_string(), Some(90.0)), ("W02".to_string(), Some(75.0))]);
+    }
+
+    #[test]
+    fn test_hostile_terms() {
+        let hostile = [
+            "2024'; DROP TABLE trend_courses; --",
+            "x') THEN avg_grade ELSE null END) AS a FROM pg_user --",
+            "\"quoted\" term",
+            "",
+        ];
+        let data = pivot_trends(hostile.iter().map(|term| row("W01", term, Some(50.0))).collect());
+        assert_eq!(data.len(), hostile.len());
+        for key in data.keys() {
+            assert!(key.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_'), "{key}");
+            assert_eq!(grades(&data, key), vec![("W01".to_string(), Some(50.0))]);
+        }
+    }
+
+    #[test]
+    fn test_colliding_terms() {
+        let terms = ["2024-4F", "2024_4F", "2024 4f"].map(String::from);
+        assert_eq!(term_keys(&terms), vec!["avg_grade_2024_4f", "avg_grade_2024_4f_2", "avg_grade_2024_4f_3"]);
+        let data = pivot_trends(terms.iter().enumerate()
+            .map(|(index, term)| row("W01", term, Some(index as f64)))
+            .collect());
+        assert_eq!(data.len(), 3);
+    }
+}
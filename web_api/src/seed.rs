@@ -0,0 +1,173 @@
+use rocket::time::{Date, Duration, Month};
+use rocket_db_pools::sqlx::PgPool;
+
+use crate::canvas::{Assignment, Course, CourseSnapshot, CourseTerm, Enrollment, Grades, Student, Submission};
+use crate::config::Config;
+use crate::sync::{self, SyncSummary, Target};
+
+const ASSIGNMENTS : i32 = 12;
+const STUDENTS : i32 = 20;
+const TERMS : [&str; 6] = ["2025-1W", "2024-4F", "2024-3M", "2024-2S", "2024-1W", "2023-4F"];
+
+// Deterministic pseudo random numbers so the fixtures are the same every run
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> f64 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 11) as f64 / (1_u64 << 53) as f64
+    }
+}
+
+fn timestamp(date : Date) -> String {
+    format!("{:04}-{:02}-{:02}T23:59:00Z", date.year(), date.month() as u8, date.day())
+}
+
+// Build a course with weekly assignments (a Prove worth 100 points and a
+// Ponder worth 10 points).  Each student has an ability that decides their
+// scores, late work, and missing work so the aggregates look realistic.
+fn fixture_course(course_id : i32, code : &str, term : &str, term_index : usize) -> CourseSnapshot {
+    let mut random = Random(course_id as u64);
+    let start = Date::from_calendar_date(2025 - term_index as i32 / 3, Month::January, 6)
+        .unwrap_or(Date::MIN);
+
+    let assignments = (0..ASSIGNMENTS).map(|index| {
+        let week = index / 2 + 1;
+        let prove = index % 2 == 0;
+        Assignment {
+            id : course_id * 100 + index,
+            name : format!("W{:02} {}", week, if prove { "Prove" } else { "Ponder" }),
+            points_possible : Some(if prove { 100.0 } else { 10.0 }),
+            assignment_group_id : Some(if prove { 1 } else { 2 }),
+            due_at : Some(timestamp(start + Duration::weeks(week as i64))),
+        }
+    }).collect::<Vec<Assignment>>();
+
+    let mut students = Vec::new();
+    let mut submissions = Vec::new();
+    for index in 0..STUDENTS {
+        let user_id = 1000 + index;
+        let ability = 0.45 + 0.55 * random.next();
+        let mut earned = 0.0;
+        let mut possible = 0.0;
+        for assignment in assignments.iter() {
+            let points = assignment.points_possible.unwrap_or(0.0);
+            let missing = random.next() > ability + 0.1;
+            let late = !missing && random.next() > ability;
+            let score = if missing { None } else { Some((points * (ability + 0.15 * random.next())).min(points).round()) };
+            earned += score.unwrap_or(0.0);
+            possible += points;
+            submissions.push(Submission {
+                assignment_id : assignment.id,
+                user_id,
+                attempt : if missing { None } else { Some(1) },
+                score,
+                missing,
+                excused : Some(false),
+                late,
+                grade_matches_current_submission : Some(true),
+                submitted_at : if missing { None } else { assignment.due_at.clone() },
+            });
+        }
+        let current_score = (earned / possible * 100.0) as f32;
+        let current_grade = match current_score {
+            s if s >= 90.0 => "A",
+            s if s >= 80.0 => "B",
+            s if s >= 70.0 => "C",
+            s if s >= 60.0 => "D",
+            _ => "F"
+        };
+        students.push(Student {
+            id : user_id,
+            name : format!("Student {:02}", index + 1),
+            enrollments : vec![Enrollment {grades : Some(Grades {
+                current_grade : Some(current_grade.to_string()),
+                current_score : Some(current_score)
+            })}],
+        });
+    }
+
+    CourseSnapshot {
+        course : Course {
+            id : course_id,
+            course_code : code.to_uppercase().replacen("CSE", "CSE ", 1),
+            term : Some(CourseTerm {name : term.to_string()}),
+            total_students : Some(STUDENTS),
+        },
+        assignments,
+        submissions,
+        students,
+    }
+}
+
+// Fixture data for every course in the config: the current courses (for the
+// curr_* tables) and the trends sections, each with an older term (for the
+// trend_* tables).
+fn fixtures(config : &Config) -> (Vec<CourseSnapshot>, Vec<CourseSnapshot>) {
+    let mut current = config.current_courses.iter().collect::<Vec<(&String, &i32)>>();
+    current.sort();
+    let current = current.into_iter()
+        .map(|(code, id)| fixture_course(*id, code, TERMS[0], 0))
+        .collect::<Vec<CourseSnapshot>>();
+
+    let mut trends = config.trends_config.iter().collect::<Vec<_>>();
+    trends.sort_by(|a, b| a.0.cmp(b.0));
+    let trends = trends.into_iter()
+        .flat_map(|(code, sections)| sections.iter().enumerate()
+            .map(move |(index, section)| {
+                let index = index % TERMS.len();
+                fixture_course(section.0, code, TERMS[index], index)
+            }))
+        .collect::<Vec<CourseSnapshot>>();
+    (current, trends)
+}
+
+// Load the fixture data into the curr_* and trend_* tables
+pub async fn seed(pool : &PgPool, config : &Config) -> Result<(SyncSummary, SyncSummary), String> {
+    let (current, trends) = fixtures(config);
+    let current = sync::write_snapshots(pool, Target::Current, &current).await?;
+    let trends = sync::write_snapshots(pool, Target::Trends, &trends).await?;
+    Ok((current, trends))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn config() -> Config {
+        Config {
+            current_courses : HashMap::from([("cse210".to_string(), 301), ("cse280".to_string(), 302)]),
+            trends_config : HashMap::from([("cse210".to_string(), vec![(201, vec![]), (202, vec![])])]),
+            canvas : Default::default(),
+            at_risk : Default::default(),
+            cache : Default::default(),
+            auth : Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let (current, trends) = fixtures(&config());
+        let (again, trends_again) = fixtures(&config());
+        assert_eq!(format!("{current:?}"), format!("{again:?}"));
+        assert_eq!(format!("{trends:?}"), format!("{trends_again:?}"));
+    }
+
+    #[test]
+    fn test_every_table() {
+        // One snapshot row for *_courses and rows for the other three tables
+        let (current, trends) = fixtures(&config());
+        assert_eq!(current.iter().map(|snapshot| snapshot.course.id).collect::<Vec<i32>>(), vec![301, 302]);
+        assert_eq!(trends.iter().map(|snapshot| snapshot.course.id).collect::<Vec<i32>>(), vec![201, 202]);
+        for snapshot in current.iter().chain(trends.iter()) {
+            assert_eq!(snapshot.assignments.len(), ASSIGNMENTS as usize);
+            assert_eq!(snapshot.students.len(), STUDENTS as usize);
+            assert_eq!(snapshot.submissions.len(), (ASSIGNMENTS * STUDENTS) as usize);
+        }
+        let terms = trends.iter()
+            .filter_map(|snapshot| snapshot.course.term.as_ref().map(|term| term.name.as_str()))
+            .collect::<Vec<&str>>();
+        assert_eq!(terms, vec![TERMS[0], TERMS[1]]);
+    }
+}
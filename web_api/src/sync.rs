@@ -0,0 +1,326 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use rocket::{Rocket, Orbit, State};
+use rocket::fairing::AdHoc;
+use rocket::http::Status;
+use rocket::serde::Serialize;
+use rocket::tokio::{self, sync::Mutex};
+use rocket_db_pools::Database;
+use rocket_db_pools::sqlx::{self, PgPool, Postgres, Transaction};
+use utoipa::ToSchema;
+
+use crate::auth::{Auth, Role};
+use crate::cache::ResponseCache;
+use crate::canvas::{Canvas, CourseSnapshot};
+use crate::config::{Config, SharedConfig};
+use crate::database::DBPool;
+use crate::macros::err;
+use crate::response::{ApiResponse, Rows};
+
+// Which set of tables to fill.  The prefix is never taken from a request.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    Current,
+    Trends
+}
+
+impl Target {
+    fn prefix(&self) -> &'static str {
+        match self {
+            Target::Current => "curr",
+            Target::Trends => "trend"
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SyncError {
+    Running,
+    Failed(String)
+}
+
+#[derive(Debug, Serialize, Clone, Default, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct SyncSummary {
+    courses : usize,
+    assignments : usize,
+    students : usize,
+    submissions : usize,
+    seconds : f64,
+}
+
+impl Rows for SyncSummary {
+    fn rows(&self) -> usize {
+        self.courses
+    }
+}
+
+// Only one sync can run at a time.  The scheduled job and the update
+// routes share this lock.
+#[derive(Default, Clone)]
+pub struct SyncState {
+    running : Arc<Mutex<()>>
+}
+
+// Load every course from Canvas first and then write all of them in a
+// single transaction so the tables are never partially updated.
+pub async fn sync_courses(canvas : &Canvas, pool : &PgPool, target : Target, course_ids : &[i32]) -> Result<SyncSummary, String> {
+    let start = Instant::now();
+    let mut snapshots = Vec::new();
+    for course_id in course_ids {
+        snapshots.push(canvas.get_course(*course_id).await?);
+    }
+
+    let mut summary = write_snapshots(pool, target, &snapshots).await?;
+    summary.seconds = start.elapsed().as_secs_f64();
+    Ok(summary)
+}
+
+// Write the courses in a single transaction.  This is also used to load
+// the fixture data (see seed.rs).
+pub async fn write_snapshots(pool : &PgPool, target : Target, snapshots : &[CourseSnapshot]) -> Result<SyncSummary, String> {
+    let mut tx = pool.begin()
+        .await
+        .map_err(|e| err!("SQL Transaction Failure", e))?;
+    let mut summary = SyncSummary::default();
+    for snapshot in snapshots.iter() {
+        upsert_course(&mut tx, target, snapshot)
+            .await
+            .map_err(|e| err!(format!("SQL Upsert Failure (course {})", snapshot.course.id), e))?;
+        summary.courses += 1;
+        summary.assignments += snapshot.assignments.len();
+        summary.students += snapshot.students.len();
+        summary.submissions += snapshot.submissions.len();
+    }
+    tx.commit()
+        .await
+        .map_err(|e| err!("SQL Commit Failure", e))?;
+    Ok(summary)
+}
+
+async fn upsert_course(tx : &mut Transaction<'_, Postgres>, target : Target, snapshot : &CourseSnapshot) -> Result<(), sqlx::Error> {
+    let prefix = target.prefix();
+    let course = &snapshot.course;
+    let term = course.term.as_ref().map(|term| term.name.clone()).unwrap_or_default();
+
+    sqlx::query(&format!(
+        "INSERT INTO {prefix}_courses (id, code, term, students)
+         VALUES ($1, $2, $3, $4)
+         ON CONFLICT (id) DO UPDATE SET
+            code = EXCLUDED.code, term = EXCLUDED.term, students = EXCLUDED.students"))
+        .bind(course.id)
+        .bind(&course.course_code)
+        .bind(term)
+        .bind(course.total_students.unwrap_or(snapshot.students.len() as i32))
+        .execute(&mut **tx)
+        .await?;
+
+    // Assignments
+    let assignments = &snapshot.assignments;
+    sqlx::query(&format!(
+        "INSERT INTO {prefix}_assignments (id, course_id, name, points_possible, assignment_group_id, due_at)
+         SELECT id, $1, name, points_possible, assignment_group_id, due_at::timestamptz
+         FROM UNNEST($2::int[], $3::text[], $4::float8[], $5::int[], $6::text[])
+            AS a(id, name, points_possible, assignment_group_id, due_at)
+         ON CONFLICT (id) DO UPDATE SET
+            course_id = EXCLUDED.course_id, name = EXCLUDED.name,
+            points_possible = EXCLUDED.points_possible,
+            assignment_group_id = EXCLUDED.assignment_group_id, due_at = EXCLUDED.due_at"))
+        .bind(course.id)
+        .bind(assignments.iter().map(|a| a.id).collect::<Vec<i32>>())
+        .bind(assignments.iter().map(|a| a.name.clone()).collect::<Vec<String>>())
+        .bind(assignments.iter().map(|a| a.points_possible.unwrap_or(0.0)).collect::<Vec<f64>>())
+        .bind(assignments.iter().map(|a| a.assignment_group_id.unwrap_or(0)).collect::<Vec<i32>>())
+        .bind(assignments.iter().map(|a| a.due_at.clone()).collect::<Vec<Option<String>>>())
+        .execute(&mut **tx)
+        .await?;
+
+    // Students (a student can be in more than one course)
+    let students = &snapshot.students;
+    let grades = students.iter()
+        .map(|s| s.enrollments.iter().find_map(|e| e.grades.as_ref()))
+        .collect::<Vec<_>>();
+    sqlx::query(&format!(
+        "INSERT INTO {prefix}_students (id, course_id, name, curr_grade, curr_score)
+         SELECT id, $1, name, curr_grade, curr_score
+         FROM UNNEST($2::int[], $3::text[], $4::text[], $5::real[])
+            AS s(id, name, curr_grade, curr_score)
+         ON CONFLICT (course_id, id) DO UPDATE SET
+            name = EXCLUDED.name, curr_grade = EXCLUDED.curr_grade, curr_score = EXCLUDED.curr_score"))
+        .bind(course.id)
+        .bind(students.iter().map(|s| s.id).collect::<Vec<i32>>())
+        .bind(students.iter().map(|s| s.name.clone()).collect::<Vec<String>>())
+        .bind(grades.iter().map(|g| g.and_then(|g| g.current_grade.clone()).unwrap_or("-".to_string())).collect::<Vec<String>>())
+        .bind(grades.iter().map(|g| g.and_then(|g| g.current_score).unwrap_or(0.0)).collect::<Vec<f32>>())
+        .execute(&mut **tx)
+        .await?;
+
+    // Submissions
+    let submissions = &snapshot.submissions;
+    sqlx::query(&format!(
+        "INSERT INTO {prefix}_submissions (assignment_id, user_id, attempt, score, missing, excused, late,
+                                           current_submission, submitted_at)
+         SELECT assignment_id, user_id, attempt, score, missing, excused, late,
+                current_submission, submitted_at::timestamptz
+         FROM UNNEST($1::int[], $2::int[], $3::int[], $4::float8[], $5::bool[], $6::bool[], $7::bool[],
+                     $8::bool[], $9::text[])
+            AS s(assignment_id, user_id, attempt, score, missing, excused, late, current_submission, submitted_at)
+         ON CONFLICT (assignment_id, user_id) DO UPDATE SET
+            attempt = EXCLUDED.attempt, score = EXCLUDED.score, missing = EXCLUDED.missing,
+            excused = EXCLUDED.excused, late = EXCLUDED.late,
+            current_submission = EXCLUDED.current_submission, submitted_at = EXCLUDED.submitted_at"))
+        .bind(submissions.iter().map(|s| s.assignment_id).collect::<Vec<i32>>())
+        .bind(submissions.iter().map(|s| s.user_id).collect::<Vec<i32>>())
+        .bind(submissions.iter().map(|s| s.attempt.unwrap_or(0)).collect::<Vec<i32>>())
+        .bind(submissions.iter().map(|s| s.score).collect::<Vec<Option<f64>>>())
+        .bind(submissions.iter().map(|s| s.missing).collect::<Vec<bool>>())
+        .bind(submissions.iter().map(|s| s.excused.unwrap_or(false)).collect::<Vec<bool>>())
+        .bind(submissions.iter().map(|s| s.late).collect::<Vec<bool>>())
+        .bind(submissions.iter().map(|s| s.grade_matches_current_submission.unwrap_or(true)).collect::<Vec<bool>>())
+        .bind(submissions.iter().map(|s| s.submitted_at.clone()).collect::<Vec<Option<String>>>())
+        .execute(&mut **tx)
+        .await?;
+
+    // Remove anything that was deleted in Canvas (or students who dropped)
+    sqlx::query(&format!(
+        "DELETE FROM {prefix}_submissions
+         WHERE assignment_id IN (SELECT id FROM {prefix}_assignments WHERE course_id = $1)
+           AND (assignment_id, user_id) NOT IN (SELECT * FROM UNNEST($2::int[], $3::int[]))"))
+        .bind(course.id)
+        .bind(submissions.iter().map(|s| s.assignment_id).collect::<Vec<i32>>())
+        .bind(submissions.iter().map(|s| s.user_id).collect::<Vec<i32>>())
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query(&format!(
+        "DELETE FROM {prefix}_assignments WHERE course_id = $1 AND id <> ALL($2)"))
+        .bind(course.id)
+        .bind(assignments.iter().map(|a| a.id).collect::<Vec<i32>>())
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query(&format!(
+        "DELETE FROM {prefix}_students WHERE course_id = $1 AND id <> ALL($2)"))
+        .bind(course.id)
+        .bind(students.iter().map(|s| s.id).collect::<Vec<i32>>())
+        .execute(&mut **tx)
+        .await?;
+
+    Ok(())
+}
+
+fn course_ids(config : &Config, target : Target) -> Vec<i32> {
+    let mut ids = match target {
+        Target::Current => config.current_courses.values().copied().collect::<Vec<i32>>(),
+        Target::Trends => config.trends_config.values()
+            .flat_map(|sections| sections.iter().map(|section| section.0))
+            .collect::<Vec<i32>>()
+    };
+    ids.sort();
+    ids.dedup();
+    ids
+}
+
+// Run a sync unless one is already running.  Cached responses are dropped
+// once the new data is written.
+pub async fn run(state : &SyncState, config : &Config, pool : &PgPool, cache : &ResponseCache, target : Target) -> Result<SyncSummary, SyncError> {
+    let _running = state.running.try_lock()
+        .map_err(|_| SyncError::Running)?;
+    let canvas = Canvas::new(&config.canvas);
+    let summary = sync_courses(&canvas, pool, target, &course_ids(config, target))
+        .await
+        .map_err(SyncError::Failed)?;
+    cache.clear(None, None);
+    Ok(summary)
+}
+
+// Fairing that starts the scheduled sync of the current courses
+pub fn stage() -> AdHoc {
+    AdHoc::on_liftoff("Canvas Sync", |rocket| Box::pin(async move {
+        schedule(rocket);
+    }))
+}
+
+fn schedule(rocket : &Rocket<Orbit>) {
+    let (Some(shared), Some(db), Some(state), Some(cache)) = (rocket.state::<SharedConfig>(), DBPool::fetch(rocket),
+                                                            rocket.state::<SyncState>(), rocket.state::<ResponseCache>()) else {
+        println!("Canvas Sync: Not Scheduled (missing state)");
+        return;
+    };
+    // The interval is set at launch, the rest of the config is read again
+    // for each sync so reloads are picked up.
+    let config = shared.current();
+    if config.canvas.sync_minutes == 0 || config.canvas.token.is_empty() {
+        println!("Canvas Sync: Not Scheduled");
+        return;
+    }
+    let shared = shared.clone();
+    let pool = (**db).clone();
+    let state = state.clone();
+    let cache = cache.clone();
+    let shutdown = rocket.shutdown();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(config.canvas.sync_minutes * 60));
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    match run(&state, &shared.current(), &pool, &cache, Target::Current).await {
+                        Ok(summary) => println!("Canvas Sync: {:?}", summary),
+                        Err(error) => println!("Canvas Sync: {:?}", error)
+                    }
+                }
+                _ = shutdown.clone() => break
+            }
+        }
+    });
+}
+
+// Routes
+
+fn update_result(result : Result<SyncSummary, SyncError>) -> ApiResponse<SyncSummary> {
+    match result {
+        Ok(summary) => ApiResponse::ok(summary),
+        Err(SyncError::Running) => ApiResponse::error(Status::Conflict, "Sync Already Running"),
+        Err(SyncError::Failed(error)) => ApiResponse::server_error("Sync Failed", error)
+    }
+}
+
+/// Load the current courses from Canvas (admin role).
+#[utoipa::path(
+    tag = "admin",
+    responses(
+        (status = 200, description = "Sync summary", body = SyncResponse),
+        (status = 401, description = "Missing or invalid token", body = SyncResponse),
+        (status = 403, description = "Role or course not allowed", body = SyncResponse),
+        (status = 409, description = "A sync is already running", body = SyncResponse),
+        (status = 500, description = "Server error (details are logged)", body = SyncResponse)),
+    security(("bearer" = []))
+)]
+#[post("/update/current")]
+pub async fn route_update_current(auth : Auth,
+                                  db : &State<DBPool>,
+                                  config : &Config,
+                                  state : &State<SyncState>,
+                                  cache : &State<ResponseCache>) -> Result<ApiResponse<SyncSummary>, Status> {
+    auth.require_role(Role::Admin)?;
+    Ok(update_result(run(state, config, db, cache, Target::Current).await))
+}
+
+/// Load the trend sections from Canvas (admin role).
+#[utoipa::path(
+    tag = "admin",
+    responses(
+        (status = 200, description = "Sync summary", body = SyncResponse),
+        (status = 401, description = "Missing or invalid token", body = SyncResponse),
+        (status = 403, description = "Role or course not allowed", body = SyncResponse),
+        (status = 409, description = "A sync is already running", body = SyncResponse),
+        (status = 500, description = "Server error (details are logged)", body = SyncResponse)),
+    security(("bearer" = []))
+)]
+#[post("/update/trends")]
+pub async fn route_update_trends(auth : Auth,
+                                 db : &State<DBPool>,
+                                 config : &Config,
+                                 state : &State<SyncState>,
+                                 cache : &State<ResponseCache>) -> Result<ApiResponse<SyncSummary>, Status> {
+    auth.require_role(Role::Admin)?;
+    Ok(update_result(run(state, config, db, cache, Target::Trends).await))
+}
@@ -0,0 +1,311 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+struct CacheEntry {
+    // Shared with the readers so a hit does not copy the file
+    bytes : Arc<[u8]>,
+    modified : Option<SystemTime>,
+    size : u64,
+    tick : u64
+}
+
+struct CacheData {
+    entries : HashMap<String, CacheEntry>,
+    // Maps the last used tick to the path.  The first entry is always the
+    // least recently used file.
+    order : BTreeMap<u64, String>,
+    tick : u64,
+    bytes : usize,
+    max_bytes : usize,
+    hits : u64,
+    misses : u64,
+    evictions : u64,
+    invalidations : u64
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CacheStats {
+    pub entries : usize,
+    pub bytes : usize,
+    pub max_bytes : usize,
+    pub hits : u64,
+    pub misses : u64,
+    pub evictions : u64,
+    pub invalidations : u64
+}
+
+#[derive(Clone)]
+pub struct FileCache {
+    data : Arc<Mutex<CacheData>>
+}
+
+impl FileCache {
+
+    /* Create an empty cache that will hold at most max_bytes of file
+     * contents.  Clones of the FileCache share the same data.
+     */
+    pub fn new(max_bytes : usize) -> Self {
+        let data = CacheData {
+            entries : HashMap::new(),
+            order : BTreeMap::new(),
+            tick : 0,
+            bytes : 0,
+            max_bytes,
+            hits : 0,
+            misses : 0,
+            evictions : 0,
+            invalidations : 0
+        };
+        FileCache { data : Arc::new(Mutex::new(data)) }
+    }
+
+    /* Read the file at the path.  If the file is in the cache and the
+     * modified time and size on disk still match, then the cached bytes are
+     * returned.  Otherwise the file is read from disk and added to the cache
+     * (evicting the least recently used files if needed).  Files that are
+     * larger than the cache are never stored.
+     */
+    pub fn read(&self, path : &str) -> io::Result<Arc<[u8]>> {
+        let metadata = fs::metadata(path)?;
+        let modified = metadata.modified().ok();
+        let size = metadata.len();
+
+        {
+            let mut data = self.lock();
+            if let Some(bytes) = data.lookup(path, modified, size) {
+                return Ok(bytes);
+            }
+        }
+
+        // Read from disk without holding the lock so other clients are not
+        // blocked by a slow file.
+        let file = File::open(path)?;
+        let mut reader = BufReader::new(file);
+        let mut bytes = Vec::<u8>::new();
+        reader.read_to_end(&mut bytes)?;
+        let bytes = Arc::<[u8]>::from(bytes);
+
+        self.lock().store(path, &bytes, modified, size);
+        Ok(bytes)
+    }
+
+    /* Remove all files from the cache.  The counters are not reset.
+     */
+    pub fn clear(&self) {
+        let mut data = self.lock();
+        data.entries.clear();
+        data.order.clear();
+        data.bytes = 0;
+    }
+
+    /* Get a snapshot of the cache counters.
+     */
+    pub fn stats(&self) -> CacheStats {
+        let data = self.lock();
+        CacheStats {
+            entries : data.entries.len(),
+            bytes : data.bytes,
+            max_bytes : data.max_bytes,
+            hits : data.hits,
+            misses : data.misses,
+            evictions : data.evictions,
+            invalidations : data.invalidations
+        }
+    }
+
+    /* Lock the cache data.  The cache only holds copies of files on disk so
+     * if a thread panicked while holding the lock, we can safely keep using it.
+     */
+    fn lock(&self) -> std::sync::MutexGuard<'_, CacheData> {
+        self.data.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl CacheData {
+
+    /* Find a valid entry for the path and mark it as most recently used.
+     * A stale entry (file changed on disk) is removed.
+     */
+    fn lookup(&mut self, path : &str, modified : Option<SystemTime>, size : u64) -> Option<Arc<[u8]>> {
+        let fresh = match self.entries.get(path) {
+            Some(entry) => entry.modified == modified && entry.size == size,
+            None => {
+                self.misses += 1;
+                return None;
+            }
+        };
+
+        if !fresh {
+            self.remove(path);
+            self.invalidations += 1;
+            self.misses += 1;
+            return None;
+        }
+
+        self.tick += 1;
+        let tick = self.tick;
+        let entry = self.entries.get_mut(path)?;
+        self.order.remove(&entry.tick);
+        self.order.insert(tick, path.to_string());
+        entry.tick = tick;
+        self.hits += 1;
+        Some(Arc::clone(&entry.bytes))
+    }
+
+    /* Add the file to the cache, evicting least recently used files until
+     * there is room.
+     */
+    fn store(&mut self, path : &str, bytes : &Arc<[u8]>, modified : Option<SystemTime>, size : u64) {
+        if bytes.len() > self.max_bytes {
+            return;
+        }
+
+        // Another client may have loaded the same file while we were reading
+        self.remove(path);
+
+        while self.bytes + bytes.len() > self.max_bytes {
+            let oldest = match self.order.first_key_value() {
+                Some((_, oldest)) => oldest.clone(),
+                None => break
+            };
+            self.remove(&oldest);
+            self.evictions += 1;
+        }
+
+        self.tick += 1;
+        self.order.insert(self.tick, path.to_string());
+        self.entries.insert(path.to_string(),
+            CacheEntry { bytes : Arc::clone(bytes), modified, size, tick : self.tick });
+        self.bytes += bytes.len();
+    }
+
+    fn remove(&mut self, path : &str) {
+        if let Some(entry) = self.entries.remove(path) {
+            self.order.remove(&entry.tick);
+            self.bytes -= entry.bytes.len();
+        }
+    }
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        let lookups = self.hits + self.misses;
+        let hit_rate = if lookups == 0 { 0.0 } else { self.hits as f64 / lookups as f64 * 100.0 };
+        write!(f, "Entries: {}  Bytes: {}/{}\nHits: {}  Misses: {}  Hit Rate: {:.1}%\nEvictions: {}  Invalidations: {}",
+            self.entries, self.bytes, self.max_bytes,
+            self.hits, self.misses, hit_rate,
+            self.evictions, self.invalidations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    /* Create an empty folder for one test in the temp directory.
+     */
+    fn folder(name : &str) -> PathBuf {
+        let folder = std::env::temp_dir().join(format!("file_cache_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    fn write(folder : &Path, name : &str, bytes : &[u8]) -> String {
+        let path = folder.join(name);
+        fs::write(&path, bytes).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let folder = folder("lru");
+        let a = write(&folder, "a.html", &[b'a'; 40]);
+        let b = write(&folder, "b.html", &[b'b'; 40]);
+        let c = write(&folder, "c.html", &[b'c'; 40]);
+        let cache = FileCache::new(100);
+
+        cache.read(&a).unwrap();
+        cache.read(&b).unwrap();
+        // a is now more recently used than b, so b goes first
+        cache.read(&a).unwrap();
+        cache.read(&c).unwrap();
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.bytes, stats.evictions), (2, 80, 1));
+
+        assert_eq!(&*cache.read(&a).unwrap(), &[b'a'; 40]);
+        assert_eq!(&*cache.read(&c).unwrap(), &[b'c'; 40]);
+        assert_eq!(cache.stats().hits, 3);
+        cache.read(&b).unwrap();
+        let stats = cache.stats();
+        assert_eq!((stats.misses, stats.evictions), (4, 2));
+        let _ = fs::remove_dir_all(folder);
+    }
+
+    #[test]
+    fn test_invalidates_changed_files() {
+        let folder = folder("invalidate");
+        let path = write(&folder, "index.html", b"first");
+        let cache = FileCache::new(100);
+        assert_eq!(&*cache.read(&path).unwrap(), b"first");
+        assert_eq!(&*cache.read(&path).unwrap(), b"first");
+
+        // A different size
+        write(&folder, "index.html", b"second");
+        assert_eq!(&*cache.read(&path).unwrap(), b"second");
+        assert_eq!(cache.stats().invalidations, 1);
+
+        // The same size but a different modified time
+        write(&folder, "index.html", b"thirds");
+        let file = File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(60)).unwrap();
+        assert_eq!(&*cache.read(&path).unwrap(), b"thirds");
+        let stats = cache.stats();
+        assert_eq!((stats.invalidations, stats.hits, stats.misses, stats.entries), (2, 1, 3, 1));
+        let _ = fs::remove_dir_all(folder);
+    }
+
+    #[test]
+    fn test_skips_files_larger_than_cache() {
+        let folder = folder("oversize");
+        let small = write(&folder, "small.html", &[0; 10]);
+        let large = write(&folder, "large.jpeg", &[1; 200]);
+        let cache = FileCache::new(100);
+        cache.read(&small).unwrap();
+        assert_eq!(cache.read(&large).unwrap().len(), 200);
+        assert_eq!(cache.read(&large).unwrap().len(), 200);
+
+        // The large file is read from disk every time and evicts nothing
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.bytes, stats.misses, stats.evictions), (1, 10, 3, 0));
+        assert!(cache.read(&folder.join("missing.html").to_string_lossy()).is_err());
+        let _ = fs::remove_dir_all(folder);
+    }
+
+    #[test]
+    fn test_clear() {
+        // CACHE CLEAR in the shell
+        let folder = folder("clear");
+        let path = write(&folder, "index.html", b"hello");
+        let cache = FileCache::new(100);
+        let shared = cache.clone();
+        cache.read(&path).unwrap();
+        cache.read(&path).unwrap();
+        shared.clear();
+
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.bytes), (0, 0));
+        // The counters are kept
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+        cache.read(&path).unwrap();
+        assert_eq!(cache.stats().misses, 2);
+        assert_eq!(format!("{}", cache.stats()).lines().next(), Some("Entries: 1  Bytes: 5/100"));
+        let _ = fs::remove_dir_all(folder);
+    }
+}
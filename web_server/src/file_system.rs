@@ -1,11 +1,14 @@
 use std::fs::{self, File};
 use std::io::{self, BufReader, Read};
 use std::path::Path;
+use std::sync::Arc;
 use deflate::deflate_bytes;
+use crate::file_cache::{FileCache, CacheStats};
 
 #[derive(Clone)]
 pub struct FileSystem {
-    path : String
+    path : String,
+    cache : Option<FileCache>
 }
 
 impl FileSystem {
@@ -15,7 +18,31 @@ impl FileSystem {
      * TODO: Can we make this a singleton?
      */
     pub fn new(path : &str) -> Self {
-        FileSystem {path : path.to_string()}
+        FileSystem {path : path.to_string(), cache : None}
+    }
+
+    /* Keep up to max_bytes of file contents in memory.  The cache is
+     * shared by all clones of the FileSystem.  A size of 0 disables the cache.
+     */
+    pub fn enable_cache(&mut self, max_bytes : usize) {
+        self.cache = match max_bytes {
+            0 => None,
+            _ => Some(FileCache::new(max_bytes))
+        };
+    }
+
+    /* Get the cache counters if the cache is enabled.
+     */
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache.as_ref().map(|cache| cache.stats())
+    }
+
+    /* Remove all files from the cache if the cache is enabled.
+     */
+    pub fn clear_cache(&self) {
+        if let Some(cache) = &self.cache {
+            cache.clear();
+        }
     }
 
     /* Verify if the folder path is valid
@@ -35,14 +62,22 @@ impl FileSystem {
         
     }
 
-    /* Obtain a file and return bytes and mime type.  For impages,
-     * compress the file.
+    /* Obtain a file and return bytes and mime type.  If the cache is
+     * enabled, the file will be read from the cache when it has not changed
+     * on disk.  For impages, compress the file.
      */
-    pub fn get_file(&self, target : &str) -> io::Result<(Vec<u8>,&str)> {
-        let file = File::open(format!("{}/{}",self.path, target))?;
-        let mut reader = BufReader::new(file);
-        let mut bytes = Vec::<u8>::new();
-        reader.read_to_end(&mut bytes)?;
+    pub fn get_file(&self, target : &str) -> io::Result<(Arc<[u8]>,&str)> {
+        let path = format!("{}/{}",self.path, target);
+        let bytes = match &self.cache {
+            Some(cache) => cache.read(&path)?,
+            None => {
+                let file = File::open(path)?;
+                let mut reader = BufReader::new(file);
+                let mut bytes = Vec::<u8>::new();
+                reader.read_to_end(&mut bytes)?;
+                bytes.into()
+            }
+        };
         let (mime_type, compress) = match self.get_type(target) {
             Some(ext) if ext == "html" => ("text/html",false),
             Some(ext) if ext == "jpeg" => ("image/jpeg",true),
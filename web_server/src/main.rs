@@ -5,15 +5,21 @@ mod method;
 mod client;
 mod server;
 mod file_system;
+mod file_cache;
+mod worker_pool;
+// The original thread per request design is kept to benchmark the WorkerPool
+#[cfg(test)]
 mod thread_family;
 
 use clap::Parser;
+use clap::builder::RangedU64ValueParser;
 use std::io::{self, BufRead};
 use std::net::TcpListener;
 use std::str;
 use std::thread;
 use file_system::FileSystem;
 use server::Server;
+use worker_pool::{WorkerPool, PoolMonitor};
 
 // Command Line Setup
 
@@ -27,29 +33,44 @@ struct Args {
     port : u16,
 
     #[clap(help = "Root Path")]
-    root_path : String
+    root_path : String,
+
+    #[clap(short, long, default_value_t = 0, help = "File Cache Size in Bytes (0 = disabled)")]
+    cache_size : usize,
+
+    #[clap(short, long, default_value_t = 5, value_parser = RangedU64ValueParser::<usize>::new().range(1..),
+           help = "Number of Worker Threads")]
+    workers : usize,
+
+    #[clap(short, long, default_value_t = 100, help = "Maximum Queued Clients")]
+    queue_size : usize
 }
 
-fn start(ip_address : &str, port : u16, root_path : &str) -> Result<(),String> {
+fn start(args : &Args) -> Result<(),String> {
+    let Args { ip_address, port, root_path, cache_size, workers, queue_size } = args;
+
     // Must have a valid root path
-    let file_system = FileSystem::new(root_path);
+    let mut file_system = FileSystem::new(root_path);
     file_system.check_folder()
         .map_err(|err| format!("Root path does not exist\n{}",err))?;
+    file_system.enable_cache(*cache_size);
 
     // Must successfully create the server socket
     let listener = TcpListener::bind(format!("{}:{}", ip_address, port))
         .map_err(|err| format!("Unable to create server socket\n{}",err))?;
 
-    let server = Server::new(listener, file_system.clone());
+    let pool = WorkerPool::new(*workers, *queue_size);
+    let monitor = pool.monitor();
+    let server = Server::new(listener, file_system.clone(), pool);
     let _ = thread::spawn(move || server.run());
 
-    run_shell();
+    run_shell(&file_system, &monitor);
 
     Ok(())
 }
 
 
-fn run_shell() {
+fn run_shell(file_system : &FileSystem, monitor : &PoolMonitor) {
     println!("Starting Shell.");
     let mut input = String::new();
     let stdin = io::stdin();
@@ -61,7 +82,12 @@ fn run_shell() {
         match input.as_str() {
             "EXIT" => break,
             "LOG" => println!("LOG DISPLAY"),
-            "ACTIVE" => println!("ACTIVE DISPLAY"),
+            "ACTIVE" => println!("{}", monitor.stats()),
+            "CACHE" => match file_system.cache_stats() {
+                Some(stats) => println!("{}", stats),
+                None => println!("Cache Disabled")
+            },
+            "CACHE CLEAR" => file_system.clear_cache(),
             _ => ()
         }
     }
@@ -78,7 +104,7 @@ fn run_shell() {
 
 fn main() {
     let args = Args::parse();
-    if let Err(err) = start(&args.ip_address, args.port, &args.root_path) {
+    if let Err(err) = start(&args) {
         println!("Error: {}", err);
     }
 }
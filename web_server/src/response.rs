@@ -85,6 +85,15 @@ impl Response {
         self
     }
 
+    /* Sets the status code and text for a Service Unavailable (503)
+     * response.  This function supports chaining.
+     */
+    pub fn service_unavailable(&mut self) -> &mut Self {
+        self.status_code = "503".to_string();
+        self.status_text = "SERVICE UNAVAILABLE".to_string();
+        self
+    }
+
     /* Adds a key/value pair to the headers.  This function supports
      * chaining.
      */
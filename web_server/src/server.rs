@@ -1,34 +1,32 @@
-
 use std::net::TcpListener;
 use std::time::Duration;
 use crate::file_system::FileSystem;
 use crate::client::Client;
-use crate::thread_family::ThreadFamily;
+use crate::response::Response;
+use crate::worker_pool::{WorkerPool, Submit};
 
 pub struct Server 
 {
     listener : TcpListener,
     file_system : FileSystem,
+    pool : WorkerPool,
 }
 
 impl Server
 {
 
     /* Create a new server which is defined by an already created
-     * TCPListener and a FileSystem.
+     * TCPListener, a FileSystem, and the WorkerPool that will run the clients.
      */
-    pub fn new(listener : TcpListener, file_system : FileSystem) -> Self {
-        Server { listener, file_system }
+    pub fn new(listener : TcpListener, file_system : FileSystem, pool : WorkerPool) -> Self {
+        Server { listener, file_system, pool }
     }
 
-    /* The server thread will start by creating a ThreadFamily to manage
-     * all active and pending client threads.  The server will block waiting for
-     * a client to connect.  
+    /* The server thread will block waiting for a client to connect.  Each
+     * client is given to the WorkerPool.  If the pool is full, the client
+     * is sent a Service Unavailable response.
      */
     pub fn run(&self) {
-        // Create the ThreadFamily
-        let mut thread_family = ThreadFamily::new(5);
-
         // Listen for client connections
         for stream in self.listener.incoming() {
             if let Ok(stream) = stream {
@@ -38,17 +36,24 @@ impl Server
                     break;
                 }
 
+                // Keep a second handle to the stream in case the pool rejects
+                // the client.
+                let overflow = stream.try_clone();
+
                 // Create a new client object
                 // TODO: Is there any reason we want to put a mutex on this?  Or is there a 
                 // way to do a singleton?
                 let mut client = Client::new(stream, self.file_system.clone());
 
-                // Give the client thread function to the thread family.  Note that we are 
-                // transfering ownership of the client to the thread.
-                if thread_family.request(move || client.run()).is_none() {
-                    // If the ThreadFamily fails, then it is not recoverable.  Restart the server.
-                    // TODO: Create a new ThreadFamily?
-                    break;
+                // Give the client thread function to the pool.  Note that we are 
+                // transfering ownership of the client to the job.
+                if self.pool.submit(move || client.run()) == Submit::Rejected {
+                    if let Ok(mut stream) = overflow {
+                        let _ = Response::new()
+                            .version("HTTP/1.1")
+                            .service_unavailable()
+                            .write_to_stream(&mut stream);
+                    }
                 }
             } 
             else {
@@ -0,0 +1,404 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/* Result of submitting a job to the WorkerPool:
+ *
+ *     - Submit::Accepted - An idle worker will start the job immediately.
+ *     - Submit::Queued - All workers are busy.  The job will wait in the
+ *            queue for the next available worker.
+ *     - Submit::Rejected - The queue is full (or the pool is closing).  The
+ *            job was dropped without running.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Submit {
+    Accepted,
+    Queued,
+    Rejected
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PoolStats {
+    pub workers : usize,
+    pub idle : usize,
+    pub queued : usize,
+    pub capacity : usize,
+    pub accepted : u64,
+    pub delayed : u64,
+    pub rejected : u64,
+    pub completed : u64,
+    pub panicked : u64,
+    pub total_wait : Duration,
+    pub max_wait : Duration,
+    pub total_run : Duration,
+    pub max_run : Duration
+}
+
+struct Job {
+    task : Box<dyn FnOnce() + Send + 'static>,
+    submitted : Instant
+}
+
+struct PoolState {
+    queue : VecDeque<Job>,
+    idle : usize,
+    closing : bool,
+    stats : PoolStats
+}
+
+struct Shared {
+    state : Mutex<PoolState>,
+    job_ready : Condvar,
+    workers : Mutex<Vec<JoinHandle<()>>>
+}
+
+/* A fixed number of long lived worker threads that pull jobs from a
+ * bounded queue.  Unlike the ThreadFamily, no threads are created per
+ * request.  If a job panics, the worker is replaced so the pool never
+ * shrinks.
+ */
+pub struct WorkerPool {
+    shared : Arc<Shared>
+}
+
+/* A handle that can read the statistics of a WorkerPool from another
+ * thread without owning the pool.
+ */
+#[derive(Clone)]
+pub struct PoolMonitor {
+    shared : Arc<Shared>
+}
+
+/* Each worker thread owns a Sentinel.  If the job panics, the Sentinel is
+ * dropped while the thread is unwinding and a replacement worker is spawned.
+ */
+struct Sentinel {
+    shared : Arc<Shared>
+}
+
+fn lock<T>(mutex : &Mutex<T>) -> MutexGuard<'_, T> {
+    // Jobs never run while holding a lock so a poisoned lock still has
+    // consistent data.
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl WorkerPool {
+
+    /* Create a WorkerPool with the number of workers and the maximum number
+     * of jobs that can wait in the queue.  All workers are started immediately.
+     * Panics if there are no workers since queued jobs would never run.
+     */
+    pub fn new(workers : usize, capacity : usize) -> Self {
+        assert!(workers > 0, "A WorkerPool needs at least one worker");
+        let stats = PoolStats { workers, capacity, ..PoolStats::default() };
+        let state = PoolState { queue : VecDeque::with_capacity(capacity), idle : 0, closing : false, stats };
+        let shared = Arc::new(Shared {
+            state : Mutex::new(state),
+            job_ready : Condvar::new(),
+            workers : Mutex::new(Vec::with_capacity(workers))
+        });
+        for _ in 0..workers {
+            spawn_worker(&shared);
+        }
+        WorkerPool { shared }
+    }
+
+    /* Submit a job to the pool.  This function never blocks.  See Submit for
+     * the possible results.
+     */
+    pub fn submit<F>(&self, job : F) -> Submit
+        where F: FnOnce() + Send + 'static
+    {
+        let mut state = lock(&self.shared.state);
+        // Jobs that an idle worker is about to take are not waiting, so only
+        // the rest count against the capacity.
+        let waiting = state.queue.len().saturating_sub(state.idle);
+        let result = if state.closing {
+            Submit::Rejected
+        }
+        else if state.idle > state.queue.len() {
+            Submit::Accepted
+        }
+        else if waiting >= state.stats.capacity {
+            Submit::Rejected
+        }
+        else {
+            Submit::Queued
+        };
+
+        match result {
+            Submit::Rejected => state.stats.rejected += 1,
+            Submit::Accepted => state.stats.accepted += 1,
+            Submit::Queued => state.stats.delayed += 1
+        }
+        if result != Submit::Rejected {
+            state.queue.push_back(Job { task : Box::new(job), submitted : Instant::now() });
+            self.shared.job_ready.notify_one();
+        }
+        result
+    }
+
+    /* Get a handle that can be used to read the statistics.
+     */
+    pub fn monitor(&self) -> PoolMonitor {
+        PoolMonitor { shared : Arc::clone(&self.shared) }
+    }
+}
+
+impl Drop for WorkerPool {
+    /* Pending jobs are discarded and the workers are notified to exit.
+     * Running jobs are allowed to finish.
+     */
+    fn drop(&mut self) {
+        {
+            let mut state = lock(&self.shared.state);
+            state.closing = true;
+            state.queue.clear();
+        }
+        self.shared.job_ready.notify_all();
+
+        // A worker that panics during shutdown adds a replacement handle so
+        // keep joining until the list is empty.
+        loop {
+            let handle = lock(&self.shared.workers).pop();
+            match handle {
+                Some(handle) => { let _ = handle.join(); }
+                None => break
+            }
+        }
+    }
+}
+
+impl PoolMonitor {
+
+    /* Get a snapshot of the pool counters.
+     */
+    pub fn stats(&self) -> PoolStats {
+        let state = lock(&self.shared.state);
+        PoolStats { idle : state.idle, queued : state.queue.len(), ..state.stats }
+    }
+}
+
+/* Start a worker thread and save the JoinHandle so the pool can wait for it.
+ * A replacement for a panicked worker takes the place of the dead worker's
+ * handle so the list never grows.
+ */
+fn spawn_worker(shared : &Arc<Shared>) {
+    // Held while spawning so a worker can always find its own handle
+    let mut workers = lock(&shared.workers);
+    let sentinel = Sentinel { shared : Arc::clone(shared) };
+    let handle = thread::spawn(move || sentinel.work());
+    let current = thread::current().id();
+    match workers.iter_mut().find(|worker| worker.thread().id() == current) {
+        Some(worker) => *worker = handle,
+        None => workers.push(handle)
+    }
+}
+
+impl Sentinel {
+
+    /* Wait for jobs and run them until the pool is closing.
+     */
+    fn work(&self) {
+        lock(&self.shared.state).idle += 1;
+        loop {
+            let job = {
+                let mut state = lock(&self.shared.state);
+                loop {
+                    if let Some(job) = state.queue.pop_front() {
+                        state.idle -= 1;
+                        break job;
+                    }
+                    if state.closing {
+                        return;
+                    }
+                    state = self.shared.job_ready.wait(state)
+                        .unwrap_or_else(PoisonError::into_inner);
+                }
+            };
+
+            let wait = job.submitted.elapsed();
+            let start = Instant::now();
+            (job.task)();
+            let run = start.elapsed();
+
+            let mut state = lock(&self.shared.state);
+            state.idle += 1;
+            let stats = &mut state.stats;
+            stats.completed += 1;
+            stats.total_wait += wait;
+            stats.max_wait = stats.max_wait.max(wait);
+            stats.total_run += run;
+            stats.max_run = stats.max_run.max(run);
+        }
+    }
+}
+
+impl Drop for Sentinel {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            lock(&self.shared.state).stats.panicked += 1;
+            spawn_worker(&self.shared);
+        }
+    }
+}
+
+impl fmt::Display for PoolStats {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        let average = |total : Duration| match self.completed {
+            0 => Duration::ZERO,
+            count => total.div_f64(count as f64)
+        };
+        write!(f, "Workers: {} ({} idle)  Queue: {}/{}\n\
+                   Accepted: {}  Queued: {}  Rejected: {}  Completed: {}  Panicked: {}\n\
+                   Wait: avg {:?} max {:?}\nRun: avg {:?} max {:?}",
+            self.workers, self.idle, self.queued, self.capacity,
+            self.accepted, self.delayed, self.rejected, self.completed, self.panicked,
+            average(self.total_wait), self.max_wait,
+            average(self.total_run), self.max_run)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thread_family::ThreadFamily;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Barrier;
+    use std::sync::mpsc::channel;
+
+    /* Wait until the counter reaches the target or fail after 60 seconds.
+     */
+    fn wait_for(counter : &AtomicUsize, target : usize) {
+        let start = Instant::now();
+        while counter.load(Ordering::SeqCst) < target {
+            assert!(start.elapsed() < Duration::from_secs(60), "Timed out waiting for jobs");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn test_runs_all_jobs() {
+        let pool = WorkerPool::new(4, 100);
+        let counter = Arc::new(AtomicUsize::new(0));
+        for _ in 0..100 {
+            let counter = Arc::clone(&counter);
+            assert_ne!(pool.submit(move || { counter.fetch_add(1, Ordering::SeqCst); }), Submit::Rejected);
+        }
+        wait_for(&counter, 100);
+        drop(pool);
+        assert_eq!(counter.load(Ordering::SeqCst), 100);
+    }
+
+    #[test]
+    fn test_back_pressure() {
+        let pool = WorkerPool::new(1, 1);
+        let monitor = pool.monitor();
+        let (tx, rx) = channel::<()>();
+
+        // Wait for the worker to be idle so the first job is accepted
+        while monitor.stats().idle == 0 {
+            thread::yield_now();
+        }
+        assert_eq!(pool.submit(move || { let _ = rx.recv(); }), Submit::Accepted);
+
+        // Wait for the worker to pick up the blocking job
+        while monitor.stats().idle == 1 || monitor.stats().queued == 1 {
+            thread::yield_now();
+        }
+        assert_eq!(pool.submit(|| ()), Submit::Queued);
+        assert_eq!(pool.submit(|| ()), Submit::Rejected);
+
+        let _ = tx.send(());
+        let stats = monitor.stats();
+        assert_eq!((stats.accepted, stats.delayed, stats.rejected), (1, 1, 1));
+    }
+
+    #[test]
+    fn test_panic_respawns_worker() {
+        let pool = WorkerPool::new(2, 10);
+        let monitor = pool.monitor();
+        let counter = Arc::new(AtomicUsize::new(0));
+        for _ in 0..4 {
+            pool.submit(|| panic!("Job panic (expected in test)"));
+        }
+        for _ in 0..4 {
+            let counter = Arc::clone(&counter);
+            pool.submit(move || { counter.fetch_add(1, Ordering::SeqCst); });
+        }
+        wait_for(&counter, 4);
+        let stats = monitor.stats();
+        assert_eq!(stats.panicked, 4);
+        assert_eq!(stats.completed, 4);
+        // The dead workers' handles were replaced, not added to
+        assert_eq!(lock(&pool.shared.workers).len(), 2);
+    }
+
+    #[test]
+    fn test_idle_workers_without_queue() {
+        let pool = WorkerPool::new(3, 0);
+        let monitor = pool.monitor();
+        let barrier = Arc::new(Barrier::new(4));
+        while monitor.stats().idle < 3 {
+            thread::yield_now();
+        }
+        // Every job goes straight to a worker so none of them wait
+        for _ in 0..3 {
+            let barrier = Arc::clone(&barrier);
+            assert_eq!(pool.submit(move || { barrier.wait(); }), Submit::Accepted);
+        }
+        assert_eq!(pool.submit(|| ()), Submit::Rejected);
+        barrier.wait();
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one worker")]
+    fn test_no_workers() {
+        WorkerPool::new(0, 10);
+    }
+
+    /* Benchmark the WorkerPool against the ThreadFamily.  Run with:
+     *
+     *     cargo test --release -- --ignored --nocapture
+     */
+    #[test]
+    #[ignore]
+    fn bench_pool_vs_thread_family() {
+        const JOBS : usize = 20_000;
+        const WORKERS : usize = 8;
+        for work in [0_u64, 10_000] {
+            let job = move |counter : Arc<AtomicUsize>| move || {
+                let mut total = 0_u64;
+                for i in 0..work {
+                    total = total.wrapping_add(i * i);
+                }
+                std::hint::black_box(total);
+                counter.fetch_add(1, Ordering::SeqCst);
+            };
+
+            let counter = Arc::new(AtomicUsize::new(0));
+            let start = Instant::now();
+            let mut family = ThreadFamily::new(WORKERS);
+            for _ in 0..JOBS {
+                family.request(job(Arc::clone(&counter)));
+            }
+            wait_for(&counter, JOBS);
+            let family_time = start.elapsed();
+            drop(family);
+
+            let counter = Arc::new(AtomicUsize::new(0));
+            let start = Instant::now();
+            let pool = WorkerPool::new(WORKERS, JOBS);
+            let monitor = pool.monitor();
+            for _ in 0..JOBS {
+                pool.submit(job(Arc::clone(&counter)));
+            }
+            wait_for(&counter, JOBS);
+            let pool_time = start.elapsed();
+            println!("{} jobs (work={}): ThreadFamily {:?}  WorkerPool {:?}\n{}",
+                JOBS, work, family_time, pool_time, monitor.stats());
+        }
+    }
+}